@@ -0,0 +1,105 @@
+//! Compression speed vs ratio of file sink frames with transaction payloads.
+//!
+//! ```sh
+//! BENCH_MESSAGES=20000 cargo bench --bench file_compression
+//! ```
+
+use {
+    std::{env, time::Instant},
+    yellowstone_grpc_kafka::frame::{FileCompression, FileFrameFormat},
+    yellowstone_grpc_proto::{
+        prelude::{
+            subscribe_update::UpdateOneof, CompiledInstruction, Message, SubscribeUpdate,
+            SubscribeUpdateTransaction, SubscribeUpdateTransactionInfo, Transaction,
+            TransactionStatusMeta,
+        },
+        prost::Message as _,
+    },
+};
+
+fn create_payload(slot: u64) -> Vec<u8> {
+    let seed = slot as u8;
+    SubscribeUpdate {
+        filters: vec!["client".to_owned()],
+        update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+            transaction: Some(SubscribeUpdateTransactionInfo {
+                signature: (0..64).map(|idx| seed.wrapping_mul(idx)).collect(),
+                is_vote: false,
+                transaction: Some(Transaction {
+                    signatures: vec![(0..64).map(|idx| seed.wrapping_add(idx)).collect()],
+                    message: Some(Message {
+                        account_keys: (0..16u8).map(|idx| vec![idx; 32]).collect(),
+                        recent_blockhash: vec![seed; 32],
+                        instructions: (0..4u32)
+                            .map(|idx| CompiledInstruction {
+                                program_id_index: idx,
+                                accounts: vec![0, 1, 2, 3],
+                                data: vec![seed ^ idx as u8; 64],
+                            })
+                            .collect(),
+                        ..Default::default()
+                    }),
+                }),
+                meta: Some(TransactionStatusMeta {
+                    fee: 5_000,
+                    pre_balances: vec![1_000_000_000 + slot; 16],
+                    post_balances: vec![999_995_000 + slot; 16],
+                    log_messages: (0..20)
+                        .map(|idx| format!("Program log: instruction #{idx} processed"))
+                        .collect(),
+                    ..Default::default()
+                }),
+                index: slot,
+            }),
+            slot,
+        })),
+        created_at: None,
+    }
+    .encode_to_vec()
+}
+
+fn main() -> anyhow::Result<()> {
+    let messages = env::var("BENCH_MESSAGES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(20_000u64)
+        .max(1);
+    let payloads = (0..messages).map(create_payload).collect::<Vec<_>>();
+    let raw_size = payloads
+        .iter()
+        .map(|payload| payload.len() + 4)
+        .sum::<usize>();
+
+    for compression in [
+        FileCompression::None,
+        FileCompression::Lz4Frame,
+        FileCompression::Zstd,
+    ] {
+        let mut file = Vec::with_capacity(raw_size);
+        let ts = Instant::now();
+        {
+            let compressed = compression.writer(&mut file)?;
+            let mut writer = FileFrameFormat::LengthPrefixed4.writer(compressed)?;
+            for payload in payloads.iter() {
+                writer.write_frame(payload)?;
+            }
+            writer.finish()?;
+        }
+        let write_elapsed = ts.elapsed();
+
+        let ts = Instant::now();
+        let mut reader = FileFrameFormat::LengthPrefixed4.reader(compression.reader(&file[..])?)?;
+        while reader.read_frame()?.is_some() {}
+        let read_elapsed = ts.elapsed();
+
+        let mb = raw_size as f64 / 1024.0 / 1024.0;
+        println!(
+            "{:>8}: ratio {:.2}, write {:.0} MiB/s, read {:.0} MiB/s",
+            format!("{compression:?}"),
+            raw_size as f64 / file.len() as f64,
+            mb / write_elapsed.as_secs_f64(),
+            mb / read_elapsed.as_secs_f64(),
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,190 @@
+//! Synthetic benchmark of the grpc2kafka serialize path: decode, JSON or
+//! protobuf encoding, hashing and key formatting. Nothing is sent to Kafka.
+//! Totals compare JSON format with protobuf format, which skips decode.
+//! JSON payload size is also reported per `account_data_encoding`.
+//!
+//! ```sh
+//! BENCH_MESSAGES=100000 BENCH_RATE=50000 cargo bench --bench hot_path
+//! ```
+
+use {
+    std::{
+        env,
+        time::{Duration, Instant},
+    },
+    yellowstone_grpc_kafka::kafka::{
+        config::{ConfigAccountDataEncoding, ConfigSignatureEncoding},
+        payload,
+    },
+    yellowstone_grpc_proto::{
+        prelude::{
+            subscribe_update::UpdateOneof, CompiledInstruction, Message, MessageHeader,
+            SubscribeUpdate, SubscribeUpdateTransaction, SubscribeUpdateTransactionInfo,
+            Transaction, TransactionStatusMeta,
+        },
+        prost::Message as _,
+    },
+};
+
+fn create_update(slot: u64) -> SubscribeUpdate {
+    let account_keys = (0..16u8).map(|idx| vec![idx; 32]).collect::<Vec<_>>();
+    let instructions = (0..4u32)
+        .map(|idx| CompiledInstruction {
+            program_id_index: idx,
+            accounts: vec![0, 1, 2, 3, 4, 5],
+            data: vec![idx as u8; 64],
+        })
+        .collect();
+    SubscribeUpdate {
+        filters: vec!["client".to_owned()],
+        update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+            transaction: Some(SubscribeUpdateTransactionInfo {
+                signature: vec![slot as u8; 64],
+                is_vote: false,
+                transaction: Some(Transaction {
+                    signatures: vec![vec![slot as u8; 64]],
+                    message: Some(Message {
+                        header: Some(MessageHeader {
+                            num_required_signatures: 1,
+                            num_readonly_signed_accounts: 0,
+                            num_readonly_unsigned_accounts: 4,
+                        }),
+                        account_keys,
+                        recent_blockhash: vec![7; 32],
+                        instructions,
+                        ..Default::default()
+                    }),
+                }),
+                meta: Some(TransactionStatusMeta {
+                    fee: 5_000,
+                    pre_balances: vec![1_000_000_000; 16],
+                    post_balances: vec![999_995_000; 16],
+                    log_messages: (0..20)
+                        .map(|idx| format!("Program log: instruction #{idx} processed"))
+                        .collect(),
+                    compute_units_consumed: Some(42_000),
+                    ..Default::default()
+                }),
+                index: slot,
+            }),
+            slot,
+        })),
+        created_at: None,
+    }
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+#[derive(Debug, Default)]
+struct Stage {
+    total: Duration,
+    bytes: usize,
+}
+
+impl Stage {
+    fn measure<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let ts = Instant::now();
+        let value = f();
+        self.total += ts.elapsed();
+        value
+    }
+
+    fn report(&self, name: &str, messages: u64) {
+        let avg = self.total / messages as u32;
+        if self.bytes > 0 {
+            println!(
+                "{name:>10}: {avg:>10?} per message, {} bytes avg",
+                self.bytes / messages as usize
+            );
+        } else {
+            println!("{name:>10}: {avg:>10?} per message");
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let messages = env_u64("BENCH_MESSAGES").unwrap_or(100_000).max(1);
+    let rate = env_u64("BENCH_RATE");
+
+    let updates = (0..1_024).map(create_update).collect::<Vec<_>>();
+    let mut decode = Stage::default();
+    let mut json = Stage::default();
+    let mut protobuf = Stage::default();
+    let mut hash = Stage::default();
+    let mut protobuf_hash = Stage::default();
+    let mut key = Stage::default();
+
+    let started = Instant::now();
+    for idx in 0..messages {
+        if let Some(rate) = rate {
+            let deadline = started + Duration::from_secs_f64(idx as f64 / rate as f64);
+            if let Some(delay) = deadline.checked_duration_since(Instant::now()) {
+                std::thread::sleep(delay);
+            }
+        }
+
+        let update = &updates[idx as usize % updates.len()];
+        let Some(UpdateOneof::Transaction(msg)) = &update.update_oneof else {
+            unreachable!("only transactions are generated");
+        };
+        let transaction = msg.transaction.as_ref().expect("defined");
+
+        let tx = decode.measure(|| payload::decode_transaction(transaction))?;
+        let json_payload = json.measure(|| serde_json::to_vec(&tx))?;
+        json.bytes += json_payload.len();
+        let protobuf_payload = protobuf.measure(|| update.encode_to_vec());
+        protobuf.bytes += protobuf_payload.len();
+        let payload_hash = hash.measure(|| payload::payload_hash(&json_payload));
+        let protobuf_payload_hash =
+            protobuf_hash.measure(|| payload::payload_hash(&protobuf_payload));
+        let message_key = key.measure(|| format!("{}_{payload_hash}", msg.slot));
+        std::hint::black_box((message_key, protobuf_payload, protobuf_payload_hash));
+    }
+    let elapsed = started.elapsed();
+
+    println!(
+        "{messages} messages in {elapsed:?}, {:.0} messages/sec",
+        messages as f64 / elapsed.as_secs_f64()
+    );
+    decode.report("decode", messages);
+    json.report("json", messages);
+    protobuf.report("protobuf", messages);
+    hash.report("sha256", messages);
+    protobuf_hash.report("sha256 pb", messages);
+    key.report("key", messages);
+
+    let json_total = decode.total + json.total + hash.total;
+    let protobuf_total = protobuf.total + protobuf_hash.total;
+    println!(
+        "json format: {:?} per message, protobuf format: {:?} per message, {:.1}% saved",
+        json_total / messages as u32,
+        protobuf_total / messages as u32,
+        100.0 * (1.0 - protobuf_total.as_secs_f64() / json_total.as_secs_f64())
+    );
+
+    for encoding in [
+        ConfigAccountDataEncoding::Raw,
+        ConfigAccountDataEncoding::Base64,
+        ConfigAccountDataEncoding::Base58,
+        ConfigAccountDataEncoding::Hex,
+    ] {
+        let mut bytes = 0;
+        for update in &updates {
+            let Some(UpdateOneof::Transaction(msg)) = &update.update_oneof else {
+                unreachable!("only transactions are generated");
+            };
+            let tx = payload::decode_transaction(msg.transaction.as_ref().expect("defined"))?;
+            let mut value = payload::transaction_to_value(&tx, ConfigSignatureEncoding::Bytes);
+            payload::encode_account_data(&tx, &mut value, encoding);
+            bytes += serde_json::to_vec(&value)?.len();
+        }
+        println!(
+            "{:>10}: {} bytes avg",
+            format!("{encoding:?}").to_lowercase(),
+            bytes / updates.len()
+        );
+    }
+    Ok(())
+}
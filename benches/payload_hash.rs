@@ -0,0 +1,47 @@
+//! Throughput of `payload_hash_algorithm` for typical (256 bytes) and large
+//! (10 KiB) account payloads.
+//!
+//! ```sh
+//! BENCH_MESSAGES=100000 cargo bench --bench payload_hash
+//! ```
+
+use {
+    std::{env, time::Instant},
+    yellowstone_grpc_kafka::kafka::{config::ConfigPayloadHashAlgorithm, payload},
+};
+
+fn main() {
+    let messages = env::var("BENCH_MESSAGES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(100_000u64)
+        .max(1);
+
+    for size in [256, 10 * 1024] {
+        let payloads = (0..64u8)
+            .map(|seed| {
+                (0..size)
+                    .map(|idx| seed.wrapping_add(idx as u8))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        for algorithm in [
+            ConfigPayloadHashAlgorithm::Sha256,
+            ConfigPayloadHashAlgorithm::Blake3,
+            ConfigPayloadHashAlgorithm::Xxhash64,
+        ] {
+            let ts = Instant::now();
+            for idx in 0..messages {
+                let payload = &payloads[idx as usize % payloads.len()];
+                std::hint::black_box(payload::payload_hash_with(payload, algorithm));
+            }
+            let elapsed = ts.elapsed();
+            println!(
+                "{size:>6} bytes {:>9}: {:>10?} per message, {:.0} MiB/s",
+                format!("{algorithm:?}").to_lowercase(),
+                elapsed / messages as u32,
+                (size as u64 * messages) as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64(),
+            );
+        }
+    }
+}
@@ -1,39 +1,93 @@
-#[path = "../generated/mod.rs"]
-mod generated;
-
+#[cfg(feature = "instruction-decoder")]
+use yellowstone_grpc_kafka::kafka::instruction::InstructionDecoders;
+#[cfg(feature = "parquet-sink")]
+use yellowstone_grpc_kafka::kafka::parquet::{ParquetRow, ParquetSink};
+#[cfg(feature = "script-transform")]
+use yellowstone_grpc_kafka::kafka::script::{Script, ScriptOutput};
 use {
+    actix_web::{web, App, HttpResponse, HttpServer},
+    actix_web_codegen::routes,
     anyhow::Context,
     clap::{Parser, Subcommand},
-    futures::{future::BoxFuture, stream::StreamExt},
-    rdkafka::{config::ClientConfig, consumer::Consumer, message::Message, producer::FutureRecord},
-    sha2::{Digest, Sha256},
-    std::{net::SocketAddr, sync::Arc, time::Duration},
-    tokio::task::JoinSet,
-    tonic::transport::ClientTlsConfig,
-    tracing::{debug, trace, warn},
-    yellowstone_grpc_client::GeyserGrpcClient,
+    futures::future::{BoxFuture, FutureExt},
+    rdkafka::{
+        admin::AdminClient,
+        client::{ClientContext, DefaultClientContext},
+        config::ClientConfig,
+        consumer::{CommitMode, Consumer, StreamConsumer},
+        message::{Header, Message, OwnedHeaders},
+        producer::{FutureProducer, FutureRecord, Producer},
+        topic_partition_list::{Offset, TopicPartitionList},
+    },
+    std::{
+        collections::{HashMap, HashSet},
+        net::SocketAddr,
+        path::PathBuf,
+        sync::Arc,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tokio::{
+        signal::unix::{signal, SignalKind},
+        sync::{watch, Mutex},
+        task::JoinSet,
+        time::{sleep, Instant},
+    },
+    tracing::{debug, info, trace, warn},
     yellowstone_grpc_kafka::{
-        config::{load as config_load, GrpcRequestToProto},
-        create_shutdown,
+        config::{load as config_load, load_with_deprecations, redact_secrets, GrpcRequestToProto},
+        create_shutdown_signal,
+        generated::FILE_DESCRIPTOR_SET,
         kafka::{
-            config::{Config, ConfigDedup, ConfigGrpc2Kafka, ConfigKafka2Grpc},
-            dedup::KafkaDedup,
-            grpc::GrpcService,
+            checkpoint::{Checkpoint, CheckpointWriter},
+            commitment::{CommitmentUpgradeFilter, SlotCommitmentTracker},
+            compression::{self, KafkaCompression},
+            config::{
+                validate_kafka_config, Config, ConfigAccountDataEncoding, ConfigDedup,
+                ConfigFingerprintMode, ConfigGroupIdSuffix, ConfigGrpc2Grpc, ConfigGrpc2Kafka,
+                ConfigGrpc2KafkaFormat, ConfigKafka2Grpc, ConfigKafkaStartupRetry,
+                ConfigSignatureEncoding,
+            },
+            confirmation::SlotConfirmationWait,
+            dead_letter::{FailedRecord, FailedSendHandler},
+            dedup::{simhash, slot_epoch, KafkaDedup, KafkaDedupMemory, KafkaDedupNearMemory},
+            error_events::{ErrorEventKind, ErrorEvents},
+            grpc::{account_filter_header, GrpcBroadcast, GrpcBroadcastSender, GrpcService},
+            in_flight::{InFlightLimiter, KindInFlightLimiter},
+            leader::{LeaderElection, LeaderRole},
             metrics,
+            monotonic::MonotonicSlots,
+            partition::{self, PartitionOverrides},
+            payload,
+            readiness::{self, Dependency, Readiness},
+            reorder::SlotReorderBuffer,
+            replay::{KafkaReplay, ReplayCursor},
+            sampler::Sampler,
+            shard,
+            slot_limit::SlotLimiter,
+            subscriber::{parse_x_token_headers, GeyserFailover, GeyserSource, GeyserSubscriber},
+            transform::{TransformChain, TransformVars},
+            watchdog::RestartPolicy,
+            webhook::PreShutdownWebhook,
+            write_behind::{CachedRecord, WriteBehindCache},
+        },
+        metrics::{
+            encode_text as prometheus_encode_text, init_registry as prometheus_init_registry,
+            pod_metadata_labels as prometheus_pod_metadata_labels,
+            run_server as prometheus_run_server, run_statsd,
+            run_textfile as prometheus_run_textfile, GprcMessageKind,
         },
-        metrics::{run_server as prometheus_run_server, GprcMessageKind},
         setup_tracing,
+        version::VERSION,
     },
     yellowstone_grpc_proto::{
-        prelude::{subscribe_update::UpdateOneof, SubscribeUpdate},
+        prelude::{
+            subscribe_update::UpdateOneof, SlotStatus, SubscribeUpdate, SubscribeUpdateAccount,
+        },
         prost::Message as _,
     },
-    serde_json,
-    actix_web::{App, HttpServer, Responder},
-    actix_web_codegen::routes,
-    std::thread,
 };
-use base64::{engine::general_purpose, Engine as _};
+
+const DEDUP_FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone, Parser)]
 #[clap(author, version, about = "Yellowstone gRPC Kafka Tool")]
@@ -61,11 +115,174 @@ enum ArgsAction {
     /// Receive data from Kafka and send them over gRPC
     #[command(name = "kafka2grpc")]
     Kafka2Grpc,
+    /// Receive data from gRPC and send them over gRPC, without Kafka
+    #[command(name = "grpc2grpc")]
+    Grpc2Grpc,
+    /// Print partitions, leaders and replicas of Kafka topics and exit
+    #[command(name = "list-topics")]
+    ListTopics {
+        /// Only topics with this name prefix
+        filter: Option<String>,
+    },
+    /// Protobuf schema the binary was built against
+    #[command(subcommand)]
+    Schema(ArgsSchema),
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum ArgsSchema {
+    /// Write compiled `FileDescriptorSet` to the file and exit
+    Export {
+        /// Output file
+        #[clap(short, long, default_value = "./geyser_descriptor.bin")]
+        output: PathBuf,
+    },
+}
+
+impl ArgsSchema {
+    fn run(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Export { output } => {
+                std::fs::write(output, FILE_DESCRIPTOR_SET).with_context(|| {
+                    format!("failed to write descriptor set to {}", output.display())
+                })?;
+                println!(
+                    "{} bytes of file descriptor set written to {}",
+                    FILE_DESCRIPTOR_SET.len(),
+                    output.display()
+                );
+                Ok(())
+            }
+        }
+    }
 }
 
 impl ArgsAction {
-    async fn run(self, config: Config, kafka_config: ClientConfig) -> anyhow::Result<()> {
-        let shutdown = create_shutdown()?;
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Dedup => "dedup",
+            Self::Grpc2Kafka => "grpc2kafka",
+            Self::Kafka2Grpc => "kafka2grpc",
+            Self::Grpc2Grpc => "grpc2grpc",
+            Self::ListTopics { .. } => "list-topics",
+            Self::Schema(_) => "schema",
+        }
+    }
+
+    fn list_topics(kafka_config: &ClientConfig, filter: Option<&str>) -> anyhow::Result<()> {
+        let admin = kafka_config
+            .create::<AdminClient<DefaultClientContext>>()
+            .context("failed to create kafka admin client")?;
+        let metadata = admin
+            .inner()
+            .fetch_metadata(None, Duration::from_secs(10))
+            .context("failed to fetch metadata")?;
+
+        let mut topics = metadata
+            .topics()
+            .iter()
+            .filter(|topic| filter.is_none_or(|prefix| topic.name().starts_with(prefix)))
+            .collect::<Vec<_>>();
+        topics.sort_by_key(|topic| topic.name());
+        println!(
+            "{:<48} {:>10} {:>10} {:>8}  replicas",
+            "topic", "partitions", "partition", "leader"
+        );
+        for topic in topics {
+            if let Some(error) = topic.error() {
+                println!("{:<48} error: {error:?}", topic.name());
+                continue;
+            }
+            println!("{:<48} {:>10}", topic.name(), topic.partitions().len());
+            for partition in topic.partitions() {
+                let replicas = partition
+                    .replicas()
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                println!(
+                    "{:<48} {:>10} {:>10} {:>8}  {replicas}",
+                    "",
+                    "",
+                    partition.id(),
+                    partition.leader()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// With `auto_restart` failed action is restarted with the config loaded
+    /// at startup, until `max_restart_attempts` failures within
+    /// `restart_window_seconds`. Changes of the file are applied only with
+    /// SIGHUP reload or process restart
+    async fn run_with_watchdog(
+        self,
+        config_path: String,
+        config: Config,
+        kafka_config: ClientConfig,
+    ) -> anyhow::Result<()> {
+        let shutdown = create_shutdown_signal()?;
+        let grace_period = Duration::from_millis(config.shutdown_grace_period_ms);
+        let shutdown = async move {
+            let signal = shutdown.await;
+            readiness::set_shutting_down();
+            info!("{signal} received, start drain in {grace_period:?}");
+            sleep(grace_period).await;
+            signal
+        }
+        .boxed();
+        let shutdown = match &config.pre_shutdown_webhook {
+            Some(url) => {
+                let webhook = PreShutdownWebhook::new(
+                    url.clone(),
+                    Duration::from_millis(config.pre_shutdown_webhook_timeout_ms),
+                    kafka_config.get("client.id").unwrap_or_default().to_owned(),
+                )?;
+                // called before actions start drain
+                async move { webhook.notify(shutdown.await).await }.boxed()
+            }
+            None => shutdown.map(|_signal| ()).boxed(),
+        }
+        .shared();
+        if !config.auto_restart {
+            return self
+                .run(config_path, config, kafka_config, shutdown.boxed())
+                .await;
+        }
+
+        // restarted pipeline uses the config validated at startup, changes
+        // of the file are applied only with SIGHUP reload or process restart
+        let policy = RestartPolicy {
+            max_restart_attempts: config.max_restart_attempts as usize,
+            backoff: Duration::from_millis(config.restart_backoff_ms),
+            window: Duration::from_secs(config.restart_window_seconds),
+        };
+        policy
+            .run(self.name(), shutdown.clone(), || {
+                self.clone().run(
+                    config_path.clone(),
+                    config.clone(),
+                    kafka_config.clone(),
+                    shutdown.clone().boxed(),
+                )
+            })
+            .await
+    }
+
+    async fn run(
+        self,
+        config_path: String,
+        config: Config,
+        kafka_config: ClientConfig,
+        shutdown: BoxFuture<'static, ()>,
+    ) -> anyhow::Result<()> {
+        let retry = config.kafka_startup_retry;
+        let compression = KafkaCompression::new(
+            config.kafka_compression_codec.as_deref(),
+            config.kafka_compression_level,
+        )?;
         println!("running {:?}", self);
         match self {
             ArgsAction::Dedup => {
@@ -73,54 +290,170 @@ impl ArgsAction {
                 let config = config.dedup.ok_or_else(|| {
                     anyhow::anyhow!("`dedup` section in config should be defined")
                 })?;
-                Self::dedup(kafka_config, config, shutdown).await
+                Self::dedup(kafka_config, compression, config, retry, shutdown).await
             }
             ArgsAction::Grpc2Kafka => {
                 println!("running Grpc2Kafka");
                 let config = config.grpc2kafka.ok_or_else(|| {
                     anyhow::anyhow!("`grpc2kafka` section in config should be defined")
                 })?;
-                Self::grpc2kafka(kafka_config, config, shutdown).await
+                Self::grpc2kafka(
+                    kafka_config,
+                    compression,
+                    config,
+                    retry,
+                    config_path,
+                    shutdown,
+                )
+                .await
             }
             ArgsAction::Kafka2Grpc => {
                 println!("running Kafka2Grpc");
                 let config = config.kafka2grpc.ok_or_else(|| {
                     anyhow::anyhow!("`kafka2grpc` section in config should be defined")
                 })?;
-                Self::kafka2grpc(kafka_config, config, shutdown).await
+                Self::kafka2grpc(kafka_config, config, retry, shutdown).await
             }
+            ArgsAction::Grpc2Grpc => {
+                println!("running Grpc2Grpc");
+                let config = config.grpc2grpc.ok_or_else(|| {
+                    anyhow::anyhow!("`grpc2grpc` section in config should be defined")
+                })?;
+                Self::grpc2grpc(config, shutdown).await
+            }
+            ArgsAction::ListTopics { filter } => {
+                Self::list_topics(&kafka_config, filter.as_deref())
+            }
+            ArgsAction::Schema(schema) => schema.run(),
         }
     }
 
     async fn dedup(
         mut kafka_config: ClientConfig,
+        compression: KafkaCompression,
         config: ConfigDedup,
+        retry: ConfigKafkaStartupRetry,
         mut shutdown: BoxFuture<'static, ()>,
     ) -> anyhow::Result<()> {
         for (key, value) in config.kafka.into_iter() {
             kafka_config.set(key, value);
         }
+        validate_kafka_config(&kafka_config)?;
+        set_group_id_suffix(&mut kafka_config, config.kafka_group_id_suffix);
+        Dependency::Kafka.require();
 
         // input
+        let mut consumer_config = kafka_config.clone();
+        for (key, value) in config.consumer_timeouts.properties() {
+            consumer_config.set(key, value);
+        }
+        if config.flush_before_offset_commit {
+            consumer_config.set("enable.auto.commit", "false");
+        }
         let (consumer, kafka_error_rx1) =
-            metrics::StatsContext::create_stream_consumer(&kafka_config)
-                .context("failed to create kafka consumer")?;
-        consumer.subscribe(&[&config.kafka_input])?;
+            metrics::StatsContext::create_with_retry(retry, "consumer", || {
+                metrics::StatsContext::create_stream_consumer(&consumer_config)
+            })
+            .await
+            .context("failed to create kafka consumer")?;
+        match &config.assign {
+            Some(partitions) => assign_partitions(&consumer, &config.kafka_input, partitions)?,
+            None => consumer.subscribe(&[&config.kafka_input])?,
+        }
+        let consumer = Arc::new(consumer);
+        if let Some(interval) = config.watermark_poll_interval_seconds {
+            metrics::spawn_partition_watermark_monitor(
+                Arc::downgrade(&consumer),
+                Duration::from_secs(interval),
+            );
+        }
 
         // output
-        let (kafka, kafka_error_rx2) = metrics::StatsContext::create_future_producer(&kafka_config)
-            .context("failed to create kafka producer")?;
+        let (output, kafka_error_rx, grpc_shutdown) = match (
+            config.kafka_output,
+            config.grpc_output,
+        ) {
+            (Some(topic), None) => {
+                let producer_config = compression.producer_config(&kafka_config);
+                let (producer, kafka_error_rx2) =
+                    metrics::StatsContext::create_with_retry(retry, "producer", || {
+                        metrics::StatsContext::create_future_producer(&producer_config)
+                    })
+                    .await
+                    .context("failed to create kafka producer")?;
+                let output = DedupOutput::Kafka {
+                    producer,
+                    topic: Arc::new(topic),
+                };
+                let kafka_error_rx = futures::future::join(kafka_error_rx1, kafka_error_rx2)
+                    .map(|_| ())
+                    .boxed();
+                (output, kafka_error_rx, None)
+            }
+            (None, Some(grpc)) => {
+                anyhow::ensure!(
+                        config.input_format == Some(ConfigGrpc2KafkaFormat::Protobuf),
+                        "`grpc_output` requires `input_format: protobuf`, input messages are broadcast as `SubscribeUpdate`"
+                    );
+                let (grpc_tx, grpc_shutdown) = GrpcService::run(
+                    &grpc.listen,
+                    grpc.channel_capacity,
+                    Duration::from_secs(grpc.grpc_drain_timeout_seconds),
+                )?;
+                let kafka_error_rx = kafka_error_rx1.map(|_| ()).boxed();
+                (
+                    DedupOutput::Grpc(grpc_tx),
+                    kafka_error_rx,
+                    Some(grpc_shutdown),
+                )
+            }
+            (None, None) => anyhow::bail!("`kafka_output` or `grpc_output` should be defined"),
+            (Some(_), Some(_)) => {
+                anyhow::bail!("only one of `kafka_output` and `grpc_output` can be defined")
+            }
+        };
+        if config.flush_before_offset_commit {
+            // without auto-commit offsets of processed messages are lost on revoke
+            let hook_consumer = Arc::downgrade(&consumer);
+            let hook_output = output.clone();
+            consumer.context().set_revoke_hook(move || {
+                if let Err(error) = hook_output.flush() {
+                    warn!("kafka rebalance: failed to flush output: {error}");
+                    return;
+                }
+                if let Some(consumer) = hook_consumer.upgrade() {
+                    match consumer.commit_consumer_state(CommitMode::Sync) {
+                        Ok(()) => info!("kafka rebalance: offsets committed on revoke"),
+                        Err(error) => warn!("kafka rebalance: failed to commit offsets: {error}"),
+                    }
+                }
+            });
+        }
 
         let mut kafka_error = false;
-        let kafka_error_rx = futures::future::join(kafka_error_rx1, kafka_error_rx2);
         tokio::pin!(kafka_error_rx);
 
         // dedup
         let dedup = config.backend.create().await?;
+        let near_dedup = match config.fingerprint_mode {
+            ConfigFingerprintMode::Exact => None,
+            ConfigFingerprintMode::NearDuplicate {
+                shingle_size,
+                bit_diff_threshold,
+                min_payload_size,
+            } => Some((
+                KafkaDedupNearMemory::new(bit_diff_threshold)?,
+                shingle_size,
+                min_payload_size,
+            )),
+        };
+        let mut current_epoch = None;
+        let in_flight_limiter =
+            InFlightLimiter::new(config.max_in_flight, config.max_in_flight_per_slot);
 
         // input -> output loop
-        let kafka_output = Arc::new(config.kafka_output);
         let mut send_tasks = JoinSet::new();
+        let mut uncommitted = 0;
         loop {
             let message = tokio::select! {
                 _ = &mut shutdown => break,
@@ -150,6 +483,22 @@ impl ArgsAction {
                 message.key().and_then(|k| std::str::from_utf8(k).ok())
             );
 
+            if let (Some(max_lag), Some(timestamp)) = (
+                config.skip_if_output_lag_exceeds_seconds,
+                message.timestamp().to_millis(),
+            ) {
+                let lag = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)?
+                    .saturating_sub(Duration::from_millis(timestamp.max(0) as u64));
+                if lag > Duration::from_secs(max_lag) {
+                    metrics::dedup_lag_skip_inc();
+                    if config.flush_before_offset_commit {
+                        uncommitted += 1;
+                    }
+                    continue;
+                }
+            }
+
             let (key, payload) = match (
                 message
                     .key()
@@ -157,44 +506,125 @@ impl ArgsAction {
                 message.payload(),
             ) {
                 (Some(key), Some(payload)) => (key, payload.to_vec()),
-                _ => continue,
+                _ => {
+                    warn!("skip message without key or payload");
+                    metrics::skipped_inc("invalid_key");
+                    continue;
+                }
             };
-            let Some((slot, hash, bytes)) = key
-                .split_once('_')
-                .and_then(|(slot, hash)| slot.parse::<u64>().ok().map(|slot| (slot, hash)))
-                .and_then(|(slot, hash)| {
-                    let mut bytes: [u8; 32] = [0u8; 32];
-                    const_hex::decode_to_slice(hash, &mut bytes)
-                        .ok()
-                        .map(|()| (slot, hash, bytes))
-                })
-            else {
+            let Some((slot, hash)) = payload::parse_message_key(&key) else {
+                warn!("skip message with invalid key: {key}");
+                metrics::skipped_inc("invalid_key");
                 continue;
             };
-            debug!("received message slot #{slot} with hash {hash}");
+            debug!("received message slot #{slot} with key {key}");
+            if config.dedup_epoch_reset {
+                let epoch = slot_epoch(slot);
+                match current_epoch {
+                    Some(current) if epoch > current => {
+                        info!("epoch {epoch} started at slot #{slot}, reset dedup state");
+                        dedup.clear().await;
+                        if let Some((near_dedup, _shingle_size, _min_payload_size)) = &near_dedup {
+                            near_dedup.clear().await;
+                        }
+                        metrics::dedup_epoch_reset_inc();
+                        current_epoch = Some(epoch);
+                    }
+                    Some(_) => {}
+                    None => current_epoch = Some(epoch),
+                }
+            }
+            // decode only if required, payload is opaque otherwise
+            let key = if config.rekey_accounts_by_pubkey {
+                payload::account_pubkey(&payload).unwrap_or(key)
+            } else {
+                key
+            };
 
-            let kafka = kafka.clone();
+            let permit = tokio::select! {
+                _ = &mut shutdown => break,
+                _ = &mut kafka_error_rx => {
+                    kafka_error = true;
+                    break;
+                }
+                permit = in_flight_limiter.acquire(slot) => permit,
+            };
+            let task_output = output.clone();
             let dedup = dedup.clone();
-            let kafka_output = Arc::clone(&kafka_output);
+            let near_dedup = near_dedup.clone();
+            let in_flight = consumer.context().in_flight();
             send_tasks.spawn(async move {
-                if dedup.allowed(slot, bytes).await {
-                    let record = FutureRecord::to(&kafka_output).key(&key).payload(&payload);
-                    match kafka.send_result(record) {
-                        Ok(future) => {
-                            let result = future.await;
-                            debug!("kafka send message with key: {key}, result: {result:?}");
+                let _in_flight = in_flight;
+                let _permit = permit;
+                let ts = Instant::now();
+                let mut allowed = dedup.allowed(slot, &hash).await;
+                if !allowed {
+                    metrics::dedup_inc();
+                } else if let Some((near_dedup, shingle_size, min_payload_size)) = near_dedup {
+                    if payload.len() >= min_payload_size {
+                        allowed = near_dedup
+                            .allowed(slot, simhash(&payload, shingle_size))
+                            .await;
+                        if !allowed {
+                            metrics::near_duplicate_dedup_inc();
+                        }
+                    }
+                }
+                metrics::dedup_backend_latency_observe(ts.elapsed());
+                if allowed {
+                    match task_output {
+                        DedupOutput::Kafka { producer, topic } => {
+                            let record = FutureRecord::to(&topic).key(&key).payload(&payload);
+                            match producer.send_result(record) {
+                                Ok(future) => {
+                                    let result = future.await;
+                                    debug!(
+                                        "kafka send message with key: {key}, result: {result:?}"
+                                    );
 
-                            result?.map_err(|(error, _message)| error)?;
-                            metrics::sent_inc(GprcMessageKind::Unknown);
-                            Ok::<(), anyhow::Error>(())
+                                    result?.map_err(|(error, _message)| error)?;
+                                    metrics::sent_inc(GprcMessageKind::Unknown);
+                                    Ok::<(), anyhow::Error>(())
+                                }
+                                Err(error) => Err(error.0.into()),
+                            }
+                        }
+                        DedupOutput::Grpc(grpc_tx) => {
+                            match SubscribeUpdate::decode(payload.as_slice()) {
+                                Ok(message) => {
+                                    let kind = message
+                                        .update_oneof
+                                        .as_ref()
+                                        .map(GprcMessageKind::from)
+                                        .unwrap_or(GprcMessageKind::Unknown);
+                                    // not sent without subscribers
+                                    if grpc_tx.broadcast(message) {
+                                        metrics::sent_inc(kind);
+                                    }
+                                }
+                                Err(error) => {
+                                    warn!("failed to decode message with key {key}: {error}");
+                                    metrics::decode_error_inc(payload::decode_error_kind(&error));
+                                }
+                            }
+                            Ok(())
                         }
-                        Err(error) => Err(error.0.into()),
                     }
                 } else {
-                    metrics::dedup_inc();
                     Ok(())
                 }
             });
+            if config.flush_before_offset_commit {
+                uncommitted += 1;
+                if uncommitted >= config.commit_every_n_messages {
+                    while let Some(result) = send_tasks.join_next().await {
+                        result??;
+                    }
+                    output.flush()?;
+                    consumer.commit_consumer_state(CommitMode::Async)?;
+                    uncommitted = 0;
+                }
+            }
             if send_tasks.len() >= config.kafka_queue_size {
                 tokio::select! {
                     _ = &mut shutdown => break,
@@ -221,227 +651,984 @@ impl ArgsAction {
                     }
                 }
             }
+            if config.flush_before_offset_commit && uncommitted > 0 {
+                output.flush()?;
+                consumer.commit_consumer_state(CommitMode::Sync)?;
+            }
+        }
+        drop(output);
+        if let Some(grpc_shutdown) = grpc_shutdown {
+            grpc_shutdown.await??;
         }
         Ok(())
     }
 
     async fn grpc2kafka(
         mut kafka_config: ClientConfig,
+        compression: KafkaCompression,
         config: ConfigGrpc2Kafka,
+        retry: ConfigKafkaStartupRetry,
+        config_path: String,
         mut shutdown: BoxFuture<'static, ()>,
     ) -> anyhow::Result<()> {
-        for (key, value) in config.kafka.into_iter() {
+        let decode_transactions = config.decode_transactions();
+        for (key, value) in config.kafka.iter() {
             print!("kafka_config:  key {}, value {}", &key, &value);
             kafka_config.set(key, value);
         }
+        validate_kafka_config(&kafka_config)?;
 
         // Connect to kafka
-        let (kafka, kafka_error_rx) = metrics::StatsContext::create_future_producer(&kafka_config)
+        Dependency::Kafka.require();
+        Dependency::Grpc.require();
+        let producer_config = compression.producer_config(&kafka_config);
+        let (kafka, kafka_error_rx) =
+            metrics::StatsContext::create_with_retry(retry, "producer", || {
+                metrics::StatsContext::create_future_producer(&producer_config)
+            })
+            .await
             .context("failed to create kafka producer")?;
         let mut kafka_error = false;
         tokio::pin!(kafka_error_rx);
+        let failed_send = FailedSendHandler::new(
+            kafka.clone(),
+            config.retry_failed_send_count,
+            config.failed_send_dlq_topic.clone(),
+        );
 
-        let endpoints: Vec<String> = config
-        .endpoint
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .collect();
-        let mut ep_idx = 0;
-        let ep_count = endpoints.len();
+        let transform_chain = TransformChain::new(config.transform_chain.clone());
+        let env_headers = config
+            .header_injection_from_env
+            .iter()
+            .map(|(header, var)| {
+                std::env::var(var)
+                    .map(|value| (header.clone(), value))
+                    .with_context(|| format!("env variable {var} for header {header} is not set"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let base_headers =
+            env_headers
+                .iter()
+                .fold(OwnedHeaders::new(), |headers, (name, value)| {
+                    headers.insert(Header {
+                        key: name,
+                        value: Some(value),
+                    })
+                });
+        #[cfg(feature = "script-transform")]
+        let script = config
+            .script
+            .as_deref()
+            .map(Script::parse)
+            .transpose()
+            .context("failed to parse `script`")?;
+        #[cfg(not(feature = "script-transform"))]
+        anyhow::ensure!(
+            config.script.is_none(),
+            "`script` requires `script-transform` feature"
+        );
+        #[cfg(feature = "instruction-decoder")]
+        let instruction_decoders = config
+            .decode_instructions
+            .then(InstructionDecoders::with_builtin);
+        #[cfg(not(feature = "instruction-decoder"))]
+        anyhow::ensure!(
+            !config.decode_instructions,
+            "`decode_instructions` requires `instruction-decoder` feature"
+        );
+        let mut sampler = Sampler::new(&config.sample_rate, config.sample_seed)?;
+        let kind_in_flight = KindInFlightLimiter::new(&config.kafka_queue_size_per_kind)?;
+        let allow_types = config
+            .allow_types
+            .as_ref()
+            .map(|kinds| {
+                kinds
+                    .iter()
+                    .map(|kind| {
+                        GprcMessageKind::from_name(kind)
+                            .map(GprcMessageKind::as_str)
+                            .ok_or_else(|| anyhow::anyhow!("unknown kind in `allow_types`: {kind}"))
+                    })
+                    .collect::<anyhow::Result<HashSet<_>>>()
+            })
+            .transpose()?;
+        let mut slot_limiter = config.max_messages_per_slot.map(SlotLimiter::new);
+        let commitment_tracker = SlotCommitmentTracker::default();
+        let mut commitment_upgrade = config
+            .commitment_upgrade_window_slots
+            .map(CommitmentUpgradeFilter::new);
+        // randomly seeded per instance, so instances don't share delays
+        let mut jitter_rng = fastrand::Rng::new();
+        let mut jitter_slot = 0;
+        let (errors, errors_task) = match &config.error_topic {
+            Some(topic) => {
+                let (errors, task) = ErrorEvents::spawn(kafka.clone(), topic.clone());
+                (errors, Some(task))
+            }
+            None => (ErrorEvents::default(), None),
+        };
+        let partition_overrides = if config.partition_key_overrides.is_empty() {
+            None
+        } else {
+            let metadata = kafka
+                .client()
+                .fetch_metadata(Some(&config.kafka_topic), Duration::from_secs(10))
+                .context("failed to fetch topic metadata")?;
+            let topic = metadata
+                .topics()
+                .iter()
+                .find(|topic| topic.name() == config.kafka_topic)
+                .ok_or_else(|| anyhow::anyhow!("topic {} not found", config.kafka_topic))?;
+            if let Some(error) = topic.error() {
+                anyhow::bail!(
+                    "failed to fetch metadata for topic {}: {error:?}",
+                    config.kafka_topic
+                );
+            }
+            let partition_overrides = PartitionOverrides::new(
+                &config.partition_key_overrides,
+                topic.partitions().len() as i32,
+            )?;
+            if partition_overrides.has_fixed()
+                && kafka_config.get("enable.idempotence") != Some("true")
+            {
+                warn!("retries can reorder messages on fixed partitions, set `enable.idempotence` to `true` for strict ordering");
+            }
+            Some(partition_overrides)
+        };
+        let partition_refresh_task = partition_overrides
+            .clone()
+            .zip(
+                kafka_config
+                    .get("topic.metadata.refresh.interval.ms")
+                    .and_then(|interval_ms| interval_ms.parse::<u64>().ok())
+                    .filter(|interval_ms| *interval_ms > 0),
+            )
+            .map(|(partition_overrides, interval_ms)| {
+                partition::spawn_partition_count_refresh(
+                    kafka.clone(),
+                    config.kafka_topic.clone(),
+                    partition_overrides,
+                    Duration::from_millis(interval_ms),
+                )
+            });
+        let (leader_rx, leader_task) = match &config.leader_election {
+            Some(leader_election) => {
+                let (leader_rx, task) = LeaderElection::spawn(&kafka_config, leader_election)?;
+                (Some(leader_rx), Some(task))
+            }
+            None => (None, None),
+        };
 
-        loop {
-            let ep = &endpoints[ep_idx];
-            println!("trying connect to endpoint[{}]: {}", ep_idx, ep);
-
-            let builder = GeyserGrpcClient::build_from_shared(ep.clone())?    // :contentReference[oaicite:0]{index=0}
-            .x_token(config.x_token.clone())?                               // :contentReference[oaicite:1]{index=1}
-            .connect_timeout(Duration::from_secs(10))                     // :contentReference[oaicite:2]{index=2}
-            .timeout(Duration::from_secs(5))                              // :contentReference[oaicite:3]{index=3}
-            .tls_config(ClientTlsConfig::new().with_native_roots())?;     // :contentReference[oaicite:4]{index=4}
-
-            // 关键：用 builder.connect() 而非私有的 build()
-            let mut client = match builder.connect().await {                 // :contentReference[oaicite:5]{index=5}
-                Ok(c) => {
-                    println!("connected success, gRPC client is ready");
-                    c
-                }
-                Err(err) => {
-                    println!("connected failed: {:?}, swtich to next endpoint", err);
-                    ep_idx = (ep_idx + 1) % ep_count;
-                    thread::sleep(Duration::from_millis(2000)); 
-                    continue;
+        #[cfg(feature = "parquet-sink")]
+        let (parquet_sink, parquet_task) = match config.parquet.clone() {
+            Some(parquet) => {
+                let (sink, task) = ParquetSink::spawn(parquet)?;
+                (Some(sink), Some(task))
+            }
+            None => (None, None),
+        };
+        #[cfg(not(feature = "parquet-sink"))]
+        anyhow::ensure!(
+            config.parquet.is_none(),
+            "`parquet` sink requires `parquet-sink` feature"
+        );
+        let (write_behind_cache, write_behind_task) = match config.write_behind_cache.clone() {
+            Some(cache_config) => {
+                let (cache, task) =
+                    WriteBehindCache::spawn(cache_config, kafka.clone(), errors.clone());
+                (Some(cache), Some(task))
+            }
+            None => (None, None),
+        };
+        anyhow::ensure!(
+            !config.produce_heartbeats || config.heartbeat_topic.is_some(),
+            "`produce_heartbeats` requires `heartbeat_topic`"
+        );
+        if config.dual_write.is_some() {
+            anyhow::ensure!(
+                config.format == ConfigGrpc2KafkaFormat::Protobuf,
+                "`dual_write` requires `protobuf` format"
+            );
+            anyhow::ensure!(
+                config.parquet.is_none(),
+                "`dual_write` can't be used with `parquet` sink"
+            );
+        }
+
+        // gRPC subscriptions
+        anyhow::ensure!(
+            config.connection_pool_size > 0,
+            "`connection_pool_size` should be greater than 0"
+        );
+        anyhow::ensure!(
+            config.hedged_connect_concurrency != Some(0),
+            "`hedged_connect_concurrency` should be greater than 0"
+        );
+        // copies of updates from pooled connections
+        let pool_dedup = (config.connection_pool_size > 1).then(KafkaDedupMemory::default);
+        let mut request = config.request.clone().to_proto();
+        anyhow::ensure!(
+            !config.wait_for_confirmation || !request.slots.is_empty(),
+            "`wait_for_confirmation` requires `slots` in the subscribe request"
+        );
+        // updated by send tasks once the broker acknowledged the message
+        let checkpoint = match &config.checkpoint_path {
+            Some(path) => {
+                if config.checkpoint_resume && request.from_slot.is_none() {
+                    if let Some(checkpoint) = Checkpoint::load(path).await? {
+                        info!("subscribe from checkpoint slot {}", checkpoint.slot);
+                        request.from_slot = Some(checkpoint.slot);
+                    }
                 }
-            };
+                Some(Arc::new(Mutex::new(CheckpointWriter::new(
+                    path.clone(),
+                    config.checkpoint_every_n_messages,
+                    Duration::from_secs(config.checkpoint_interval_seconds),
+                ))))
+            }
+            None => None,
+        };
+        let (x_token_header, x_token_header_overrides) =
+            parse_x_token_headers(&config.x_token_header, &config.x_token_header_overrides)?;
+        // without `from_slot` there is no backfill
+        let mut reorder_buffer = config
+            .backfill_reorder
+            .filter(|_| request.from_slot.is_some())
+            .map(|reorder| {
+                info!("subscribe from slot with backfill reorder: {reorder:?}");
+                SlotReorderBuffer::new(
+                    reorder.window_slots,
+                    reorder.max_buffered_messages,
+                    reorder.settle_messages,
+                )
+            });
+        let (request_tx, request_rx) = watch::channel(request);
+        let mut sighup = if config.use_streaming_subscribe {
+            Some(signal(SignalKind::hangup())?)
+        } else {
+            None
+        };
+        let mut upstream = GeyserFailover::spawn(
+            GeyserSubscriber {
+                source: GeyserSource::Primary,
+                endpoints: split_endpoints(&config.endpoint),
+                x_token: config.x_token.clone(),
+                x_token_header,
+                x_token_header_overrides,
+                request: request_rx,
+                keepalive: config.keepalive,
+                errors: errors.clone(),
+                client_ping_interval: config.client_ping_interval.map(Duration::from_millis),
+                initial_reconnect_delay: Duration::from_millis(config.initial_reconnect_delay_ms),
+                health_service_name: config.grpc_health_service_name.clone(),
+                user_agent: config.grpc_user_agent.clone(),
+                health_check_interval: config
+                    .grpc_health_check_interval_seconds
+                    .map(Duration::from_secs),
+                slot_gap_reconnect_threshold: config.slot_gap_reconnect_threshold,
+                startup_message_timeout: config
+                    .startup_message_timeout_seconds
+                    .map(Duration::from_secs),
+                hedged_connect_concurrency: config.hedged_connect_concurrency,
+            },
+            config.secondary_endpoint.as_deref().map(split_endpoints),
+            config.connection_pool_size,
+            Duration::from_secs(config.failover_threshold_seconds),
+        );
 
-            let req = config.request.clone(); 
+        if config.enforce_monotonic_slots {
+            info!("monotonic slots enforced after reconnect and failover");
+        }
+        let mut monotonic_slots = MonotonicSlots::default();
+        let mut last_subscriptions = 0;
+        let mut last_source = GeyserSource::Primary;
+        let mut confirmation_wait = config.wait_for_confirmation.then(|| {
+            SlotConfirmationWait::new(
+                Duration::from_millis(config.confirmation_timeout_ms),
+                config.confirmation_max_pending,
+            )
+        });
 
-            println!("subscribe, {:?}", req); 
-            // let mut geyser = client.subscribe_once(config.request.to_proto()).await?;
-            let mut geyser = match client.subscribe_once(req.to_proto()).await {
-                Ok(s) => s,
-                Err(err) => {
-                    println!("subscribe failed: {:?}, switch to next endpoint", err);
-                    ep_idx = (ep_idx + 1) % ep_count;
-                    thread::sleep(Duration::from_millis(2000)); 
+        // Receive-send loop
+        let mut send_tasks = JoinSet::new();
+        'messages: loop {
+            let (released, (source, message)) = tokio::select! {
+                _ = &mut shutdown => break,
+                _ = &mut kafka_error_rx => {
+                    kafka_error = true;
+                    break;
+                }
+                Some(result) = send_tasks.join_next() => {
+                    result??;
+                    continue;
+                }
+                _ = async { sighup.as_mut().expect("defined").recv().await }, if sighup.is_some() => {
+                    match config_load::<Config>(config_path.as_str()).await.and_then(|config| {
+                        config.grpc2kafka.ok_or_else(|| {
+                            anyhow::anyhow!("`grpc2kafka` section in config should be defined")
+                        })
+                    }) {
+                        Ok(new_config) => {
+                            info!("SIGHUP received, update subscribe request");
+                            let _ = request_tx.send(new_config.request.to_proto());
+                        }
+                        Err(error) => warn!("failed to reload config on SIGHUP: {error:?}"),
+                    }
                     continue;
                 }
+                message = async { confirmation_wait.as_mut().expect("defined").next().await }, if confirmation_wait.is_some() => (true, message),
+                message = async {
+                    match reorder_buffer.as_mut() {
+                        Some(buffer) => upstream.recv_reordered(buffer).await,
+                        None => upstream.recv().await,
+                    }
+                } => (false, message?),
             };
+            let received_at = Instant::now();
 
-            // Receive-send loop
-            let mut send_tasks = JoinSet::new();
-            'stream_loop: loop {
-                let msg_result = tokio::select! {
-                    _ = &mut shutdown => break,
-                    _ = &mut kafka_error_rx => {
-                        kafka_error = true;
-                        break;
-                    }
-                    maybe_result = send_tasks.join_next() => match maybe_result {
-                        Some(result) => {
-                            result??;
-                            continue;
-                        }
-                        None => tokio::select! {
-                            _ = &mut shutdown => break,
-                            _ = &mut kafka_error_rx => {
-                                kafka_error = true;
-                                break;
+            let (source, mut message) = match (&mut confirmation_wait, released) {
+                (Some(wait), false) => {
+                    let slot = match &message.update_oneof {
+                        Some(UpdateOneof::Slot(msg)) => {
+                            if msg.status == SlotStatus::SlotConfirmed as i32
+                                || msg.status == SlotStatus::SlotFinalized as i32
+                            {
+                                wait.confirm(msg.slot);
                             }
-                            message = geyser.next() => message,
+                            None
                         }
-                    },
-                    message = geyser.next() => message,
-                }
-                .transpose();
-
-                let message;
-                match msg_result {
-                    Ok(Some(msg)) => {
-                        message = msg;
-                        // let payload = message.encode_to_vec();
-                        let mut payload: Option<Vec<u8>> = None;
-                        let message = match &message.update_oneof {
-                            Some(value) => value,
-                            None => unreachable!("Expect valid message"),
-                        };
-                        let slot = match message {
-                            UpdateOneof::Account(msg) => msg.slot,
-                            UpdateOneof::Slot(msg) => msg.slot,
-                            UpdateOneof::Transaction(msg) => {
-                                payload = msg.transaction.as_ref().and_then(|transaction| {
-                                    let tx_data = transaction.encode_to_vec();
-                                    let b64: String = general_purpose::STANDARD.encode(&tx_data);
-                                    print!("tx_data: {}", b64);
-                                    match crate::generated::prelude::SubscribeUpdateTransactionInfo::decode(tx_data.as_slice()) {
-                                        Ok(tx) => {
-                                            let tx_json = serde_json::to_string(&tx).unwrap();
-                                            // print!("tx_json: {}", &tx_json);
-                                            Some(tx_json.into_bytes())
-                                        }
-                                        Err(error) => {
-                                            warn!("failed to decode message: {}", error);
-                                            None
-                                        }
-                                    }
-                                });
-                                msg.slot
-                            },
-                            UpdateOneof::TransactionStatus(msg) => msg.slot,
-                            UpdateOneof::Block(msg) => msg.slot,
-                            UpdateOneof::Ping(_) => continue,
-                            UpdateOneof::Pong(_) => continue,
-                            UpdateOneof::BlockMeta(msg) => msg.slot,
-                            UpdateOneof::Entry(msg) => msg.slot,
-                        };
-                        
-                        let Some(send_data) = payload else {
-                            continue;
-                        };
+                        Some(update) => payload::update_slot(update),
+                        None => None,
+                    };
+                    match slot {
+                        Some(slot) => match wait.push(slot, (source, message)) {
+                            Some(message) => message,
+                            None => continue,
+                        },
+                        None => (source, message),
+                    }
+                }
+                _ => (source, message),
+            };
 
-                        let hash = Sha256::digest(&send_data);
-                        let key = format!("{slot}_{}", const_hex::encode(hash));
-                        let prom_kind = GprcMessageKind::from(message);
-                        // print!("received data, key: {}\n", &key);
+            if let Some(leader_rx) = &leader_rx {
+                if !*leader_rx.borrow() {
+                    continue;
+                }
+            }
 
-                        let record = FutureRecord::to(&config.kafka_topic)
-                            .key(&key)
-                            .payload(&send_data);
+            if let (
+                Some(projection),
+                Some(UpdateOneof::Account(SubscribeUpdateAccount {
+                    account: Some(account),
+                    ..
+                })),
+            ) = (config.account_projection, &mut message.update_oneof)
+            {
+                payload::project_account(account, projection);
+            }
 
+            let mut payload: Option<Vec<u8>> = None;
+            let mut transformed = None;
+            let mut failed_transaction_topic = None;
+            #[cfg(feature = "script-transform")]
+            let mut script_skip = false;
+            let update = match &message.update_oneof {
+                Some(value) => value,
+                None => unreachable!("Expect valid message"),
+            };
+            if let (Some(dedup), Some(slot)) = (&pool_dedup, payload::update_slot(update)) {
+                if !dedup.allowed(slot, &payload::update_hash(update)).await {
+                    continue;
+                }
+            }
+            // before sampling, otherwise sampled out slots are counted as gaps
+            if let UpdateOneof::Slot(msg) = update {
+                commitment_tracker.update(msg.slot, msg.status);
+                let (last_seen, gap) = metrics::slot_seen(msg.slot);
+                if gap > metrics::SLOT_GAP_ALERT {
+                    warn!("missed {gap} slots before slot #{}", msg.slot);
+                }
+                if let Some(slot_marker_topic) = &config.slot_marker_topic {
+                    if last_seen != 0 && msg.slot > last_seen {
+                        let marker = serde_json::json!({
+                            "slot": last_seen,
+                            "next_slot": msg.slot,
+                            "timestamp": SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64,
+                        });
+                        let marker_payload = serde_json::to_vec(&marker)?;
+                        let marker_key = last_seen.to_string();
+                        let record = FutureRecord::to(slot_marker_topic)
+                            .key(&marker_key)
+                            .payload(&marker_payload)
+                            .headers(base_headers.clone());
                         match kafka.send_result(record) {
                             Ok(future) => {
                                 let _ = send_tasks.spawn(async move {
-                                    let result = future.await;
-                                    println!("kafka send message with key: {key}, result: {result:?}");
-
-                                    let _ = result?.map_err(|(error, _message)| error)?;
-                                    metrics::sent_inc(prom_kind);
+                                    if let Err((error, _message)) = future.await? {
+                                        return Err(error.into());
+                                    }
                                     Ok::<(), anyhow::Error>(())
                                 });
-                                if send_tasks.len() >= config.kafka_queue_size {
-                                    tokio::select! {
-                                        _ = &mut shutdown => break,
-                                        _ = &mut kafka_error_rx => {
-                                            kafka_error = true;
-                                            break;
+                            }
+                            Err((error, _record)) => return Err(error.into()),
+                        }
+                    }
+                }
+            }
+            if let Some(sharding) = &config.sharding {
+                let shard = shard::shard_of(update, sharding.hash_field, sharding.shard_count);
+                if shard != sharding.shard_index {
+                    metrics::skipped_inc("other_shard");
+                    continue;
+                }
+            }
+            let prom_kind = GprcMessageKind::from(update);
+            if let Some(allow_types) = &allow_types {
+                if !allow_types.contains(prom_kind.as_str()) {
+                    metrics::skipped_inc("type_not_allowed");
+                    continue;
+                }
+            }
+            if !sampler.keep(prom_kind) {
+                metrics::sampled_out_inc(prom_kind);
+                continue;
+            }
+            if let Some(slot_limiter) = &mut slot_limiter {
+                match update {
+                    UpdateOneof::Slot(msg) => {
+                        if msg.status == SlotStatus::SlotConfirmed as i32 {
+                            slot_limiter.confirmed(msg.slot);
+                        }
+                    }
+                    update => {
+                        if let Some(slot) = payload::update_slot(update) {
+                            if !slot_limiter.allow(slot) {
+                                metrics::per_slot_limit_exceeded_inc(prom_kind);
+                                continue;
+                            }
+                        }
+                    }
+                }
+            }
+            let slot = match update {
+                UpdateOneof::Account(msg) => msg.slot,
+                UpdateOneof::Slot(msg) => msg.slot,
+                UpdateOneof::Transaction(msg) => {
+                    let failed = msg
+                        .transaction
+                        .as_ref()
+                        .and_then(|transaction| transaction.meta.as_ref())
+                        .is_some_and(|meta| meta.err.is_some());
+                    if failed && config.skip_failed_transactions {
+                        match &config.failed_transaction_topic {
+                            Some(topic) => {
+                                metrics::failed_transaction_skipped_inc("routed");
+                                failed_transaction_topic = Some(topic);
+                            }
+                            None => {
+                                metrics::failed_transaction_skipped_inc("skipped");
+                                metrics::skipped_inc("failed_transaction");
+                                continue;
+                            }
+                        }
+                    }
+                    if let (Some(filter), Some(transaction)) =
+                        (&mut commitment_upgrade, &msg.transaction)
+                    {
+                        let commitment = commitment_tracker.derived_commitment(msg.slot);
+                        if !filter.allow(&transaction.signature, msg.slot, commitment) {
+                            metrics::skipped_inc("commitment_duplicate");
+                            continue;
+                        }
+                    }
+                    if decode_transactions {
+                        payload = msg.transaction.as_ref().and_then(|transaction| {
+                            match payload::decode_transaction(transaction) {
+                                Ok(tx)
+                                    if transform_chain.is_empty()
+                                        && config.script.is_none()
+                                        && !config.include_loaded_addresses
+                                        && !config.decode_instructions
+                                        && config.account_data_encoding
+                                            == ConfigAccountDataEncoding::Raw
+                                        && config.signature_encoding
+                                            == ConfigSignatureEncoding::Bytes =>
+                                {
+                                    match serde_json::to_vec(&tx) {
+                                        Ok(tx_json) => Some(tx_json),
+                                        // not expected for generated types, retry
+                                        // field by field as `transaction_to_value`
+                                        Err(_error) => Some(
+                                            payload::transaction_to_value(
+                                                &tx,
+                                                config.signature_encoding,
+                                            )
+                                            .to_string()
+                                            .into_bytes(),
+                                        ),
+                                    }
+                                }
+                                Ok(tx) => {
+                                    let mut tx_json = payload::transaction_to_value(
+                                        &tx,
+                                        config.signature_encoding,
+                                    );
+                                    payload::encode_account_data(
+                                        &tx,
+                                        &mut tx_json,
+                                        config.account_data_encoding,
+                                    );
+                                    if config.include_loaded_addresses {
+                                        payload::insert_loaded_addresses(&tx, &mut tx_json);
+                                    }
+                                    #[cfg(feature = "instruction-decoder")]
+                                    if let Some(decoders) = &instruction_decoders {
+                                        decoders.decode_transaction(&tx, &mut tx_json);
+                                    }
+                                    let result = transform_chain.apply(&mut tx_json);
+                                    #[cfg(feature = "script-transform")]
+                                    let result = match script
+                                        .as_ref()
+                                        .map(|script| script.apply(&mut tx_json))
+                                    {
+                                        Some(ScriptOutput::Skip) => {
+                                            script_skip = true;
+                                            return None;
                                         }
-                                        result = send_tasks.join_next() => {
-                                            if let Some(result) = result {
-                                                result??;
-                                            }
+                                        Some(ScriptOutput::Produce(Some(key))) => {
+                                            result.with_key_template(key)
                                         }
-                                    }
+                                        _ => result,
+                                    };
+                                    transformed = Some(result);
+                                    Some(tx_json.to_string().into_bytes())
+                                }
+                                Err(error) => {
+                                    warn!("failed to decode message: {}", error);
+                                    errors.emit(
+                                        ErrorEventKind::Decode,
+                                        format!(
+                                            "failed to decode transaction at slot {}: {error}",
+                                            msg.slot
+                                        ),
+                                    );
+                                    None
                                 }
                             }
-                            Err(error) => return Err(error.0.into()),
+                        });
+                    }
+                    #[cfg(feature = "script-transform")]
+                    if script_skip {
+                        metrics::skipped_inc("script");
+                        continue;
+                    }
+                    msg.slot
+                }
+                UpdateOneof::TransactionStatus(msg) => msg.slot,
+                UpdateOneof::Block(msg) => msg.slot,
+                UpdateOneof::Ping(_) | UpdateOneof::Pong(_) => {
+                    let Some(heartbeat_topic) = config
+                        .heartbeat_topic
+                        .as_ref()
+                        .filter(|_| config.produce_heartbeats)
+                    else {
+                        continue;
+                    };
+                    let timestamp =
+                        SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+                    let source = source.to_string();
+                    let heartbeat = serde_json::json!({
+                        "timestamp": timestamp,
+                        "source": source,
+                        "kind": prom_kind.as_str(),
+                    });
+                    let heartbeat_payload = serde_json::to_vec(&heartbeat)?;
+                    let record = FutureRecord::to(heartbeat_topic)
+                        .key(&source)
+                        .payload(&heartbeat_payload)
+                        .headers(base_headers.clone());
+                    match kafka.send_result(record) {
+                        Ok(future) => {
+                            let _ = send_tasks.spawn(async move {
+                                if let Err((error, _message)) = future.await? {
+                                    return Err(error.into());
+                                }
+                                metrics::heartbeat_sent_inc(&source);
+                                Ok::<(), anyhow::Error>(())
+                            });
                         }
+                        Err((error, _record)) => return Err(error.into()),
                     }
-                    Ok(None) => {
-                        // closed by the remote peer
-                        println!("gRPC is closed (Ok(None)), switch to next endpoint");  // 
-                        break 'stream_loop;
+                    continue;
+                }
+                UpdateOneof::BlockMeta(msg) => msg.slot,
+                UpdateOneof::Entry(msg) => msg.slot,
+            };
+            // messages released by confirmation wait and backfill reorder are
+            // out of order by design
+            if config.enforce_monotonic_slots
+                && !released
+                && reorder_buffer
+                    .as_ref()
+                    .is_none_or(|buffer| buffer.is_passthrough())
+            {
+                let subscriptions = upstream.subscriptions();
+                if subscriptions != last_subscriptions || source != last_source {
+                    last_subscriptions = subscriptions;
+                    last_source = source;
+                    monotonic_slots.reconnected();
+                    if monotonic_slots.is_catching_up() {
+                        info!("new {source} stream, drop messages below last produced slots");
                     }
-                    Err(status) => {
-                        // RPC/connection error
-                        println!("rpc error(code={:?}): {}, switch to next endpoint", 
-                                 status.code(), status.message());                  // 
-                        break 'stream_loop;
+                }
+                let commitment = match update {
+                    UpdateOneof::Slot(msg) => Some(msg.status),
+                    _ => None,
+                };
+                let catching_up = monotonic_slots.is_catching_up();
+                if !monotonic_slots.allow((prom_kind.as_str(), commitment), slot) {
+                    metrics::skipped_inc("non_monotonic_slot");
+                    continue;
+                }
+                if catching_up && !monotonic_slots.is_catching_up() {
+                    info!("slots are monotonic again from slot {slot}");
+                }
+            }
+
+            // decoded JSON transaction is the second output in dual write mode
+            let mut json_payload = None;
+            if config.format == ConfigGrpc2KafkaFormat::Protobuf {
+                json_payload = payload.take();
+                payload = Some(message.encode_to_vec());
+            }
+
+            let Some(send_data) = payload else {
+                continue;
+            };
+            let topic = failed_transaction_topic.unwrap_or(&config.kafka_topic);
+            let Some(topic) = config.payload_topic(topic, send_data.len()) else {
+                warn!(
+                    "skip message at slot {slot}: payload {} bytes exceeds `max_payload_bytes`",
+                    send_data.len()
+                );
+                metrics::skipped_inc("oversize");
+                continue;
+            };
+
+            let hash = payload::payload_hash_with(&send_data, config.payload_hash_algorithm);
+            let mut key = format!("{slot}_{hash}");
+
+            let mut headers = base_headers.clone();
+            if let UpdateOneof::Transaction(msg) = update {
+                headers = headers.insert(Header {
+                    key: "x-derived-commitment",
+                    value: Some(commitment_tracker.derived_commitment(msg.slot)),
+                });
+            }
+            if let Some(transformed) = transformed {
+                let (new_key, new_headers) = transformed.render(&TransformVars {
+                    slot,
+                    kind: prom_kind.as_str(),
+                    hash: &hash,
+                    key: &key,
+                });
+                for (name, value) in new_headers.iter() {
+                    headers = headers.insert(Header {
+                        key: name,
+                        value: Some(value),
+                    });
+                }
+                if let Some(new_key) = new_key {
+                    key = new_key;
+                }
+            }
+
+            #[cfg(feature = "parquet-sink")]
+            if let Some(sink) = &parquet_sink {
+                let row = ParquetRow::new(slot, prom_kind.as_str(), key, update, send_data);
+                sink.push(row).await?;
+                metrics::sent_inc(prom_kind);
+                continue;
+            }
+
+            // delay only the start of the slot burst, the loop is not blocked
+            // for every message
+            if let Some(jitter_send_delay_ms) = config
+                .jitter_send_delay_ms
+                .filter(|ms| *ms > 0 && slot > jitter_slot)
+            {
+                jitter_slot = slot;
+                let delay = jitter_rng.u64(0..jitter_send_delay_ms * 1_000);
+                sleep(Duration::from_micros(delay)).await;
+            }
+
+            // failed transactions are only routed to `failed_transaction_topic`
+            let json_payload = json_payload.filter(|_| failed_transaction_topic.is_none());
+            let json_output = match (&config.dual_write, json_payload) {
+                (Some(dual_write), Some(json_payload)) => {
+                    match config.payload_topic(&dual_write.json_topic, json_payload.len()) {
+                        Some(topic) => Some((topic, json_payload)),
+                        None => {
+                            warn!(
+                                "skip JSON message at slot {slot}: payload {} bytes exceeds `max_payload_bytes`",
+                                json_payload.len()
+                            );
+                            metrics::skipped_inc("oversize");
+                            None
+                        }
                     }
                 }
-                ep_idx = (ep_idx + 1) % ep_count;
-                thread::sleep(Duration::from_millis(2000));
-            }
-            if !kafka_error {
-                warn!("shutdown received...");
-                loop {
-                    tokio::select! {
-                        _ = &mut kafka_error_rx => break,
-                        result = send_tasks.join_next() => match result {
-                            Some(result) => result??,
-                            None => break
+                _ => None,
+            };
+
+            let partition = match &partition_overrides {
+                Some(partition_overrides) if topic == &config.kafka_topic => partition_overrides
+                    .partition(&TransformVars {
+                        slot,
+                        kind: prom_kind.as_str(),
+                        hash: &hash,
+                        key: &key,
+                    }),
+                _ => None,
+            };
+
+            if let (Some(cache), UpdateOneof::Account(msg)) = (&write_behind_cache, update) {
+                if let Some(account) = &msg.account {
+                    let record = CachedRecord {
+                        topic: topic.clone(),
+                        key,
+                        payload: send_data,
+                        headers,
+                        partition,
+                        kind: prom_kind,
+                    };
+                    cache.push(account.pubkey.clone(), record).await?;
+                    continue;
+                }
+            }
+
+            // JSON record of dual write shares key, headers and limits with
+            // the main record
+            let dual_write = config.dual_write.is_some();
+            let outputs = std::iter::once((topic, send_data, partition, false))
+                .chain(json_output.map(|(topic, payload)| (topic, payload, None, true)));
+            for (topic, send_data, partition, json) in outputs {
+                // waits only while the kind is at its limit, messages of other
+                // kinds wait behind it to keep the order
+                let kind_permit = tokio::select! {
+                    _ = &mut shutdown => break 'messages,
+                    _ = &mut kafka_error_rx => {
+                        kafka_error = true;
+                        break 'messages;
+                    }
+                    permit = kind_in_flight.acquire(prom_kind) => permit,
+                };
+
+                let key = key.clone();
+                let payload_size = send_data.len();
+                let mut record = FutureRecord::to(topic)
+                    .key(&key)
+                    .payload(&send_data)
+                    .headers(headers.clone());
+                record.partition = partition;
+
+                match kafka.send_result(record) {
+                    Ok(future) => {
+                        let checkpoint = checkpoint.as_ref().filter(|_| !json).map(Arc::clone);
+                        let errors = errors.clone();
+                        let failed_send = failed_send.clone();
+                        let _ = send_tasks.spawn(async move {
+                            let result = future.await;
+                            drop(kind_permit);
+                            debug!("kafka send message with key: {key}, result: {result:?}");
+
+                            if let Err((error, message)) = result? {
+                                match failed_send
+                                    .handle(FailedRecord::from_message(message), error)
+                                    .await
+                                {
+                                    Ok(true) => {}
+                                    Ok(false) => return Ok(()),
+                                    Err(error) => {
+                                        errors.emit(
+                                            ErrorEventKind::Produce,
+                                            format!(
+                                                "failed to send message with key {key}: {error}"
+                                            ),
+                                        );
+                                        return Err(error);
+                                    }
+                                }
+                            }
+                            if let Some(checkpoint) = checkpoint {
+                                checkpoint.lock().await.update(slot, &key).await;
+                            }
+                            if json {
+                                metrics::dual_write_sent_inc("json");
+                                return Ok(());
+                            }
+                            metrics::sent_inc(prom_kind);
+                            metrics::bytes_produced_inc(prom_kind, payload_size);
+                            if dual_write {
+                                metrics::dual_write_sent_inc("protobuf");
+                            }
+                            metrics::message_latency_observe(prom_kind, received_at.elapsed());
+                            Ok::<(), anyhow::Error>(())
+                        });
+                        if send_tasks.len() >= config.kafka_queue_size {
+                            tokio::select! {
+                                _ = &mut shutdown => break 'messages,
+                                _ = &mut kafka_error_rx => {
+                                    kafka_error = true;
+                                    break 'messages;
+                                }
+                                result = send_tasks.join_next() => {
+                                    if let Some(result) = result {
+                                        result??;
+                                    }
+                                }
+                            }
                         }
                     }
+                    Err((error, record)) => {
+                        match failed_send
+                            .handle(FailedRecord::from_record(record), error)
+                            .await
+                        {
+                            Ok(true) if json => metrics::dual_write_sent_inc("json"),
+                            Ok(true) => {
+                                metrics::sent_inc(prom_kind);
+                                if let Some(checkpoint) = &checkpoint {
+                                    checkpoint.lock().await.update(slot, &key).await;
+                                }
+                            }
+                            Ok(false) => {}
+                            Err(error) => {
+                                errors.emit(
+                                    ErrorEventKind::Produce,
+                                    format!("failed to send message with key {key}: {error}"),
+                                );
+                                return Err(error);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        upstream.abort();
+        if let Some(leader_task) = leader_task {
+            leader_task.abort();
+        }
+        if let Some(partition_refresh_task) = partition_refresh_task {
+            partition_refresh_task.abort();
+        }
+        drop(write_behind_cache);
+        if !kafka_error {
+            warn!("shutdown received...");
+            loop {
+                tokio::select! {
+                    _ = &mut kafka_error_rx => break,
+                    result = send_tasks.join_next() => match result {
+                        Some(result) => result??,
+                        None => break
+                    }
                 }
             }
+            if let Some(task) = write_behind_task {
+                task.await??;
+            }
+            wait_for_kafka_lag_drain(&kafka, config.kafka_drain_timeout_seconds).await?;
+            if let Some(checkpoint) = &checkpoint {
+                checkpoint.lock().await.flush().await;
+            }
+        }
+
+        drop(send_tasks);
+        drop(errors);
+        if let Some(errors_task) = errors_task {
+            let _ = errors_task.await;
         }
+
+        #[cfg(feature = "parquet-sink")]
+        if let Some(task) = parquet_task {
+            drop(parquet_sink);
+            task.await??;
+        }
+        Ok(())
     }
 
     async fn kafka2grpc(
         mut kafka_config: ClientConfig,
         config: ConfigKafka2Grpc,
+        retry: ConfigKafkaStartupRetry,
         mut shutdown: BoxFuture<'static, ()>,
     ) -> anyhow::Result<()> {
+        let topic_pattern = config.kafka_topic_pattern()?;
+        anyhow::ensure!(
+            topic_pattern.is_none() || config.assign.is_none(),
+            "`assign` can't be used with topic pattern"
+        );
         for (key, value) in config.kafka.into_iter() {
             kafka_config.set(key, value);
         }
+        for (key, value) in config.consumer_timeouts.properties() {
+            kafka_config.set(key, value);
+        }
+        set_group_id_suffix(&mut kafka_config, config.kafka_group_id_suffix);
+        Dependency::Kafka.require();
+        Dependency::Partitions.require();
 
-        let (grpc_tx, grpc_shutdown) = GrpcService::run(config.listen, config.channel_capacity)?;
+        let replay = match config.replay_window_seconds {
+            Some(window) => {
+                anyhow::ensure!(
+                    topic_pattern.is_none(),
+                    "`replay_window_seconds` can't be used with topic pattern"
+                );
+                Some(KafkaReplay {
+                    kafka_config: kafka_config.clone(),
+                    topic: config.kafka_topic.clone(),
+                    window: Duration::from_secs(window),
+                    filter_from_headers: config.filter_from_headers,
+                })
+            }
+            None => None,
+        };
+        // cursors are only useful to resume replay
+        let cursors = replay.is_some();
+        let (grpc_tx, grpc_shutdown) = GrpcService::run_with(
+            &config.listen,
+            config.channel_capacity,
+            Duration::from_secs(config.grpc_drain_timeout_seconds),
+            config.per_client_rate_limit_per_second,
+            replay,
+        )?;
 
         let (consumer, kafka_error_rx) =
-            metrics::StatsContext::create_stream_consumer(&kafka_config)
-                .context("failed to create kafka consumer")?;
+            metrics::StatsContext::create_with_retry(retry, "consumer", || {
+                metrics::StatsContext::create_stream_consumer(&kafka_config)
+            })
+            .await
+            .context("failed to create kafka consumer")?;
         let mut kafka_error = false;
         tokio::pin!(kafka_error_rx);
-        consumer.subscribe(&[&config.kafka_topic])?;
+        if let Some(pattern) = topic_pattern {
+            let metadata = consumer
+                .fetch_metadata(None, Duration::from_secs(10))
+                .context("failed to fetch metadata")?;
+            let topics = metadata
+                .topics()
+                .iter()
+                .map(|topic| topic.name())
+                .filter(|name| pattern.is_match(name))
+                .collect::<Vec<_>>();
+            info!("kafka topic pattern {pattern} matches {topics:?}");
+        }
+        match &config.assign {
+            Some(partitions) => assign_partitions(&consumer, &config.kafka_topic, partitions)?,
+            None => consumer.subscribe(&[&config.kafka_topic])?,
+        }
 
         loop {
             let message = tokio::select! {
@@ -453,6 +1640,7 @@ impl ArgsAction {
                 message = consumer.recv() => message?,
             };
             metrics::recv_inc();
+            let _in_flight = consumer.context().in_flight();
             debug!(
                 "received message with key: {:?}",
                 message.key().and_then(|k| std::str::from_utf8(k).ok())
@@ -460,11 +1648,21 @@ impl ArgsAction {
 
             if let Some(payload) = message.payload() {
                 match SubscribeUpdate::decode(payload) {
-                    Ok(message) => {
-                        let _ = grpc_tx.send(message);
+                    Ok(update) => {
+                        let account = if config.filter_from_headers {
+                            account_filter_header(message.headers())
+                        } else {
+                            None
+                        };
+                        grpc_tx.send(GrpcBroadcast {
+                            update,
+                            account,
+                            cursor: cursors.then(|| ReplayCursor::from_message(&message)),
+                        });
                     }
                     Err(error) => {
                         warn!("failed to decode message: {error}");
+                        metrics::decode_error_inc(payload::decode_error_kind(&error));
                     }
                 }
             }
@@ -475,53 +1673,347 @@ impl ArgsAction {
         }
         Ok(grpc_shutdown.await??)
     }
+
+    async fn grpc2grpc(
+        config: ConfigGrpc2Grpc,
+        mut shutdown: BoxFuture<'static, ()>,
+    ) -> anyhow::Result<()> {
+        let (x_token_header, x_token_header_overrides) =
+            parse_x_token_headers(&config.x_token_header, &config.x_token_header_overrides)?;
+        let (grpc_tx, grpc_shutdown) = GrpcService::run_with(
+            &config.listen,
+            config.channel_capacity,
+            Duration::from_secs(config.grpc_drain_timeout_seconds),
+            config.per_client_rate_limit_per_second,
+            None,
+        )?;
+
+        Dependency::Grpc.require();
+        let (_request_tx, request_rx) = watch::channel(config.request.to_proto());
+        let mut upstream = GeyserFailover::spawn(
+            GeyserSubscriber {
+                source: GeyserSource::Primary,
+                endpoints: split_endpoints(&config.endpoint),
+                x_token: config.x_token,
+                x_token_header,
+                x_token_header_overrides,
+                request: request_rx,
+                keepalive: config.keepalive,
+                errors: ErrorEvents::default(),
+                client_ping_interval: config.client_ping_interval.map(Duration::from_millis),
+                initial_reconnect_delay: Duration::from_millis(config.initial_reconnect_delay_ms),
+                health_service_name: config.grpc_health_service_name,
+                user_agent: config.grpc_user_agent,
+                health_check_interval: None,
+                slot_gap_reconnect_threshold: None,
+                startup_message_timeout: None,
+                hedged_connect_concurrency: None,
+            },
+            config.secondary_endpoint.as_deref().map(split_endpoints),
+            1,
+            Duration::from_secs(config.failover_threshold_seconds),
+        );
+
+        loop {
+            let (_source, message) = tokio::select! {
+                _ = &mut shutdown => break,
+                message = upstream.recv() => message?,
+            };
+            metrics::recv_inc();
+            // clients receive pings from the service
+            if matches!(
+                message.update_oneof,
+                Some(UpdateOneof::Ping(_) | UpdateOneof::Pong(_))
+            ) {
+                continue;
+            }
+            grpc_tx.broadcast(message);
+        }
+
+        upstream.abort();
+        warn!("shutdown received...");
+        Ok(grpc_shutdown.await??)
+    }
+}
+
+/// Output of deduplicated messages
+#[derive(Clone)]
+enum DedupOutput {
+    Kafka {
+        producer: FutureProducer<metrics::StatsContext>,
+        topic: Arc<String>,
+    },
+    Grpc(GrpcBroadcastSender),
+}
+
+impl DedupOutput {
+    /// Wait for delivery of produced messages, gRPC broadcast is synchronous
+    fn flush(&self) -> anyhow::Result<()> {
+        if let Self::Kafka { producer, .. } = self {
+            tokio::task::block_in_place(|| producer.flush(DEDUP_FLUSH_TIMEOUT))?;
+        }
+        Ok(())
+    }
+}
+
+/// Flush librdkafka internal queue, messages already handed to the producer
+/// are lost otherwise on exit.
+async fn wait_for_kafka_lag_drain<C: ClientContext + 'static>(
+    producer: &FutureProducer<C>,
+    max_wait_seconds: u64,
+) -> anyhow::Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(max_wait_seconds);
+    loop {
+        let flushed = tokio::task::block_in_place(|| producer.flush(Duration::from_secs(1)));
+        let in_flight = producer.in_flight_count();
+        if flushed.is_ok() && in_flight == 0 {
+            info!("kafka producer drained");
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            warn!(
+                "kafka producer drain timed out after {max_wait_seconds}s, in-flight messages: {in_flight}"
+            );
+            return Ok(());
+        }
+        debug!("waiting kafka producer drain, in-flight messages: {in_flight}, flush: {flushed:?}");
+    }
+}
+
+fn split_endpoints(endpoints: &str) -> Vec<String> {
+    endpoints
+        .split(',')
+        .map(|endpoint| endpoint.trim().to_owned())
+        .filter(|endpoint| !endpoint.is_empty())
+        .collect()
 }
 
 #[routes]
 #[get("/health")]
 #[get("/internal/health")]
-async fn health() -> impl Responder {
-    "OK"
+async fn health() -> HttpResponse {
+    if readiness::is_shutting_down() {
+        return HttpResponse::ServiceUnavailable().body("shutting down");
+    }
+    match LeaderRole::get() {
+        LeaderRole::Disabled => HttpResponse::Ok().body("OK"),
+        role => HttpResponse::Ok().body(format!("OK\nrole: {}", role.as_str())),
+    }
+}
+
+/// Readiness with dependency checks, `/health` is a cheap liveness probe
+#[routes]
+#[get("/ready")]
+#[get("/internal/ready")]
+async fn ready() -> HttpResponse {
+    let readiness = Readiness::check();
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+#[routes]
+#[get("/version")]
+async fn version() -> HttpResponse {
+    HttpResponse::Ok().json(&VERSION)
+}
+
+/// Last librdkafka statistics by client name
+#[routes]
+#[get("/stats")]
+async fn stats() -> HttpResponse {
+    HttpResponse::Ok().json(metrics::kafka_stats_last())
+}
+
+/// Loaded config with credentials redacted
+#[routes]
+#[get("/config")]
+async fn config_redacted(config: web::Data<serde_json::Value>) -> HttpResponse {
+    HttpResponse::Ok().json(config.get_ref())
+}
+
+#[routes]
+#[get("/metrics")]
+async fn metrics_default() -> HttpResponse {
+    match prometheus_encode_text(None) {
+        Some(text) => HttpResponse::Ok().body(text),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[routes]
+#[get("/metrics/{scope}")]
+async fn metrics_scoped(scope: web::Path<String>) -> HttpResponse {
+    match prometheus_encode_text(Some(&scope)) {
+        Some(text) => HttpResponse::Ok().body(text),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Assign partitions directly, bypassing consumer group rebalances, offsets
+/// are resumed from committed offsets of `group.id`
+fn assign_partitions(
+    consumer: &StreamConsumer<metrics::StatsContext>,
+    topic: &str,
+    partitions: &[i32],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(!partitions.is_empty(), "`assign` should not be empty");
+    let metadata = consumer
+        .fetch_metadata(Some(topic), Duration::from_secs(10))
+        .context("failed to fetch topic metadata")?;
+    let existing = metadata
+        .topics()
+        .iter()
+        .find(|metadata| metadata.name() == topic)
+        .map(|metadata| {
+            metadata
+                .partitions()
+                .iter()
+                .map(|partition| partition.id())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let mut assignment = TopicPartitionList::new();
+    for partition in partitions {
+        anyhow::ensure!(
+            existing.contains(partition),
+            "partition {partition} of topic {topic} doesn't exist"
+        );
+        assignment.add_partition_offset(topic, *partition, Offset::Stored)?;
+    }
+    consumer.assign(&assignment)?;
+    readiness::set_partitions_assigned(partitions.len());
+    info!("kafka consumer assigned to topic {topic} partitions {partitions:?}");
+    Ok(())
+}
+
+fn set_group_id_suffix(kafka_config: &mut ClientConfig, suffix: ConfigGroupIdSuffix) {
+    if let Some(group_id) = kafka_config.get("group.id") {
+        let group_id = suffix.render(group_id);
+        info!("kafka consumer group: {group_id}");
+        kafka_config.set("group.id", group_id);
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    setup_tracing()?;
-
     // Parse args
     let args = Args::parse();
+    // config is not required for schema export
+    if let Some(ArgsAction::Schema(schema)) = &args.action {
+        return schema.run();
+    }
     // let args = Args {
     //     config: "/home/luke/go/src/github.com/lukeweb3/yellowstone-grpc-kafka/config-kafka.json".to_string(),  // 必须提供 String 类型值
     //     prometheus: Some("127.0.0.1:9090".parse().unwrap()),  // Option<SocketAddr> 类型
     //     action: ArgsAction::Grpc2Kafka,   // 子命令枚举实例化
     // };
-    let config = config_load::<Config>(&args.config).await?;
+    let (mut config, deprecations) = load_with_deprecations::<Config>(&args.config).await?;
+    config.apply_kafka_topic_prefix()?;
+    config.validate()?;
+    setup_tracing(config.log_config())?;
+    for deprecation in deprecations {
+        warn!("deprecated config: {deprecation}");
+    }
+    if let Some(buckets) = config.histogram_buckets.clone() {
+        metrics::set_histogram_buckets(buckets)?;
+    }
 
     // Run prometheus server
-    if let Some(address) = args.prometheus.or(config.prometheus) {
-        prometheus_run_server(address).await?;
+    let const_labels = if config.prometheus_pod_metadata {
+        prometheus_pod_metadata_labels()
+    } else {
+        HashMap::new()
+    };
+    // metrics are served on `http_listen` unless separate server is set
+    let serve_metrics = match args.prometheus.or(config.prometheus) {
+        Some(address) => {
+            prometheus_run_server(address, const_labels.clone()).await?;
+            false
+        }
+        None => {
+            prometheus_init_registry(const_labels.clone())?;
+            true
+        }
+    };
+    if let Some(path) = config.metrics_textfile_path.clone() {
+        prometheus_run_textfile(
+            path,
+            Duration::from_secs(config.metrics_textfile_interval_seconds),
+            const_labels.clone(),
+        )?;
+    }
+    if let Some(address) = &config.statsd_address {
+        run_statsd(
+            address,
+            config.statsd_prefix.clone(),
+            Duration::from_secs(config.statsd_interval_seconds),
+            const_labels,
+        )
+        .await?;
     }
 
     // Create kafka config
+    let action = args.action.unwrap_or_default();
     let mut kafka_config = ClientConfig::new();
+    kafka_config.set("client.id", config.kafka_client_id(action.name()));
     for (key, value) in config.kafka.iter() {
         kafka_config.set(key, value);
     }
+    if let Some(interval_ms) = config.kafka_statistics_interval_ms {
+        kafka_config.set("statistics.interval.ms", interval_ms.to_string());
+    }
+    if let Some(interval_ms) = config.kafka_metadata_refresh_interval_ms {
+        kafka_config.set(
+            "topic.metadata.refresh.interval.ms",
+            interval_ms.to_string(),
+        );
+    }
+    // set only on producer configs, see `ArgsAction::run`
+    KafkaCompression::new(
+        config.kafka_compression_codec.as_deref(),
+        config.kafka_compression_level,
+    )?;
+    validate_kafka_config(&kafka_config)?;
+    if let ArgsAction::ListTopics { filter } = &action {
+        return ArgsAction::list_topics(&kafka_config, filter.as_deref());
+    }
+    if let Some(codec) = &config.kafka_compression_codec {
+        compression::benchmark(codec, config.kafka_compression_level)?;
+    }
 
     // args.action.run(config, kafka_config).await
 
     // Actix-web Server Future
-    let actix_srv = HttpServer::new(|| {
+    let http_listen = config.http_listen();
+    // effective config: migrated, with defaults and topic prefix
+    let mut redacted = serde_json::to_value(&config)?;
+    redact_secrets(&mut redacted);
+    let redacted = web::Data::new(redacted);
+    info!("http server started: {http_listen}");
+    let actix_srv = HttpServer::new(move || {
         App::new()
+            .app_data(web::Data::clone(&redacted))
             // register the macro-routed handler directly
             .service(health)
+            .service(ready)
+            .service(version)
+            .service(stats)
+            .service(config_redacted)
+            .configure(|cfg| {
+                if serve_metrics {
+                    cfg.service(metrics_default).service(metrics_scoped);
+                }
+            })
     })
-    .bind(("127.0.0.1", 8080))?
+    .bind(http_listen)?
     .run();
 
-    let action = args.action.unwrap_or_default();
-    let biz = action.run(config, kafka_config);
+    let biz = action.run_with_watchdog(args.config, config, kafka_config);
     let (srv_res, biz_res) = tokio::join!(actix_srv, biz);
-    srv_res?; biz_res?;
+    srv_res?;
+    biz_res?;
     Ok(())
 }
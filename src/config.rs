@@ -1,6 +1,7 @@
 use {
     anyhow::Context,
-    serde::{de, Deserialize, Serialize},
+    serde::{de, ser, Deserialize, Serialize},
+    serde_json::{Map, Value},
     std::{
         collections::{HashMap, HashSet},
         path::Path,
@@ -19,7 +20,29 @@ use {
     },
 };
 
+/// Upgrades config by one version, returns deprecation warnings
+pub type ConfigMigration = fn(&mut Map<String, Value>) -> Vec<String>;
+
+/// `CONFIG_MIGRATIONS[n]` upgrades version `n + 1` to `n + 2`
+const CONFIG_MIGRATIONS: &[ConfigMigration] = &[];
+
+/// Current config schema version, configs without `version` are v1
+pub const CONFIG_VERSION: u64 = CONFIG_MIGRATIONS.len() as u64 + 1;
+
 pub async fn load<T>(path: impl AsRef<Path> + Copy) -> anyhow::Result<T>
+where
+    T: de::DeserializeOwned,
+{
+    load_with_deprecations(path)
+        .await
+        .map(|(config, _deprecations)| config)
+}
+
+/// Load config and upgrade it to [`CONFIG_VERSION`], returns deprecation
+/// warnings of applied migrations
+pub async fn load_with_deprecations<T>(
+    path: impl AsRef<Path> + Copy,
+) -> anyhow::Result<(T, Vec<String>)>
 where
     T: de::DeserializeOwned,
 {
@@ -27,13 +50,108 @@ where
         .await
         .context("failed to read config from file")?;
 
-    match path.as_ref().extension().and_then(|e| e.to_str()) {
-        Some("yaml") | Some("yml") => {
-            serde_yaml::from_str(&text).context("failed to parse config from file")
+    fn parse<T: de::DeserializeOwned>(text: &str, yaml: bool) -> anyhow::Result<T> {
+        if yaml {
+            serde_yaml::from_str(text).context("failed to parse config from file")
+        } else {
+            json5::from_str(text).context("failed to parse config from file")
         }
-        Some("json") => json5::from_str(&text).context("failed to parse config from file"),
+    }
+    let yaml = match path.as_ref().extension().and_then(|e| e.to_str()) {
+        Some("yaml") | Some("yml") => true,
+        Some("json") => false,
         value => anyhow::bail!("unknown config extension: {value:?}"),
+    };
+
+    #[derive(Deserialize)]
+    struct ConfigVersion {
+        version: Option<u64>,
+    }
+    let ConfigVersion { version } = parse(&text, yaml)?;
+    if version.unwrap_or(1) == CONFIG_VERSION {
+        return Ok((parse(&text, yaml)?, vec![]));
+    }
+    let mut value = parse::<Value>(&text, yaml)?;
+    let deprecations = migrate(&mut value, CONFIG_MIGRATIONS)?;
+    let config = serde_json::from_value(value).context("failed to parse migrated config")?;
+    Ok((config, deprecations))
+}
+
+/// Config keys with these parts are replaced by [`redact_secrets`]
+const SECRET_KEY_PARTS: &[&str] = &[
+    "password",
+    "secret",
+    "token",
+    "jaas",
+    "oauthbearer.config",
+    "ssl.key.pem",
+];
+
+/// Replace values of keys that look like credentials, e.g. `x_token` or
+/// `sasl.password`, header names are kept
+pub fn redact_secrets(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if !key.contains("header") && SECRET_KEY_PARTS.iter().any(|part| key.contains(part))
+                {
+                    if !value.is_null() {
+                        *value = Value::String("<redacted>".to_owned());
+                    }
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// Apply `migrations` from `version` of the config (v1 if not set) up to
+/// the last one
+fn migrate(value: &mut Value, migrations: &[ConfigMigration]) -> anyhow::Result<Vec<String>> {
+    let current = migrations.len() as u64 + 1;
+    let Value::Object(config) = value else {
+        anyhow::bail!("config should be an object");
+    };
+    let version = match config.get("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| anyhow::anyhow!("config `version` should be a number"))?,
+        None => 1,
+    };
+    anyhow::ensure!(
+        (1..=current).contains(&version),
+        "unsupported config version {version}, supported versions are 1-{current}"
+    );
+
+    let mut deprecations = vec![];
+    for (idx, migration) in migrations.iter().enumerate().skip(version as usize - 1) {
+        deprecations.extend(
+            migration(config)
+                .into_iter()
+                .map(|warning| format!("v{} -> v{}: {warning}", idx + 1, idx + 2)),
+        );
     }
+    config.insert("version".to_owned(), current.into());
+    Ok(deprecations)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Include file and line of the log event
+    pub include_location: bool,
 }
 
 pub trait GrpcRequestToProto<T> {
@@ -285,9 +403,83 @@ where
     Ok(Duration::from_millis(ms as u64))
 }
 
+/// Counterpart of [`deserialize_duration_ms_str`]
+pub fn serialize_duration_ms<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: ser::Serializer,
+{
+    serializer.serialize_u128(duration.as_millis())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::ConfigGrpcRequestAccountsFilter;
+    use {
+        super::{migrate, redact_secrets, ConfigGrpcRequestAccountsFilter, ConfigMigration},
+        serde_json::{json, Map, Value},
+    };
+
+    #[test]
+    fn config_redact_secrets() {
+        let mut config = json!({
+            "kafka": {
+                "bootstrap.servers": "localhost:9092",
+                "sasl.password": "pass",
+                "ssl.key.password": "pass",
+            },
+            "grpc2kafka": {
+                "endpoint": "http://127.0.0.1:10000",
+                "x_token": "token",
+                "x_token_header": "x-token",
+                "secondary_x_token": null,
+                "sinks": [{"api_secret": "secret"}],
+            },
+        });
+        redact_secrets(&mut config);
+        assert_eq!(
+            config,
+            json!({
+                "kafka": {
+                    "bootstrap.servers": "localhost:9092",
+                    "sasl.password": "<redacted>",
+                    "ssl.key.password": "<redacted>",
+                },
+                "grpc2kafka": {
+                    "endpoint": "http://127.0.0.1:10000",
+                    "x_token": "<redacted>",
+                    "x_token_header": "x-token",
+                    "secondary_x_token": null,
+                    "sinks": [{"api_secret": "<redacted>"}],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn config_migration() {
+        fn rename(config: &mut Map<String, Value>) -> Vec<String> {
+            match config.remove("old") {
+                Some(value) => {
+                    config.insert("new".to_owned(), value);
+                    vec!["`old` is renamed to `new`".to_owned()]
+                }
+                None => vec![],
+            }
+        }
+        let migrations: &[ConfigMigration] = &[rename];
+
+        let mut value = json!({"old": 1});
+        let deprecations = migrate(&mut value, migrations).unwrap();
+        assert_eq!(value, json!({"new": 1, "version": 2}));
+        assert_eq!(deprecations, ["v1 -> v2: `old` is renamed to `new`"]);
+
+        let mut value = json!({"old": 1, "version": 2});
+        assert!(migrate(&mut value, migrations).unwrap().is_empty());
+        assert_eq!(value, json!({"old": 1, "version": 2}));
+
+        assert!(migrate(&mut json!({"version": 0}), migrations).is_err());
+        assert!(migrate(&mut json!({"version": 3}), migrations).is_err());
+        assert!(migrate(&mut json!({"version": "1"}), migrations).is_err());
+    }
 
     #[test]
     fn grpc_config_accounts_filter_memcmp() {
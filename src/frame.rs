@@ -0,0 +1,593 @@
+use {
+    serde::Deserialize,
+    std::{
+        io::{self, BufRead, BufReader, Read, Write},
+        path::Path,
+    },
+};
+
+/// Max size of a frame, longer lengths in files are rejected as corrupted
+pub const MAX_FRAME_SIZE: usize = 256 * 1024 * 1024;
+
+/// Compression of files written by the file sink, frames are written to the
+/// compressed stream
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileCompression {
+    #[default]
+    None,
+    #[cfg(feature = "lz4_flex")]
+    Lz4Frame,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl FileCompression {
+    /// File name suffix, without dot
+    pub const fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            #[cfg(feature = "lz4_flex")]
+            Self::Lz4Frame => Some("lz4"),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Some("zst"),
+        }
+    }
+
+    /// Detect compression by file extension, used on replay
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            #[cfg(feature = "lz4_flex")]
+            Some("lz4") => Self::Lz4Frame,
+            #[cfg(feature = "zstd")]
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// Compressed stream is finished on drop
+    pub fn writer<'a>(self, inner: impl Write + 'a) -> io::Result<Box<dyn Write + 'a>> {
+        Ok(match self {
+            Self::None => Box::new(inner),
+            #[cfg(feature = "lz4_flex")]
+            Self::Lz4Frame => Box::new(lz4_flex::frame::FrameEncoder::new(inner).auto_finish()),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::new(zstd::stream::Encoder::new(inner, 0)?.auto_finish()),
+        })
+    }
+
+    pub fn reader<'a>(self, inner: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::None => Box::new(inner),
+            #[cfg(feature = "lz4_flex")]
+            Self::Lz4Frame => Box::new(lz4_flex::frame::FrameDecoder::new(inner)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::new(zstd::stream::Decoder::new(inner)?),
+        })
+    }
+}
+
+/// Framing of messages in files written by the file sink
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileFrameFormat {
+    /// 4-byte big-endian length followed by payload
+    #[default]
+    LengthPrefixed4,
+    /// 8-byte big-endian length followed by payload
+    LengthPrefixed8,
+    /// One JSON payload per line
+    NewlineDelimitedJson,
+    /// Avro Object Container File, every payload is a record with a single
+    /// `bytes` field
+    #[cfg(feature = "avro")]
+    Avro,
+}
+
+pub trait FrameWriter {
+    fn write_frame(&mut self, payload: &[u8]) -> io::Result<()>;
+
+    /// Write buffered frames and trailers, should be called once at the end
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+pub trait FrameReader {
+    /// Returns `None` at the end of the input
+    fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+impl FileFrameFormat {
+    pub fn writer<'a>(self, inner: impl Write + 'a) -> io::Result<Box<dyn FrameWriter + 'a>> {
+        Ok(match self {
+            Self::LengthPrefixed4 => Box::new(LengthPrefixedWriter::<_, 4> { inner }),
+            Self::LengthPrefixed8 => Box::new(LengthPrefixedWriter::<_, 8> { inner }),
+            Self::NewlineDelimitedJson => Box::new(NewlineDelimitedWriter { inner }),
+            #[cfg(feature = "avro")]
+            Self::Avro => Box::new(avro::AvroWriter::new(inner)?),
+        })
+    }
+
+    pub fn reader<'a>(self, inner: impl Read + 'a) -> io::Result<Box<dyn FrameReader + 'a>> {
+        Ok(match self {
+            Self::LengthPrefixed4 => Box::new(LengthPrefixedReader::<_, 4> { inner }),
+            Self::LengthPrefixed8 => Box::new(LengthPrefixedReader::<_, 8> { inner }),
+            Self::NewlineDelimitedJson => Box::new(NewlineDelimitedReader {
+                inner: BufReader::new(inner),
+            }),
+            #[cfg(feature = "avro")]
+            Self::Avro => Box::new(avro::AvroReader::new(inner)?),
+        })
+    }
+}
+
+struct LengthPrefixedWriter<W, const N: usize> {
+    inner: W,
+}
+
+impl<W: Write, const N: usize> FrameWriter for LengthPrefixedWriter<W, N> {
+    fn write_frame(&mut self, payload: &[u8]) -> io::Result<()> {
+        let len = (payload.len() as u64).to_be_bytes();
+        if len[..8 - N].iter().any(|byte| *byte != 0) || payload.len() > MAX_FRAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "payload of {} bytes is too large for {N}-byte length",
+                    payload.len()
+                ),
+            ));
+        }
+        self.inner.write_all(&len[8 - N..])?;
+        self.inner.write_all(payload)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct LengthPrefixedReader<R, const N: usize> {
+    inner: R,
+}
+
+impl<R: Read, const N: usize> FrameReader for LengthPrefixedReader<R, N> {
+    fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut len = [0u8; 8];
+        if !read_exact_or_eof(&mut self.inner, &mut len[8 - N..])? {
+            return Ok(None);
+        }
+        read_payload(&mut self.inner, u64::from_be_bytes(len)).map(Some)
+    }
+}
+
+/// Read `len` bytes, memory is allocated as data is read, so a corrupted
+/// length fails on EOF instead of allocation
+fn read_payload(reader: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    if len > MAX_FRAME_SIZE as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {len} bytes exceeds {MAX_FRAME_SIZE} bytes"),
+        ));
+    }
+    let mut payload = Vec::new();
+    reader.take(len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(payload)
+}
+
+/// Returns `false` on EOF before the first byte
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(true)
+}
+
+struct NewlineDelimitedWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> FrameWriter for NewlineDelimitedWriter<W> {
+    fn write_frame(&mut self, payload: &[u8]) -> io::Result<()> {
+        if payload.contains(&b'\n') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "payload contains newline",
+            ));
+        }
+        self.inner.write_all(payload)?;
+        self.inner.write_all(b"\n")
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct NewlineDelimitedReader<R> {
+    inner: BufReader<R>,
+}
+
+impl<R: Read> FrameReader for NewlineDelimitedReader<R> {
+    fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut payload = Vec::new();
+        if self.inner.read_until(b'\n', &mut payload)? == 0 {
+            return Ok(None);
+        }
+        if payload.last() == Some(&b'\n') {
+            payload.pop();
+        }
+        Ok(Some(payload))
+    }
+}
+
+#[cfg(feature = "avro")]
+mod avro {
+    use {
+        super::{read_exact_or_eof, read_payload, FrameReader, FrameWriter, MAX_FRAME_SIZE},
+        std::{
+            collections::{hash_map::RandomState, VecDeque},
+            hash::{BuildHasher, Hasher},
+            io::{self, Read, Write},
+        },
+    };
+
+    const MAGIC: &[u8; 4] = b"Obj\x01";
+    const SCHEMA: &str =
+        r#"{"type":"record","name":"Frame","fields":[{"name":"payload","type":"bytes"}]}"#;
+    const BLOCK_RECORDS: usize = 1_000;
+
+    fn write_long(out: &mut impl Write, value: i64) -> io::Result<()> {
+        let mut value = ((value << 1) ^ (value >> 63)) as u64;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                return out.write_all(&[byte]);
+            }
+            out.write_all(&[byte | 0x80])?;
+        }
+    }
+
+    fn write_bytes(out: &mut impl Write, value: &[u8]) -> io::Result<()> {
+        write_long(out, value.len() as i64)?;
+        out.write_all(value)
+    }
+
+    /// Returns `None` on EOF before the first byte
+    fn read_long_or_eof(reader: &mut impl Read) -> io::Result<Option<i64>> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let mut byte = [0u8];
+            if !read_exact_or_eof(reader, &mut byte)? {
+                return if shift == 0 {
+                    Ok(None)
+                } else {
+                    Err(io::ErrorKind::UnexpectedEof.into())
+                };
+            }
+            if shift >= 64 {
+                return Err(invalid_data("too long varint"));
+            }
+            value |= ((byte[0] & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                return Ok(Some((value >> 1) as i64 ^ -((value & 1) as i64)));
+            }
+        }
+    }
+
+    fn read_long(reader: &mut impl Read) -> io::Result<i64> {
+        read_long_or_eof(reader)?.ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+
+    fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+        let len = u64::try_from(read_long(reader)?).map_err(|_| invalid_data("negative length"))?;
+        read_payload(reader, len)
+    }
+
+    fn invalid_data(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+    }
+
+    pub struct AvroWriter<W: Write> {
+        inner: W,
+        sync: [u8; 16],
+        block: Vec<u8>,
+        records: usize,
+    }
+
+    impl<W: Write> AvroWriter<W> {
+        pub fn new(mut inner: W) -> io::Result<Self> {
+            let mut sync = [0u8; 16];
+            for chunk in sync.chunks_mut(8) {
+                let value = RandomState::new().build_hasher().finish();
+                chunk.copy_from_slice(&value.to_le_bytes());
+            }
+
+            inner.write_all(MAGIC)?;
+            write_long(&mut inner, 2)?;
+            write_bytes(&mut inner, b"avro.schema")?;
+            write_bytes(&mut inner, SCHEMA.as_bytes())?;
+            write_bytes(&mut inner, b"avro.codec")?;
+            write_bytes(&mut inner, b"null")?;
+            write_long(&mut inner, 0)?;
+            inner.write_all(&sync)?;
+            Ok(Self {
+                inner,
+                sync,
+                block: Vec::new(),
+                records: 0,
+            })
+        }
+
+        fn write_block(&mut self) -> io::Result<()> {
+            if self.records > 0 {
+                write_long(&mut self.inner, self.records as i64)?;
+                write_bytes(&mut self.inner, &self.block)?;
+                self.inner.write_all(&self.sync)?;
+                self.block.clear();
+                self.records = 0;
+            }
+            Ok(())
+        }
+    }
+
+    impl<W: Write> FrameWriter for AvroWriter<W> {
+        fn write_frame(&mut self, payload: &[u8]) -> io::Result<()> {
+            // block is read back as a single frame, 10 bytes of the max varint
+            if payload.len() + 10 > MAX_FRAME_SIZE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("payload of {} bytes is too large", payload.len()),
+                ));
+            }
+            if self.block.len() + payload.len() + 10 > MAX_FRAME_SIZE {
+                self.write_block()?;
+            }
+            write_bytes(&mut self.block, payload)?;
+            self.records += 1;
+            if self.records >= BLOCK_RECORDS {
+                self.write_block()?;
+            }
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.write_block()?;
+            self.inner.flush()
+        }
+    }
+
+    pub struct AvroReader<R: Read> {
+        inner: R,
+        sync: [u8; 16],
+        block: VecDeque<Vec<u8>>,
+    }
+
+    impl<R: Read> AvroReader<R> {
+        pub fn new(mut inner: R) -> io::Result<Self> {
+            let mut magic = [0u8; 4];
+            inner.read_exact(&mut magic)?;
+            if &magic != MAGIC {
+                return Err(invalid_data("invalid avro magic"));
+            }
+
+            let mut schema = None;
+            let mut codec = None;
+            loop {
+                let count = read_long(&mut inner)?;
+                if count == 0 {
+                    break;
+                }
+                if count < 0 {
+                    // negative count is followed by block size in bytes
+                    read_long(&mut inner)?;
+                }
+                for _ in 0..count.unsigned_abs() {
+                    let key = read_bytes(&mut inner)?;
+                    let value = read_bytes(&mut inner)?;
+                    match key.as_slice() {
+                        b"avro.schema" => schema = Some(value),
+                        b"avro.codec" => codec = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+            if schema.as_deref() != Some(SCHEMA.as_bytes()) {
+                return Err(invalid_data("unsupported avro schema"));
+            }
+            if !matches!(codec.as_deref(), None | Some(b"null")) {
+                return Err(invalid_data("unsupported avro codec"));
+            }
+
+            let mut sync = [0u8; 16];
+            inner.read_exact(&mut sync)?;
+            Ok(Self {
+                inner,
+                sync,
+                block: VecDeque::new(),
+            })
+        }
+    }
+
+    impl<R: Read> FrameReader for AvroReader<R> {
+        fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+            while self.block.is_empty() {
+                let Some(count) = read_long_or_eof(&mut self.inner)? else {
+                    return Ok(None);
+                };
+                let block = read_bytes(&mut self.inner)?;
+                let mut sync = [0u8; 16];
+                self.inner.read_exact(&mut sync)?;
+                if sync != self.sync {
+                    return Err(invalid_data("invalid avro sync marker"));
+                }
+
+                let mut block = block.as_slice();
+                for _ in 0..count {
+                    self.block.push_back(read_bytes(&mut block)?);
+                }
+            }
+            Ok(self.block.pop_front())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "zstd")]
+    use {super::FileCompression, std::path::Path};
+    use {
+        super::{FileFrameFormat, MAX_FRAME_SIZE},
+        std::io,
+    };
+
+    #[test]
+    fn frame_round_trip() {
+        let formats = [
+            FileFrameFormat::LengthPrefixed4,
+            FileFrameFormat::LengthPrefixed8,
+            FileFrameFormat::NewlineDelimitedJson,
+            #[cfg(feature = "avro")]
+            FileFrameFormat::Avro,
+        ];
+
+        let payloads = (0..2_500)
+            .map(|idx| format!(r#"{{"slot":{idx},"data":"{}"}}"#, "x".repeat(idx % 300)))
+            .chain([String::new()])
+            .collect::<Vec<_>>();
+        for format in formats {
+            let mut file = Vec::new();
+            let mut writer = format.writer(&mut file).unwrap();
+            for payload in payloads.iter() {
+                writer.write_frame(payload.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+            drop(writer);
+
+            let mut reader = format.reader(file.as_slice()).unwrap();
+            for payload in payloads.iter() {
+                let frame = reader.read_frame().unwrap();
+                assert_eq!(frame.as_deref(), Some(payload.as_bytes()), "{format:?}");
+            }
+            assert_eq!(reader.read_frame().unwrap(), None, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn frame_corrupted_length() {
+        let read = |format: FileFrameFormat, file: &[u8]| {
+            format
+                .reader(file)
+                .unwrap()
+                .read_frame()
+                .unwrap_err()
+                .kind()
+        };
+
+        // length is truncated
+        assert_eq!(
+            read(FileFrameFormat::LengthPrefixed8, &[0, 0, 0]),
+            io::ErrorKind::UnexpectedEof
+        );
+        // payload is truncated
+        assert_eq!(
+            read(FileFrameFormat::LengthPrefixed4, &[0, 0, 0, 10, 1, 2, 3]),
+            io::ErrorKind::UnexpectedEof
+        );
+        // length above the max frame size is not allocated
+        assert_eq!(
+            read(FileFrameFormat::LengthPrefixed4, &u32::MAX.to_be_bytes()),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            read(FileFrameFormat::LengthPrefixed8, &u64::MAX.to_be_bytes()),
+            io::ErrorKind::InvalidData
+        );
+
+        let mut file = Vec::new();
+        let mut writer = FileFrameFormat::LengthPrefixed8.writer(&mut file).unwrap();
+        let error = writer
+            .write_frame(&vec![0; MAX_FRAME_SIZE + 1])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "avro")]
+    #[test]
+    fn avro_corrupted_length() {
+        let mut file = Vec::new();
+        let mut writer = FileFrameFormat::Avro.writer(&mut file).unwrap();
+        writer.write_frame(b"payload").unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        // header is followed by block: records count, block length, ...
+        let header_len = file.len() - (1 + 1 + 1 + b"payload".len() + 16);
+
+        // block length of i64::MAX in zigzag varint
+        let mut corrupted = file[..header_len].to_vec();
+        corrupted.extend_from_slice(&[2]);
+        corrupted.extend_from_slice(&[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        let mut reader = FileFrameFormat::Avro.reader(corrupted.as_slice()).unwrap();
+        assert_eq!(
+            reader.read_frame().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // block is truncated
+        let mut reader = FileFrameFormat::Avro
+            .reader(&file[..file.len() - 20])
+            .unwrap();
+        assert_eq!(
+            reader.read_frame().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[cfg(all(feature = "lz4_flex", feature = "zstd"))]
+    #[test]
+    fn compressed_round_trip() {
+        for (path, expected) in [
+            ("capture.bin.lz4", FileCompression::Lz4Frame),
+            ("capture.bin.zst", FileCompression::Zstd),
+        ] {
+            let compression = FileCompression::from_path(Path::new(path));
+            assert_eq!(compression, expected);
+            compressed_round_trip_with(compression);
+        }
+    }
+
+    #[cfg(all(feature = "lz4_flex", feature = "zstd"))]
+    fn compressed_round_trip_with(compression: FileCompression) {
+        let payloads = (0..1_000)
+            .map(|idx| format!("payload #{idx}").into_bytes())
+            .collect::<Vec<_>>();
+        let mut file = Vec::new();
+        {
+            let compressed = compression.writer(&mut file).unwrap();
+            let mut writer = FileFrameFormat::LengthPrefixed4.writer(compressed).unwrap();
+            for payload in payloads.iter() {
+                writer.write_frame(payload).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        assert!(file.len() < payloads.iter().map(|payload| payload.len() + 4).sum());
+
+        let compressed = compression.reader(file.as_slice()).unwrap();
+        let mut reader = FileFrameFormat::LengthPrefixed4.reader(compressed).unwrap();
+        for payload in payloads.iter() {
+            assert_eq!(reader.read_frame().unwrap().as_ref(), Some(payload));
+        }
+        assert_eq!(reader.read_frame().unwrap(), None);
+    }
+}
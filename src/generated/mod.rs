@@ -2,7 +2,9 @@
 
 pub mod geyser {
     #![allow(clippy::clone_on_ref_ptr)]
+    #![allow(clippy::enum_variant_names)]
     #![allow(clippy::missing_const_for_fn)]
+    #![allow(clippy::trivially_copy_pass_by_ref)]
 
     #[cfg(feature = "tonic")]
     include!(concat!(env!("OUT_DIR"), "/geyser.rs"));
@@ -12,6 +14,7 @@ pub mod geyser {
 
 pub mod solana {
     #![allow(clippy::missing_const_for_fn)]
+    #![allow(clippy::trivially_copy_pass_by_ref)]
 
     pub mod storage {
         pub mod confirmed_block {
@@ -29,7 +32,10 @@ pub mod solana {
     }
 }
 
+/// Encoded `FileDescriptorSet` of the protos the binary was built against
+pub const FILE_DESCRIPTOR_SET: &[u8] =
+    include_bytes!(concat!(env!("OUT_DIR"), "/geyser_descriptor.bin"));
+
 pub mod prelude {
     pub use super::geyser::*;
 }
-
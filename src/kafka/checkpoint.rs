@@ -0,0 +1,126 @@
+use {
+    serde::{Deserialize, Serialize},
+    std::{
+        io,
+        path::{Path, PathBuf},
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
+    tokio::fs,
+    tracing::warn,
+};
+
+/// Last message acknowledged by the broker
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub slot: u64,
+    pub key: String,
+    /// Unix timestamp in milliseconds
+    pub timestamp: u64,
+}
+
+impl Checkpoint {
+    pub fn new(slot: u64, key: String) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Self {
+            slot,
+            key,
+            timestamp,
+        }
+    }
+
+    /// Read checkpoint, `None` if file doesn't exist
+    pub async fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        match fs::read(path).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Write to temporary file and rename, readers never see partial file
+    pub async fn store(&self, path: &Path) -> anyhow::Result<()> {
+        let mut tmp_path = path.to_path_buf().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, serde_json::to_vec(self)?).await?;
+        fs::rename(&tmp_path, path).await?;
+        Ok(())
+    }
+}
+
+/// Writes latest checkpoint every `every_n_messages` or `interval`, whichever
+/// comes first
+#[derive(Debug)]
+pub struct CheckpointWriter {
+    path: PathBuf,
+    every_n_messages: u64,
+    interval: Duration,
+    latest: Option<Checkpoint>,
+    updates: u64,
+    written_at: Instant,
+}
+
+impl CheckpointWriter {
+    pub fn new(path: PathBuf, every_n_messages: u64, interval: Duration) -> Self {
+        Self {
+            path,
+            every_n_messages,
+            interval,
+            latest: None,
+            updates: 0,
+            written_at: Instant::now(),
+        }
+    }
+
+    pub async fn update(&mut self, slot: u64, key: &str) {
+        self.latest = Some(Checkpoint::new(slot, key.to_owned()));
+        self.updates += 1;
+        if self.updates >= self.every_n_messages || self.written_at.elapsed() >= self.interval {
+            self.flush().await;
+        }
+    }
+
+    /// Write latest checkpoint if it wasn't written yet, errors are logged
+    pub async fn flush(&mut self) {
+        if self.updates == 0 {
+            return;
+        }
+        if let Some(checkpoint) = &self.latest {
+            if let Err(error) = checkpoint.store(&self.path).await {
+                warn!("failed to write checkpoint to {:?}: {error}", self.path);
+            }
+        }
+        self.updates = 0;
+        self.written_at = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{Checkpoint, CheckpointWriter},
+        std::time::Duration,
+    };
+
+    #[tokio::test]
+    async fn checkpoint_writer() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+        assert_eq!(Checkpoint::load(&path).await.unwrap(), None);
+
+        let mut writer = CheckpointWriter::new(path.clone(), 2, Duration::from_secs(3600));
+        writer.update(10, "10_a").await;
+        assert_eq!(Checkpoint::load(&path).await.unwrap(), None);
+        writer.update(11, "11_b").await;
+        let checkpoint = Checkpoint::load(&path).await.unwrap().unwrap();
+        assert_eq!((checkpoint.slot, checkpoint.key.as_str()), (11, "11_b"));
+
+        writer.update(12, "12_c").await;
+        writer.flush().await;
+        let checkpoint = Checkpoint::load(&path).await.unwrap().unwrap();
+        assert_eq!((checkpoint.slot, checkpoint.key.as_str()), (12, "12_c"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
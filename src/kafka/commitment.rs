@@ -0,0 +1,141 @@
+use {
+    super::metrics,
+    std::{
+        collections::HashMap,
+        sync::atomic::{AtomicU64, Ordering},
+    },
+    yellowstone_grpc_proto::prelude::SlotStatus,
+};
+
+/// Highest slots seen with `processed`, `confirmed` and `finalized` status
+#[derive(Debug, Default)]
+pub struct SlotCommitmentTracker {
+    processed: AtomicU64,
+    confirmed: AtomicU64,
+    finalized: AtomicU64,
+}
+
+impl SlotCommitmentTracker {
+    /// Update watermark from `Slot` update status, other statuses are ignored
+    pub fn update(&self, slot: u64, status: i32) {
+        let (watermark, commitment) = match SlotStatus::try_from(status) {
+            Ok(SlotStatus::SlotProcessed) => (&self.processed, "processed"),
+            Ok(SlotStatus::SlotConfirmed) => (&self.confirmed, "confirmed"),
+            Ok(SlotStatus::SlotFinalized) => (&self.finalized, "finalized"),
+            _ => return,
+        };
+        if watermark.fetch_max(slot, Ordering::Relaxed) < slot {
+            metrics::slot_commitment_watermark_set(commitment, slot);
+        }
+    }
+
+    pub fn is_finalized(&self, slot: u64) -> bool {
+        slot <= self.finalized.load(Ordering::Relaxed)
+    }
+
+    pub fn is_confirmed(&self, slot: u64) -> bool {
+        slot <= self.confirmed.load(Ordering::Relaxed)
+    }
+
+    /// Commitment of the slot by current watermarks
+    pub fn derived_commitment(&self, slot: u64) -> &'static str {
+        if self.is_finalized(slot) {
+            "finalized"
+        } else if self.is_confirmed(slot) {
+            "confirmed"
+        } else {
+            "processed"
+        }
+    }
+}
+
+const fn commitment_rank(commitment: &str) -> u8 {
+    match commitment.as_bytes() {
+        b"finalized" => 2,
+        b"confirmed" => 1,
+        _ => 0,
+    }
+}
+
+/// Highest produced commitment by signature, signatures are evicted
+/// `window_slots` after their slot
+#[derive(Debug)]
+pub struct CommitmentUpgradeFilter {
+    window_slots: u64,
+    max_slot: u64,
+    signatures: HashMap<Vec<u8>, (u64, u8)>,
+}
+
+impl CommitmentUpgradeFilter {
+    pub fn new(window_slots: u64) -> Self {
+        Self {
+            window_slots,
+            max_slot: 0,
+            signatures: HashMap::new(),
+        }
+    }
+
+    /// Returns `true` if the signature was not produced yet or was produced
+    /// with lower commitment
+    pub fn allow(&mut self, signature: &[u8], slot: u64, commitment: &str) -> bool {
+        if slot > self.max_slot {
+            self.max_slot = slot;
+            let min_slot = slot.saturating_sub(self.window_slots);
+            self.signatures
+                .retain(|_signature, (slot, _rank)| *slot >= min_slot);
+        }
+
+        let rank = commitment_rank(commitment);
+        match self.signatures.get_mut(signature) {
+            Some((_slot, produced)) if *produced >= rank => false,
+            Some((_slot, produced)) => {
+                *produced = rank;
+                true
+            }
+            None => {
+                self.signatures.insert(signature.to_vec(), (slot, rank));
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{CommitmentUpgradeFilter, SlotCommitmentTracker},
+        yellowstone_grpc_proto::prelude::SlotStatus,
+    };
+
+    #[test]
+    fn commitment_upgrade() {
+        let mut filter = CommitmentUpgradeFilter::new(10);
+        assert!(filter.allow(b"tx1", 100, "processed"));
+        assert!(!filter.allow(b"tx1", 100, "processed"));
+        assert!(filter.allow(b"tx1", 100, "confirmed"));
+        assert!(!filter.allow(b"tx1", 100, "processed"));
+        assert!(filter.allow(b"tx1", 100, "finalized"));
+        assert!(!filter.allow(b"tx1", 100, "finalized"));
+        assert!(filter.allow(b"tx2", 105, "confirmed"));
+
+        // `tx1` is evicted
+        assert!(filter.allow(b"tx3", 111, "processed"));
+        assert!(filter.allow(b"tx1", 100, "processed"));
+        assert!(!filter.allow(b"tx2", 105, "processed"));
+    }
+
+    #[test]
+    fn derived_commitment() {
+        let tracker = SlotCommitmentTracker::default();
+        assert_eq!(tracker.derived_commitment(100), "processed");
+
+        tracker.update(100, SlotStatus::SlotConfirmed as i32);
+        tracker.update(98, SlotStatus::SlotFinalized as i32);
+        tracker.update(90, SlotStatus::SlotConfirmed as i32);
+        assert_eq!(tracker.derived_commitment(101), "processed");
+        assert_eq!(tracker.derived_commitment(100), "confirmed");
+        assert_eq!(tracker.derived_commitment(99), "confirmed");
+        assert_eq!(tracker.derived_commitment(98), "finalized");
+        assert!(tracker.is_finalized(97));
+    }
+}
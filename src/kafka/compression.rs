@@ -0,0 +1,157 @@
+use {
+    flate2::{write::GzEncoder, Compression as GzLevel},
+    rdkafka::config::ClientConfig,
+    std::{io::Write, time::Instant},
+    tracing::info,
+};
+
+pub(crate) const CODECS: [&str; 5] = ["none", "gzip", "snappy", "lz4", "zstd"];
+
+/// Producer compression, consumers warn about producer-only properties so it
+/// is set only on producer configs
+#[derive(Debug, Default, Clone)]
+pub struct KafkaCompression {
+    codec: Option<String>,
+    level: Option<i32>,
+}
+
+impl KafkaCompression {
+    /// Validate codec and level
+    pub fn new(codec: Option<&str>, level: Option<i32>) -> anyhow::Result<Self> {
+        if let Some(codec) = codec {
+            anyhow::ensure!(
+                CODECS.contains(&codec),
+                "unknown `kafka_compression_codec`: {codec}"
+            );
+        }
+        if let Some(level) = level {
+            let range = match codec {
+                Some("gzip") => 0..=9,
+                Some("lz4") => 0..=12,
+                Some("zstd") => 1..=22,
+                _ => anyhow::bail!(
+                "`kafka_compression_level` requires `kafka_compression_codec` gzip, lz4 or zstd"
+            ),
+            };
+            anyhow::ensure!(
+                range.contains(&level),
+                "`kafka_compression_level` for {} should be in range {range:?}, got {level}",
+                codec.unwrap_or_default()
+            );
+        }
+        Ok(Self {
+            codec: codec.map(str::to_owned),
+            level,
+        })
+    }
+
+    /// Copy of the shared config with `compression.type` and
+    /// `compression.level`
+    pub fn producer_config(&self, kafka_config: &ClientConfig) -> ClientConfig {
+        let mut producer_config = kafka_config.clone();
+        if let Some(codec) = &self.codec {
+            producer_config.set("compression.type", codec);
+        }
+        if let Some(level) = self.level {
+            producer_config.set("compression.level", level.to_string());
+        }
+        producer_config
+    }
+}
+
+/// Compress sample payloads with configured codec and level, log ratio and
+/// latency. lz4 and snappy are measured without level, lz4 uses the default
+/// frame settings of `lz4_flex`.
+pub fn benchmark(codec: &str, level: Option<i32>) -> anyhow::Result<()> {
+    for size in [1_024, 100 * 1_024] {
+        let payload = sample_payload(size);
+        let ts = Instant::now();
+        let compressed = match codec {
+            "gzip" => {
+                let level = level.map(|level| level as u32).unwrap_or(6);
+                let mut encoder = GzEncoder::new(Vec::new(), GzLevel::new(level));
+                encoder.write_all(&payload)?;
+                encoder.finish()?
+            }
+            "lz4" => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(&payload)?;
+                encoder.finish()?
+            }
+            "snappy" => snap::raw::Encoder::new().compress_vec(&payload)?,
+            "zstd" => zstd::encode_all(payload.as_slice(), level.unwrap_or(3))?,
+            _ => {
+                info!("compression benchmark is not supported for {codec}");
+                return Ok(());
+            }
+        };
+        let elapsed = ts.elapsed();
+        info!(
+            "compression benchmark {codec} (level {level:?}): {size} bytes -> {} bytes, ratio {:.2}, {elapsed:?}",
+            compressed.len(),
+            payload.len() as f64 / compressed.len() as f64,
+        );
+    }
+    Ok(())
+}
+
+/// JSON-like payload with random signatures and keys, similar to produced
+/// transactions
+fn sample_payload(size: usize) -> Vec<u8> {
+    let mut rng = fastrand::Rng::with_seed(size as u64);
+    let mut payload = Vec::with_capacity(size + 256);
+    while payload.len() < size {
+        let signature = (0..64).map(|_| rng.u8(..)).collect::<Vec<_>>();
+        let account = (0..32).map(|_| rng.u8(..)).collect::<Vec<_>>();
+        let _ = write!(
+            payload,
+            r#"{{"slot":{},"signature":"{}","account_keys":["{}"],"fee":5000,"log_messages":["Program 11111111111111111111111111111111 invoke [1]","Program 11111111111111111111111111111111 success"]}}"#,
+            rng.u64(300_000_000..400_000_000),
+            const_hex::encode(signature),
+            const_hex::encode(account),
+        );
+    }
+    payload.truncate(size);
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::KafkaCompression, rdkafka::config::ClientConfig};
+
+    #[test]
+    fn compression_producer_config() {
+        let mut kafka_config = ClientConfig::new();
+        kafka_config.set("bootstrap.servers", "localhost:9092");
+        let compression = KafkaCompression::new(Some("zstd"), Some(19)).unwrap();
+        let producer_config = compression.producer_config(&kafka_config);
+        assert_eq!(producer_config.get("compression.type"), Some("zstd"));
+        assert_eq!(producer_config.get("compression.level"), Some("19"));
+        assert_eq!(
+            producer_config.get("bootstrap.servers"),
+            Some("localhost:9092")
+        );
+        // shared config used by consumers is not changed
+        assert_eq!(kafka_config.get("compression.type"), None);
+        assert_eq!(kafka_config.get("compression.level"), None);
+
+        let producer_config = KafkaCompression::default().producer_config(&kafka_config);
+        assert_eq!(producer_config.get("compression.type"), None);
+    }
+
+    #[test]
+    fn compression_validate() {
+        assert!(KafkaCompression::new(Some("snappy"), None).is_ok());
+        assert!(KafkaCompression::new(Some("brotli"), None).is_err());
+        assert!(KafkaCompression::new(Some("gzip"), Some(10)).is_err());
+        assert!(KafkaCompression::new(Some("snappy"), Some(1)).is_err());
+        assert!(KafkaCompression::new(None, Some(1)).is_err());
+    }
+
+    #[test]
+    fn compression_benchmark() {
+        for codec in super::CODECS {
+            super::benchmark(codec, None).unwrap();
+        }
+    }
+}
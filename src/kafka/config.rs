@@ -1,35 +1,457 @@
 use {
-    super::dedup::{KafkaDedup, KafkaDedupMemory},
-    crate::config::{deserialize_usize_str, ConfigGrpcRequest},
-    serde::Deserialize,
-    std::{collections::HashMap, net::SocketAddr},
+    super::{
+        compression::CODECS,
+        dedup::{KafkaDedup, KafkaDedupMemory},
+    },
+    crate::config::{
+        deserialize_duration_ms_str, deserialize_usize_str, serialize_duration_ms,
+        ConfigGrpcRequest, LogConfig, LogFormat,
+    },
+    rdkafka::config::ClientConfig,
+    regex::Regex,
+    serde::{de, Deserialize, Deserializer, Serialize, Serializer},
+    std::{
+        collections::HashMap, env, fmt, net::SocketAddr, path::PathBuf, str::FromStr,
+        time::Duration,
+    },
 };
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Config {
+    /// Config schema version, older versions are upgraded on load, v1 if not
+    /// set
+    pub version: Option<u64>,
+    pub log_format: LogFormat,
+    pub log_include_location: bool,
+    /// Separate metrics server, otherwise metrics are served on `http_listen`
     pub prometheus: Option<SocketAddr>,
+    /// Address of the HTTP server with health, readiness, version, metrics,
+    /// kafka statistics and redacted config, `127.0.0.1:8080` by default
+    pub http_listen: Option<SocketAddr>,
+    /// Add `pod`, `namespace` and `node` labels from `POD_NAME`,
+    /// `POD_NAMESPACE` and `NODE_NAME` env variables to all metrics
+    pub prometheus_pod_metadata: bool,
+    /// Write metrics to this file for node_exporter textfile collector, file
+    /// name should end with `.prom`
+    pub metrics_textfile_path: Option<PathBuf>,
+    #[serde(default = "Config::default_metrics_textfile_interval_seconds")]
+    pub metrics_textfile_interval_seconds: u64,
+    /// Send metrics to StatsD `host:port` in DogStatsD format, can be used
+    /// together with `prometheus`
+    pub statsd_address: Option<String>,
+    /// Prefix for StatsD metric names, `{prefix}.{name}`
+    pub statsd_prefix: Option<String>,
+    #[serde(default = "Config::default_statsd_interval_seconds")]
+    pub statsd_interval_seconds: u64,
+    /// Restart failed action in the process instead of exit, the config
+    /// loaded at startup is reused on restart
+    #[serde(default)]
+    pub auto_restart: bool,
+    /// Exit after this number of failures within `restart_window_seconds`
+    #[serde(default = "Config::default_max_restart_attempts")]
+    pub max_restart_attempts: u32,
+    #[serde(default = "Config::default_restart_backoff_ms")]
+    pub restart_backoff_ms: u64,
+    #[serde(default = "Config::default_restart_window_seconds")]
+    pub restart_window_seconds: u64,
+    /// URL for POST request with shutdown reason before drain on shutdown
+    pub pre_shutdown_webhook: Option<String>,
+    #[serde(default = "Config::default_pre_shutdown_webhook_timeout_ms")]
+    pub pre_shutdown_webhook_timeout_ms: u64,
+    /// Delay before drain on shutdown, `/health` and `/ready` return 503
+    /// during this time so traffic is routed away from the pod
+    #[serde(default = "Config::default_shutdown_grace_period_ms")]
+    pub shutdown_grace_period_ms: u64,
     pub kafka: HashMap<String, String>,
+    /// Prepended to all topic names of all actions, e.g. `prod-`
+    pub kafka_topic_prefix: Option<String>,
+    /// Template for `client.id`, supports `{action}`, `{hostname}` and `{pid}`
+    pub kafka_client_id: Option<String>,
+    /// Sets producer `compression.type`: `none`, `gzip`, `snappy`, `lz4` or `zstd`
+    pub kafka_compression_codec: Option<String>,
+    /// Sets producer `compression.level`: 0-9 for gzip, 0-12 for lz4, 1-22 for zstd
+    pub kafka_compression_level: Option<i32>,
+    /// Sets `statistics.interval.ms`, statistics are exported as metrics and
+    /// logged on `TRACE` level
+    pub kafka_statistics_interval_ms: Option<u64>,
+    /// Sets `topic.metadata.refresh.interval.ms`, partition count used by
+    /// `partition_key_overrides` is refreshed with the same interval
+    pub kafka_metadata_refresh_interval_ms: Option<u64>,
+    /// Retry Kafka producer / consumer creation on startup
+    #[serde(default)]
+    pub kafka_startup_retry: ConfigKafkaStartupRetry,
+    /// Histogram buckets by metric name, see `metrics::DEFAULT_LATENCY_BUCKETS`
+    /// and `metrics::DEFAULT_TTFM_BUCKETS`
+    pub histogram_buckets: Option<HashMap<String, Vec<f64>>>,
     pub dedup: Option<ConfigDedup>,
     pub grpc2kafka: Option<ConfigGrpc2Kafka>,
     pub kafka2grpc: Option<ConfigKafka2Grpc>,
+    pub grpc2grpc: Option<ConfigGrpc2Grpc>,
+}
+
+impl Config {
+    pub const DEFAULT_KAFKA_CLIENT_ID: &'static str = "yellowstone-grpc-kafka-{action}-{hostname}";
+
+    pub fn http_listen(&self) -> SocketAddr {
+        self.http_listen
+            .unwrap_or(SocketAddr::from(([127, 0, 0, 1], 8080)))
+    }
+
+    /// Rendered `client.id` for producers and consumers
+    pub fn kafka_client_id(&self, action: &str) -> String {
+        let hostname = hostname();
+        self.kafka_client_id
+            .as_deref()
+            .unwrap_or(Self::DEFAULT_KAFKA_CLIENT_ID)
+            .replace("{action}", action)
+            .replace("{hostname}", &hostname)
+            .replace("{pid}", &std::process::id().to_string())
+    }
+
+    const fn default_metrics_textfile_interval_seconds() -> u64 {
+        15
+    }
+
+    const fn default_statsd_interval_seconds() -> u64 {
+        10
+    }
+
+    const fn default_pre_shutdown_webhook_timeout_ms() -> u64 {
+        5_000
+    }
+
+    const fn default_shutdown_grace_period_ms() -> u64 {
+        2_000
+    }
+
+    const fn default_max_restart_attempts() -> u32 {
+        5
+    }
+
+    const fn default_restart_backoff_ms() -> u64 {
+        1_000
+    }
+
+    const fn default_restart_window_seconds() -> u64 {
+        300
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.kafka_startup_retry.attempts > 0,
+            "`kafka_startup_retry.attempts` should be greater than 0"
+        );
+        anyhow::ensure!(
+            self.metrics_textfile_path.is_none() || self.metrics_textfile_interval_seconds > 0,
+            "`metrics_textfile_interval_seconds` should be greater than 0"
+        );
+        anyhow::ensure!(
+            self.statsd_address.is_none() || self.statsd_interval_seconds > 0,
+            "`statsd_interval_seconds` should be greater than 0"
+        );
+        if let Some(sharding) = self
+            .grpc2kafka
+            .as_ref()
+            .and_then(|config| config.sharding.as_ref())
+        {
+            anyhow::ensure!(
+                sharding.shard_index < sharding.shard_count,
+                "`sharding.shard_index` should be lower than `sharding.shard_count`"
+            );
+        }
+        // consumers are created from global `kafka` overlaid by section `kafka`
+        let merged_kafka = |kafka: &HashMap<String, String>| {
+            self.kafka
+                .iter()
+                .chain(kafka.iter())
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<HashMap<_, _>>()
+        };
+        if let Some(config) = &self.grpc2kafka {
+            config.validate_jitter_send_delay()?;
+        }
+        if let Some(config) = &self.kafka2grpc {
+            ConfigKafka2Grpc::validate_channel_capacity(config.channel_capacity)?;
+            config
+                .consumer_timeouts
+                .validate(&merged_kafka(&config.kafka))?;
+        }
+        if let Some(config) = &self.grpc2grpc {
+            ConfigKafka2Grpc::validate_channel_capacity(config.channel_capacity)?;
+        }
+        if let Some(config) = &self.dedup {
+            if let Some(grpc_output) = &config.grpc_output {
+                ConfigKafka2Grpc::validate_channel_capacity(grpc_output.channel_capacity)?;
+            }
+            anyhow::ensure!(
+                config.max_in_flight > 0 && config.max_in_flight_per_slot > 0,
+                "`max_in_flight` and `max_in_flight_per_slot` should be greater than 0"
+            );
+            config
+                .consumer_timeouts
+                .validate(&merged_kafka(&config.kafka))?;
+        }
+        Ok(())
+    }
+
+    /// Prepend `kafka_topic_prefix` to topic names, should be called once
+    /// after load. For `kafka2grpc` patterns prefix is escaped and inserted
+    /// after `^`.
+    pub fn apply_kafka_topic_prefix(&mut self) -> anyhow::Result<()> {
+        let Some(prefix) = self.kafka_topic_prefix.as_deref() else {
+            return Ok(());
+        };
+        let apply = |topic: &mut String| {
+            *topic = format!("{prefix}{topic}");
+            validate_topic_name(topic)
+        };
+        if let Some(config) = &mut self.dedup {
+            apply(&mut config.kafka_input)?;
+            if let Some(topic) = &mut config.kafka_output {
+                apply(topic)?;
+            }
+        }
+        if let Some(config) = &mut self.grpc2kafka {
+            apply(&mut config.kafka_topic)?;
+            for topic in [
+                &mut config.oversize_topic,
+                &mut config.failed_send_dlq_topic,
+                &mut config.failed_transaction_topic,
+                &mut config.error_topic,
+                &mut config.heartbeat_topic,
+                &mut config.slot_marker_topic,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                apply(topic)?;
+            }
+            if let Some(dual_write) = &mut config.dual_write {
+                apply(&mut dual_write.json_topic)?;
+            }
+            if let Some(leader_election) = &mut config.leader_election {
+                apply(&mut leader_election.topic)?;
+            }
+        }
+        if let Some(config) = &mut self.kafka2grpc {
+            match config.kafka_topic.strip_prefix('^') {
+                Some(pattern) => {
+                    validate_topic_name(prefix)?;
+                    config.kafka_topic = format!("^{}{pattern}", regex::escape(prefix));
+                }
+                None => apply(&mut config.kafka_topic)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub const fn log_config(&self) -> LogConfig {
+        LogConfig {
+            format: self.log_format,
+            include_location: self.log_include_location,
+        }
+    }
+}
+
+/// Kafka topic name: 1-249 characters `[a-zA-Z0-9._-]`, not `.` or `..`
+fn validate_topic_name(topic: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        (1..=249).contains(&topic.len())
+            && topic != "."
+            && topic != ".."
+            && topic
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-')),
+        "invalid Kafka topic name: {topic:?}"
+    );
+    Ok(())
+}
+
+/// `HOSTNAME` env variable or `/etc/hostname`
+fn hostname() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_owned())
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned())
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ConfigDedup {
     #[serde(default)]
     pub kafka: HashMap<String, String>,
     pub kafka_input: String,
-    pub kafka_output: String,
+    /// Output topic, used unless `grpc_output` is set
+    #[serde(default)]
+    pub kafka_output: Option<String>,
+    /// Broadcast deduplicated messages to gRPC subscribers instead of Kafka,
+    /// requires `input_format: protobuf`
+    #[serde(default)]
+    pub grpc_output: Option<ConfigDedupGrpcOutput>,
+    /// Format of `kafka_input` messages written by grpc2kafka, messages are
+    /// decoded only for `grpc_output`
+    #[serde(default)]
+    pub input_format: Option<ConfigGrpc2KafkaFormat>,
     #[serde(
         default = "ConfigGrpc2Kafka::default_kafka_queue_size",
         deserialize_with = "deserialize_usize_str"
     )]
     pub kafka_queue_size: usize,
     pub backend: ConfigDedupBackend,
+    /// Disable auto-commit, commit consumer offsets every
+    /// `commit_every_n_messages` only after output is flushed
+    #[serde(default)]
+    pub flush_before_offset_commit: bool,
+    #[serde(
+        default = "ConfigDedup::default_commit_every_n_messages",
+        deserialize_with = "deserialize_usize_str"
+    )]
+    pub commit_every_n_messages: usize,
+    /// Skip dedup and output for messages older than this (by Kafka message
+    /// timestamp), offsets are still committed
+    #[serde(default)]
+    pub skip_if_output_lag_exceeds_seconds: Option<u64>,
+    /// Export high watermark, position and lag of every assigned input
+    /// partition with this interval
+    pub watermark_poll_interval_seconds: Option<u64>,
+    /// Use account pubkey as output key for account updates (for compacted
+    /// topics), messages are still deduplicated by content hash
+    #[serde(default)]
+    pub rekey_accounts_by_pubkey: bool,
+    /// Clear dedup state when slot of received message is in the next epoch
+    #[serde(default)]
+    pub dedup_epoch_reset: bool,
+    /// Additional near-duplicate check after exact (SHA-256) dedup
+    #[serde(default)]
+    pub fingerprint_mode: ConfigFingerprintMode,
+    #[serde(default)]
+    pub kafka_group_id_suffix: ConfigGroupIdSuffix,
+    /// Consume these partitions of the input topic directly instead of
+    /// consumer group subscription
+    pub assign: Option<Vec<i32>>,
+    #[serde(default)]
+    pub consumer_timeouts: ConfigConsumerTimeouts,
+    /// Max number of in-flight dedup and send tasks
+    #[serde(
+        default = "ConfigDedup::default_max_in_flight",
+        deserialize_with = "deserialize_usize_str"
+    )]
+    pub max_in_flight: usize,
+    /// Max number of in-flight tasks of one slot
+    #[serde(
+        default = "ConfigDedup::default_max_in_flight",
+        deserialize_with = "deserialize_usize_str"
+    )]
+    pub max_in_flight_per_slot: usize,
+}
+
+impl ConfigDedup {
+    const fn default_commit_every_n_messages() -> usize {
+        1_000
+    }
+
+    const fn default_max_in_flight() -> usize {
+        10_000
+    }
+}
+
+/// Backoff doubles after every failed attempt up to `max_backoff_ms`
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConfigKafkaStartupRetry {
+    /// Total number of attempts, `1` disables retry
+    pub attempts: u32,
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for ConfigKafkaStartupRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            backoff_ms: 1_000,
+            max_backoff_ms: 30_000,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ConfigFingerprintMode {
+    /// Only exact duplicates by key hash
+    #[default]
+    Exact,
+    /// Payloads with SimHash Hamming distance within `bit_diff_threshold`
+    /// in the same slot are duplicates, see `dedup::simhash`. Payloads
+    /// shorter than `min_payload_size` are checked only for exact duplicates,
+    /// SimHash of a few tokens matches unrelated payloads
+    NearDuplicate {
+        shingle_size: usize,
+        bit_diff_threshold: u8,
+        #[serde(default = "ConfigFingerprintMode::default_min_payload_size")]
+        min_payload_size: usize,
+    },
+}
+
+impl ConfigFingerprintMode {
+    const fn default_min_payload_size() -> usize {
+        256
+    }
+}
+
+/// Suffix appended to `group.id`: instances with the same group share
+/// partitions, with unique groups every instance consumes all messages
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigGroupIdSuffix {
+    /// `group.id` is used as is
+    #[default]
+    None,
+    Hostname,
+    /// Random UUID, new on every start
+    Uuid,
+    Pid,
+}
+
+impl ConfigGroupIdSuffix {
+    pub fn render(self, group_id: &str) -> String {
+        match self {
+            Self::None => group_id.to_owned(),
+            Self::Hostname => format!("{group_id}-{}", hostname()),
+            Self::Uuid => {
+                let mut bytes = fastrand::u128(..).to_be_bytes();
+                bytes[6] = (bytes[6] & 0x0f) | 0x40; // version 4
+                bytes[8] = (bytes[8] & 0x3f) | 0x80; // variant 1
+                let hex = const_hex::encode(bytes);
+                format!(
+                    "{group_id}-{}-{}-{}-{}-{}",
+                    &hex[0..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..32]
+                )
+            }
+            Self::Pid => format!("{group_id}-{}", std::process::id()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigDedupGrpcOutput {
+    /// Address or list of addresses, see [`ConfigGrpcListen`]
+    #[serde(deserialize_with = "ConfigGrpcListen::deserialize_list")]
+    pub listen: Vec<ConfigGrpcListen>,
+    #[serde(default = "ConfigKafka2Grpc::channel_capacity_default")]
+    pub channel_capacity: usize,
+    /// Max time to drain client streams on shutdown
+    #[serde(default = "ConfigKafka2Grpc::default_grpc_drain_timeout_seconds")]
+    pub grpc_drain_timeout_seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ConfigDedupBackend {
     Memory,
@@ -43,11 +465,60 @@ impl ConfigDedupBackend {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ConfigGrpc2Kafka {
+    /// Comma-separated list, switched in a round-robin on disconnect
     pub endpoint: String,
     pub x_token: Option<String>,
+    /// Header name for `x_token`, e.g. `authorization`
+    #[serde(default = "ConfigGrpc2Kafka::default_x_token_header")]
+    pub x_token_header: String,
+    /// Header name for `x_token` by endpoint, for endpoints of providers
+    /// with different conventions
+    #[serde(default)]
+    pub x_token_header_overrides: HashMap<String, String>,
+    /// Subscribed only when no messages received from `endpoint` for
+    /// `failover_threshold_seconds`, comma-separated list
+    pub secondary_endpoint: Option<String>,
+    #[serde(default = "ConfigGrpc2Kafka::default_failover_threshold_seconds")]
+    pub failover_threshold_seconds: u64,
+    #[serde(default)]
+    pub keepalive: ConfigGrpcKeepalive,
+    /// Interval in milliseconds for `Ping` requests sent on the subscribe
+    /// stream, disabled if not set
+    pub client_ping_interval: Option<u64>,
+    /// Delay in milliseconds before the first reconnect, later reconnects
+    /// wait 2 seconds
+    #[serde(default = "ConfigGrpc2Kafka::default_initial_reconnect_delay_ms")]
+    pub initial_reconnect_delay_ms: u64,
+    /// Service name for health check before subscribe
+    #[serde(default = "ConfigGrpc2Kafka::default_grpc_health_service_name")]
+    pub grpc_health_service_name: String,
+    /// Check health in background, status changes are only logged
+    pub grpc_health_check_interval_seconds: Option<u64>,
+    /// Reconnect to the next endpoint if `Slot` updates skip more than this
+    /// number of slots, requires slots in the subscription
+    pub slot_gap_reconnect_threshold: Option<u64>,
+    /// Exit with error if no updates received within this time after the
+    /// first successful subscribe, e.g. for canary deploys
+    pub startup_message_timeout_seconds: Option<u64>,
+    /// HTTP/2 `user-agent` header, `yellowstone-grpc-kafka/{version}` by
+    /// default
+    #[serde(default = "ConfigGrpc2Kafka::default_grpc_user_agent")]
+    pub grpc_user_agent: String,
+    /// Number of connections with the same subscription to the endpoint,
+    /// first received copy of every update is used
+    #[serde(default = "ConfigGrpc2Kafka::default_connection_pool_size")]
+    pub connection_pool_size: usize,
+    /// Race connect to up to this number of endpoints at once on
+    /// (re)connect, the first subscribed endpoint is used, serial if not set.
+    /// Endpoints are skipped for 30s after 3 failed attempts in a row
+    pub hedged_connect_concurrency: Option<usize>,
     pub request: ConfigGrpcRequest,
+    /// Re-read `request` from the config file on SIGHUP and send it on the
+    /// established stream
+    #[serde(default)]
+    pub use_streaming_subscribe: bool,
     #[serde(default)]
     pub kafka: HashMap<String, String>,
     pub kafka_topic: String,
@@ -56,26 +527,1031 @@ pub struct ConfigGrpc2Kafka {
         deserialize_with = "deserialize_usize_str"
     )]
     pub kafka_queue_size: usize,
+    /// Max in-flight produces per kind (`account`, `transaction`, etc.),
+    /// caps the share of `kafka_queue_size` one kind can take. Kinds are not
+    /// isolated: while a kind is at its limit the receive loop waits, so
+    /// messages of other kinds wait too
+    #[serde(default)]
+    pub kafka_queue_size_per_kind: HashMap<String, usize>,
+    /// Max time to wait for librdkafka queue drain on shutdown
+    #[serde(default = "ConfigGrpc2Kafka::default_kafka_drain_timeout_seconds")]
+    pub kafka_drain_timeout_seconds: u64,
+    /// Messages with larger payload are routed to `oversize_topic` or skipped
+    /// if it's not set
+    pub max_payload_bytes: Option<usize>,
+    pub oversize_topic: Option<String>,
+    /// Number of retries of messages failed to send before routing them to
+    /// `failed_send_dlq_topic`, send error is fatal if it's not set
+    #[serde(default = "ConfigGrpc2Kafka::default_retry_failed_send_count")]
+    pub retry_failed_send_count: u32,
+    /// Dead letter topic, messages have `x-original-topic`, `x-retry-count`
+    /// and `x-error` headers
+    pub failed_send_dlq_topic: Option<String>,
+    /// Failed transactions (with `meta.err`) are routed to
+    /// `failed_transaction_topic` or skipped if it's not set
+    #[serde(default)]
+    pub skip_failed_transactions: bool,
+    pub failed_transaction_topic: Option<String>,
+    /// After reconnect or failover drop messages with slot lower than the
+    /// last produced slot of the same kind and commitment until the new
+    /// stream catches up, downstream slots may have gaps
+    #[serde(default)]
+    pub enforce_monotonic_slots: bool,
+    /// Produce transaction with the same signature again only if its derived
+    /// commitment is higher, signatures are kept for this number of slots
+    pub commitment_upgrade_window_slots: Option<u64>,
+    /// Hold messages until their slot is confirmed, requires slots in the
+    /// subscription
+    #[serde(default)]
+    pub wait_for_confirmation: bool,
+    /// Messages are sent without confirmation after this time
+    #[serde(default = "ConfigGrpc2Kafka::default_confirmation_timeout_ms")]
+    pub confirmation_timeout_ms: u64,
+    /// Max messages held for confirmation, messages of the oldest slot are
+    /// sent without confirmation when exceeded
+    #[serde(default = "ConfigGrpc2Kafka::default_confirmation_max_pending")]
+    pub confirmation_max_pending: usize,
+    /// Write slot, key and timestamp of the last message acknowledged by
+    /// the broker to this file, every `checkpoint_every_n_messages` or
+    /// `checkpoint_interval_seconds`
+    pub checkpoint_path: Option<PathBuf>,
+    #[serde(default = "ConfigGrpc2Kafka::default_checkpoint_every_n_messages")]
+    pub checkpoint_every_n_messages: u64,
+    #[serde(default = "ConfigGrpc2Kafka::default_checkpoint_interval_seconds")]
+    pub checkpoint_interval_seconds: u64,
+    /// Subscribe from checkpoint slot if `request.from_slot` is not set
+    #[serde(default)]
+    pub checkpoint_resume: bool,
+    /// Sort messages by slot while catching up from `from_slot`, for
+    /// providers which interleave historical and live messages
+    pub backfill_reorder: Option<ConfigBackfillReorder>,
+    /// Decode, produce and reconnect errors are published to this topic as
+    /// JSON
+    pub error_topic: Option<String>,
+    /// Produce received `Ping` / `Pong` updates as JSON to `heartbeat_topic`
+    #[serde(default)]
+    pub produce_heartbeats: bool,
+    pub heartbeat_topic: Option<String>,
+    /// Produce marker when `Slot` update advances observed slot: JSON
+    /// `{"slot": N, "next_slot": M, "timestamp": ms}` with key `N`, where `N`
+    /// is the previous max slot and `M` is the new one
+    pub slot_marker_topic: Option<String>,
+    /// Partition by message kind, e.g. `{"slot": {"fixed": 0}, "block":
+    /// {"template": "{slot}"}}`
+    #[serde(default)]
+    pub partition_key_overrides: HashMap<String, ConfigPartitionKeyOverride>,
+    #[serde(default)]
+    pub format: ConfigGrpc2KafkaFormat,
+    /// Encoding of transaction signatures in JSON payloads
+    #[serde(default)]
+    pub signature_encoding: ConfigSignatureEncoding,
+    /// Hash of the payload in message key `{slot}_{hash}`
+    #[serde(default)]
+    pub payload_hash_algorithm: ConfigPayloadHashAlgorithm,
+    /// Encoding of instruction and return data in JSON transactions
+    #[serde(default)]
+    pub account_data_encoding: ConfigAccountDataEncoding,
+    /// Add `loaded_addresses` with base58 `writable` / `readonly` accounts
+    /// resolved from address table lookups to JSON transactions
+    #[serde(default)]
+    pub include_loaded_addresses: bool,
+    /// Add `decoded_instruction` to JSON instructions of System Program and
+    /// SPL Token, requires `instruction-decoder` feature
+    #[serde(default)]
+    pub decode_instructions: bool,
+    /// With `protobuf` format also produce JSON transactions to another topic,
+    /// transactions are decoded once for both outputs
+    pub dual_write: Option<ConfigDualWrite>,
+    /// Collapse account updates by pubkey before produce
+    pub write_behind_cache: Option<ConfigWriteBehindCache>,
+    /// Drop large account fields before produce, `pubkey`, `lamports`,
+    /// `owner`, `executable` and `rent_epoch` are always kept
+    pub account_projection: Option<ConfigAccountProjection>,
+    #[serde(default)]
+    pub transform_chain: Vec<ConfigTransform>,
+    /// Kafka headers added to every produced message, header name to
+    /// environment variable name, resolved on startup
+    #[serde(default)]
+    pub header_injection_from_env: HashMap<String, String>,
+    /// Script applied to JSON payloads after `transform_chain`, see
+    /// `kafka::script`, requires `script-transform` feature
+    pub script: Option<String>,
+    /// Write messages as Parquet files to object storage instead of Kafka
+    pub parquet: Option<ConfigParquetSink>,
+    /// Produce only these kinds (`account`, `slot`, `transaction`, etc.),
+    /// other kinds are skipped after slot tracking
+    pub allow_types: Option<Vec<String>>,
+    /// Fraction of messages (0.0-1.0) produced per kind (`account`, `slot`,
+    /// `transaction`, etc.), kinds without rate are not sampled
+    #[serde(default)]
+    pub sample_rate: HashMap<String, f64>,
+    /// Seed for the sampling PRNG, random if not set
+    pub sample_seed: Option<u64>,
+    /// Produce only messages of this instance shard, for multiple instances
+    /// with the same subscription
+    pub sharding: Option<ConfigSharding>,
+    /// Skip messages of the slot after this number until the slot is
+    /// confirmed, `Slot` updates are not limited. Counters are dropped 1000
+    /// slots behind the max seen slot if confirmations are missing
+    pub max_messages_per_slot: Option<usize>,
+    /// Random delay up to this value in milliseconds before the first
+    /// produce of every new slot, to spread load from instances subscribed
+    /// to the same stream. Messages of the slot are produced without delay.
+    /// The delay pauses reading of updates, so it is limited to 200ms and
+    /// half of `client_ping_interval`
+    pub jitter_send_delay_ms: Option<u64>,
+    /// Only the elected leader produces messages, standby keeps gRPC
+    /// subscription open and drops received messages
+    pub leader_election: Option<ConfigLeaderElection>,
 }
 
 impl ConfigGrpc2Kafka {
+    pub const JITTER_SEND_DELAY_MS_MAX: u64 = 200;
+
+    /// Jitter is slept in the receive loop, it should stay well below slot
+    /// time and the ping watchdog
+    fn validate_jitter_send_delay(&self) -> anyhow::Result<()> {
+        let Some(jitter_send_delay_ms) = self.jitter_send_delay_ms else {
+            return Ok(());
+        };
+        anyhow::ensure!(
+            jitter_send_delay_ms <= Self::JITTER_SEND_DELAY_MS_MAX,
+            "`jitter_send_delay_ms` should not be greater than {}, got {jitter_send_delay_ms}",
+            Self::JITTER_SEND_DELAY_MS_MAX
+        );
+        if let Some(client_ping_interval) = self.client_ping_interval {
+            anyhow::ensure!(
+                jitter_send_delay_ms <= client_ping_interval / 2,
+                "`jitter_send_delay_ms` ({jitter_send_delay_ms}) should not be greater than half of `client_ping_interval` ({client_ping_interval})"
+            );
+        }
+        Ok(())
+    }
+
+    const fn default_checkpoint_every_n_messages() -> u64 {
+        10_000
+    }
+
+    const fn default_checkpoint_interval_seconds() -> u64 {
+        10
+    }
+
+    /// Only JSON payloads require decoded transactions
+    pub fn decode_transactions(&self) -> bool {
+        self.format == ConfigGrpc2KafkaFormat::Json || self.dual_write.is_some()
+    }
+
+    /// Payloads above `max_payload_bytes` are routed to `oversize_topic`,
+    /// `None` if the payload should be skipped
+    pub const fn payload_topic<'a>(&'a self, topic: &'a String, len: usize) -> Option<&'a String> {
+        match self.max_payload_bytes {
+            Some(max_payload_bytes) if len > max_payload_bytes => self.oversize_topic.as_ref(),
+            _ => Some(topic),
+        }
+    }
+
+    const fn default_retry_failed_send_count() -> u32 {
+        3
+    }
+
+    const fn default_confirmation_timeout_ms() -> u64 {
+        10_000
+    }
+
+    const fn default_confirmation_max_pending() -> usize {
+        100_000
+    }
+
     const fn default_kafka_queue_size() -> usize {
         10_000
     }
+
+    fn default_grpc_health_service_name() -> String {
+        "geyser.Geyser".to_owned()
+    }
+
+    fn default_x_token_header() -> String {
+        "x-token".to_owned()
+    }
+
+    fn default_grpc_user_agent() -> String {
+        format!("yellowstone-grpc-kafka/{}", env!("CARGO_PKG_VERSION"))
+    }
+
+    const fn default_connection_pool_size() -> usize {
+        1
+    }
+
+    const fn default_initial_reconnect_delay_ms() -> u64 {
+        250
+    }
+
+    const fn default_failover_threshold_seconds() -> u64 {
+        30
+    }
+
+    const fn default_kafka_drain_timeout_seconds() -> u64 {
+        30
+    }
+}
+
+/// HTTP/2 keepalive pings, required to keep idle connections open behind
+/// load balancers and NAT
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConfigGrpcKeepalive {
+    /// Ping interval in milliseconds
+    #[serde(
+        deserialize_with = "deserialize_duration_ms_str",
+        serialize_with = "serialize_duration_ms"
+    )]
+    pub http2_keep_alive_interval: Duration,
+    /// Ping ack timeout in milliseconds
+    #[serde(
+        deserialize_with = "deserialize_duration_ms_str",
+        serialize_with = "serialize_duration_ms"
+    )]
+    pub keep_alive_timeout: Duration,
+    pub keep_alive_while_idle: bool,
+}
+
+impl Default for ConfigGrpcKeepalive {
+    fn default() -> Self {
+        Self {
+            http2_keep_alive_interval: Duration::from_secs(30),
+            keep_alive_timeout: Duration::from_secs(20),
+            keep_alive_while_idle: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigPartitionKeyOverride {
+    /// Explicit partition number
+    Fixed(i32),
+    /// Partition is hash of rendered template modulo number of partitions,
+    /// supports same placeholders as `transform_chain`
+    Template(String),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigGrpc2KafkaFormat {
+    /// Decoded transactions serialized as JSON, other updates are skipped
+    #[default]
+    Json,
+    /// Whole `SubscribeUpdate` encoded with protobuf, transactions are not
+    /// decoded unless `dual_write` is set
+    Protobuf,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSignatureEncoding {
+    /// Same as RPC and explorers
+    #[default]
+    Base58,
+    Base64,
+    /// Array of numbers, as serialized by serde
+    Bytes,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigPayloadHashAlgorithm {
+    #[default]
+    Sha256,
+    Blake3,
+    /// Fastest, lower collision resistance, 16 hex chars
+    Xxhash64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigAccountDataEncoding {
+    Base64,
+    Base58,
+    Hex,
+    /// Array of numbers, as serialized by serde
+    #[default]
+    Raw,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigDualWrite {
+    pub json_topic: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConfigAccountProjection {
+    pub include_data: bool,
+    pub include_write_version: bool,
+    pub include_txn_signature: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigWriteBehindCache {
+    /// Max number of accounts in the cache, flushed once reached
+    #[serde(
+        default = "ConfigWriteBehindCache::default_capacity",
+        deserialize_with = "deserialize_usize_str"
+    )]
+    pub capacity: usize,
+    /// Flush interval in milliseconds
+    #[serde(
+        default = "ConfigWriteBehindCache::default_flush_interval",
+        rename = "flush_interval_ms",
+        deserialize_with = "deserialize_duration_ms_str",
+        serialize_with = "serialize_duration_ms"
+    )]
+    pub flush_interval: Duration,
+}
+
+impl ConfigWriteBehindCache {
+    const fn default_capacity() -> usize {
+        100_000
+    }
+
+    const fn default_flush_interval() -> Duration {
+        Duration::from_millis(400)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigParquetSink {
+    /// Destination prefix: `s3://bucket/path`, `gs://bucket/path` or `file:///path`
+    pub url: String,
+    /// Options passed to the object store builder (credentials, region, etc.)
+    #[serde(default)]
+    pub options: HashMap<String, String>,
+    #[serde(
+        default = "ConfigParquetSink::default_max_rows",
+        deserialize_with = "deserialize_usize_str"
+    )]
+    pub max_rows: usize,
+    #[serde(
+        default = "ConfigParquetSink::default_max_bytes",
+        deserialize_with = "deserialize_usize_str"
+    )]
+    pub max_bytes: usize,
+    /// Flush interval in milliseconds
+    #[serde(
+        default = "ConfigParquetSink::default_flush_interval",
+        deserialize_with = "deserialize_duration_ms_str",
+        serialize_with = "serialize_duration_ms"
+    )]
+    pub flush_interval: Duration,
+}
+
+impl ConfigParquetSink {
+    const fn default_max_rows() -> usize {
+        100_000
+    }
+
+    const fn default_max_bytes() -> usize {
+        128 * 1024 * 1024
+    }
+
+    const fn default_flush_interval() -> Duration {
+        Duration::from_secs(60)
+    }
+}
+
+/// Message belongs to `shard_index` if hash of `hash_field` modulo
+/// `shard_count` is equal to it
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct ConfigSharding {
+    pub shard_count: u32,
+    pub shard_index: u32,
+    #[serde(default)]
+    pub hash_field: ConfigShardHashField,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigShardHashField {
+    #[default]
+    Slot,
+    /// Account pubkey, other messages are hashed by slot
+    Pubkey,
+    /// Transaction signature, account updates by `txn_signature` or pubkey,
+    /// other messages are hashed by slot
+    Signature,
+}
+
+/// Consumer group timeouts, override values from `kafka`
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConfigConsumerTimeouts {
+    /// `max.poll.interval.ms`
+    pub max_poll_interval_ms: Option<u64>,
+    /// `session.timeout.ms`
+    pub session_timeout_ms: Option<u64>,
+    /// `heartbeat.interval.ms`
+    pub heartbeat_interval_ms: Option<u64>,
+}
+
+/// Check producer properties which librdkafka rejects only on producer
+/// creation, with aliases
+pub fn validate_kafka_config(kafka_config: &ClientConfig) -> anyhow::Result<()> {
+    for key in ["batch.num.messages", "queue.buffering.max.ms", "linger.ms"] {
+        if let Some(value) = kafka_config.get(key) {
+            value.parse::<u32>().map_err(|error| {
+                anyhow::anyhow!("`{key}` should be a non-negative integer, got {value:?}: {error}")
+            })?;
+        }
+    }
+    for key in ["compression.type", "compression.codec"] {
+        if let Some(value) = kafka_config.get(key) {
+            anyhow::ensure!(
+                CODECS.contains(&value),
+                "`{key}` should be one of {}, got {value:?}",
+                CODECS.join(", ")
+            );
+        }
+    }
+    for key in ["acks", "request.required.acks"] {
+        if let Some(value) = kafka_config.get(key) {
+            anyhow::ensure!(
+                ["0", "1", "all", "-1"].contains(&value),
+                "`{key}` should be 0, 1 or all, got {value:?}"
+            );
+        }
+    }
+    Ok(())
+}
+
+impl ConfigConsumerTimeouts {
+    const KEYS: [(&'static str, u64); 3] = [
+        ("max.poll.interval.ms", 300_000),
+        ("session.timeout.ms", 45_000),
+        ("heartbeat.interval.ms", 3_000),
+    ];
+
+    /// librdkafka properties of set timeouts
+    pub fn properties(&self) -> impl Iterator<Item = (&'static str, String)> {
+        Self::KEYS
+            .into_iter()
+            .zip([
+                self.max_poll_interval_ms,
+                self.session_timeout_ms,
+                self.heartbeat_interval_ms,
+            ])
+            .filter_map(|((key, _default), value)| value.map(|value| (key, value.to_string())))
+    }
+
+    /// Effective values (with values from `kafka` and librdkafka defaults)
+    /// should satisfy `heartbeat < session <= max poll`
+    pub fn validate(&self, kafka: &HashMap<String, String>) -> anyhow::Result<()> {
+        let mut values = [0; 3];
+        for (((key, default), value), effective) in Self::KEYS
+            .into_iter()
+            .zip([
+                self.max_poll_interval_ms,
+                self.session_timeout_ms,
+                self.heartbeat_interval_ms,
+            ])
+            .zip(values.iter_mut())
+        {
+            *effective = match (value, kafka.get(key)) {
+                (Some(value), _) => value,
+                (None, Some(value)) => value
+                    .parse()
+                    .map_err(|error| anyhow::anyhow!("invalid `{key}` value {value}: {error}"))?,
+                (None, None) => default,
+            };
+        }
+        let [max_poll_interval, session_timeout, heartbeat_interval] = values;
+        anyhow::ensure!(
+            heartbeat_interval < session_timeout,
+            "`heartbeat.interval.ms` ({heartbeat_interval}) should be lower than `session.timeout.ms` ({session_timeout})"
+        );
+        anyhow::ensure!(
+            session_timeout <= max_poll_interval,
+            "`session.timeout.ms` ({session_timeout}) should not be greater than `max.poll.interval.ms` ({max_poll_interval})"
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct ConfigBackfillReorder {
+    /// Message is released when max seen slot is this number of slots ahead
+    #[serde(default = "ConfigBackfillReorder::default_window_slots")]
+    pub window_slots: u64,
+    /// Oldest messages are released early when buffer exceeds this size
+    #[serde(default = "ConfigBackfillReorder::default_max_buffered_messages")]
+    pub max_buffered_messages: usize,
+    /// Switch to passthrough after this number of consecutive messages within
+    /// the window
+    #[serde(default = "ConfigBackfillReorder::default_settle_messages")]
+    pub settle_messages: usize,
+}
+
+impl ConfigBackfillReorder {
+    const fn default_window_slots() -> u64 {
+        32
+    }
+
+    const fn default_max_buffered_messages() -> usize {
+        100_000
+    }
+
+    const fn default_settle_messages() -> usize {
+        10_000
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigLeaderElection {
+    /// Topic with exactly one partition, used only for group membership
+    pub topic: String,
+    pub group_id: String,
+    /// Standby takes over after leader misses heartbeats for this time
+    #[serde(default = "ConfigLeaderElection::default_session_timeout_ms")]
+    pub session_timeout_ms: u64,
 }
 
-#[derive(Debug, Deserialize)]
+impl ConfigLeaderElection {
+    const fn default_session_timeout_ms() -> u64 {
+        10_000
+    }
+}
+
+/// Enrichment step applied to JSON payloads before they are written to Kafka.
+///
+/// Templates support `{slot}`, `{kind}`, `{hash}` and `{key}` placeholders,
+/// JSON paths are dot-separated (`meta.fee`, `transaction.signatures.0`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConfigTransform {
+    AddHeader {
+        key: String,
+        value_template: String,
+    },
+    ExtractField {
+        json_path: String,
+        header_name: String,
+    },
+    RenameKey {
+        template: String,
+    },
+    DropField {
+        json_path: String,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ConfigKafka2Grpc {
     #[serde(default)]
     pub kafka: HashMap<String, String>,
+    /// Topic name or regex pattern starting with `^`
     pub kafka_topic: String,
-    pub listen: SocketAddr,
+    /// Address or list of addresses, see [`ConfigGrpcListen`]
+    #[serde(deserialize_with = "ConfigGrpcListen::deserialize_list")]
+    pub listen: Vec<ConfigGrpcListen>,
+    /// Limit of updates per second sent to every client
+    pub per_client_rate_limit_per_second: Option<u64>,
+    /// Send messages for this window from the topic to new clients before
+    /// live updates, not supported for topic patterns
+    pub replay_window_seconds: Option<u64>,
+    /// Size of the broadcast buffer and of every client queue in messages,
+    /// each queued message holds a whole update, so memory use grows with
+    /// capacity multiplied by number of clients
     #[serde(default = "ConfigKafka2Grpc::channel_capacity_default")]
     pub channel_capacity: usize,
+    /// Max time to drain client streams on shutdown
+    #[serde(default = "ConfigKafka2Grpc::default_grpc_drain_timeout_seconds")]
+    pub grpc_drain_timeout_seconds: u64,
+    #[serde(default)]
+    pub kafka_group_id_suffix: ConfigGroupIdSuffix,
+    /// Consume these partitions of the input topic directly instead of
+    /// consumer group subscription
+    pub assign: Option<Vec<i32>>,
+    /// Raise `max_poll_interval_ms` if slow clients delay polling
+    #[serde(default)]
+    pub consumer_timeouts: ConfigConsumerTimeouts,
+    /// Send messages with `x-account-filter: <pubkey>` header only to clients
+    /// with this account in `accounts` filters of the subscription, clients
+    /// without account list receive all messages
+    #[serde(default)]
+    pub filter_from_headers: bool,
 }
 
 impl ConfigKafka2Grpc {
+    /// Regex if `kafka_topic` is a pattern subscription
+    pub fn kafka_topic_pattern(&self) -> anyhow::Result<Option<Regex>> {
+        if self.kafka_topic.starts_with('^') {
+            Regex::new(&self.kafka_topic)
+                .map(Some)
+                .map_err(|error| anyhow::anyhow!("invalid `kafka_topic` pattern: {error}"))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub const CHANNEL_CAPACITY_MAX: usize = 10_000_000;
+
     const fn channel_capacity_default() -> usize {
         250_000
     }
+
+    fn validate_channel_capacity(channel_capacity: usize) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            (1..=Self::CHANNEL_CAPACITY_MAX).contains(&channel_capacity),
+            "`channel_capacity` should be in range 1-{}, got {channel_capacity}",
+            Self::CHANNEL_CAPACITY_MAX
+        );
+        Ok(())
+    }
+
+    const fn default_grpc_drain_timeout_seconds() -> u64 {
+        10
+    }
+}
+
+/// Upstream subscription options are same as in `grpc2kafka`, server options
+/// are same as in `kafka2grpc`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConfigGrpc2Grpc {
+    pub endpoint: String,
+    pub x_token: Option<String>,
+    #[serde(default = "ConfigGrpc2Kafka::default_x_token_header")]
+    pub x_token_header: String,
+    #[serde(default)]
+    pub x_token_header_overrides: HashMap<String, String>,
+    pub secondary_endpoint: Option<String>,
+    #[serde(default = "ConfigGrpc2Kafka::default_failover_threshold_seconds")]
+    pub failover_threshold_seconds: u64,
+    #[serde(default)]
+    pub keepalive: ConfigGrpcKeepalive,
+    pub client_ping_interval: Option<u64>,
+    #[serde(default = "ConfigGrpc2Kafka::default_initial_reconnect_delay_ms")]
+    pub initial_reconnect_delay_ms: u64,
+    #[serde(default = "ConfigGrpc2Kafka::default_grpc_health_service_name")]
+    pub grpc_health_service_name: String,
+    #[serde(default = "ConfigGrpc2Kafka::default_grpc_user_agent")]
+    pub grpc_user_agent: String,
+    pub request: ConfigGrpcRequest,
+    #[serde(deserialize_with = "ConfigGrpcListen::deserialize_list")]
+    pub listen: Vec<ConfigGrpcListen>,
+    pub per_client_rate_limit_per_second: Option<u64>,
+    #[serde(default = "ConfigKafka2Grpc::channel_capacity_default")]
+    pub channel_capacity: usize,
+    #[serde(default = "ConfigKafka2Grpc::default_grpc_drain_timeout_seconds")]
+    pub grpc_drain_timeout_seconds: u64,
+}
+
+/// gRPC server listen address: `127.0.0.1:10000`, `[::1]:10000` or
+/// `unix:/path/to/socket`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigGrpcListen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ConfigGrpcListen {
+    /// Accepts a single address or a non-empty list
+    fn deserialize_list<'de, D>(deserializer: D) -> Result<Vec<Self>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            One(String),
+            Many(Vec<String>),
+        }
+
+        let list = match Value::deserialize(deserializer)? {
+            Value::One(value) => vec![value],
+            Value::Many(values) => values,
+        };
+        if list.is_empty() {
+            return Err(de::Error::custom("at least one listen address is required"));
+        }
+        list.iter()
+            .map(|value| value.parse().map_err(de::Error::custom))
+            .collect()
+    }
+}
+
+impl FromStr for ConfigGrpcListen {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.strip_prefix("unix:") {
+            Some("") => Err("empty unix socket path".to_owned()),
+            Some(path) => Ok(Self::Unix(PathBuf::from(path))),
+            None => value
+                .parse()
+                .map(Self::Tcp)
+                .map_err(|error| format!("invalid listen address {value}: {error}")),
+        }
+    }
+}
+
+impl Serialize for ConfigGrpcListen {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for ConfigGrpcListen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            validate_kafka_config, Config, ConfigGroupIdSuffix, ConfigGrpcListen, ConfigKafka2Grpc,
+        },
+        crate::config::redact_secrets,
+        rdkafka::config::ClientConfig,
+        std::path::PathBuf,
+    };
+
+    #[test]
+    fn kafka_config_validation() {
+        let validate = |key: &str, value: &str| {
+            let mut kafka_config = ClientConfig::new();
+            kafka_config.set(key, value);
+            validate_kafka_config(&kafka_config)
+        };
+        assert!(validate_kafka_config(&ClientConfig::new()).is_ok());
+        assert!(validate("batch.num.messages", "10000").is_ok());
+        assert!(validate("batch.num.messages", "10k").is_err());
+        assert!(validate("queue.buffering.max.ms", "-5").is_err());
+        assert!(validate("linger.ms", "5").is_ok());
+        assert!(validate("compression.type", "zstd").is_ok());
+        assert!(validate("compression.type", "brotli").is_err());
+        assert!(validate("acks", "all").is_ok());
+        assert!(validate("acks", "2").is_err());
+        assert!(validate("request.required.acks", "-1").is_ok());
+    }
+
+    #[test]
+    fn payload_topic_oversize() {
+        let config = |oversize_topic: Option<&str>| {
+            serde_json::from_value::<Config>(serde_json::json!({
+                "grpc2kafka": {
+                    "endpoint": "http://127.0.0.1:10000",
+                    "request": {},
+                    "kafka_topic": "grpc1",
+                    "max_payload_bytes": 100,
+                    "oversize_topic": oversize_topic,
+                    "dual_write": {"json_topic": "grpc1-json"},
+                }
+            }))
+            .unwrap()
+            .grpc2kafka
+            .unwrap()
+        };
+
+        let config_skip = config(None);
+        let json_topic = &config_skip.dual_write.as_ref().unwrap().json_topic;
+        assert_eq!(
+            config_skip.payload_topic(&config_skip.kafka_topic, 100),
+            Some(&config_skip.kafka_topic)
+        );
+        assert_eq!(config_skip.payload_topic(json_topic, 100), Some(json_topic));
+        assert_eq!(config_skip.payload_topic(json_topic, 101), None);
+
+        // main and dual write JSON records are routed the same way
+        let config_route = config(Some("oversize"));
+        let json_topic = &config_route.dual_write.as_ref().unwrap().json_topic;
+        assert_eq!(
+            config_route
+                .payload_topic(json_topic, 101)
+                .map(String::as_str),
+            Some("oversize")
+        );
+        assert_eq!(
+            config_route
+                .payload_topic(&config_route.kafka_topic, 101)
+                .map(String::as_str),
+            Some("oversize")
+        );
+    }
+
+    #[test]
+    fn validate_jitter_send_delay() {
+        let config = |jitter: u64, ping: Option<u64>| {
+            serde_json::from_value::<Config>(serde_json::json!({
+                "grpc2kafka": {
+                    "endpoint": "http://127.0.0.1:10000",
+                    "request": {},
+                    "kafka_topic": "grpc1",
+                    "jitter_send_delay_ms": jitter,
+                    "client_ping_interval": ping,
+                }
+            }))
+            .unwrap()
+        };
+        assert!(config(0, None).validate().is_ok());
+        assert!(config(200, None).validate().is_ok());
+        assert!(config(201, None).validate().is_err());
+        assert!(config(100, Some(200)).validate().is_ok());
+        assert!(config(100, Some(150)).validate().is_err());
+    }
+
+    #[test]
+    fn validate_channel_capacity() {
+        let config = |channel_capacity: usize| {
+            serde_json::from_value::<Config>(serde_json::json!({
+                "kafka2grpc": {
+                    "kafka_topic": "grpc1",
+                    "listen": "127.0.0.1:10001",
+                    "channel_capacity": channel_capacity,
+                }
+            }))
+            .unwrap()
+        };
+        assert!(config(1).validate().is_ok());
+        assert!(config(250_000).validate().is_ok());
+        assert!(config(0).validate().is_err());
+        assert!(config(usize::MAX).validate().is_err());
+    }
+
+    #[test]
+    fn serialize_effective_config() {
+        let mut config = serde_json::from_value::<Config>(serde_json::json!({
+            "kafka": {"sasl.password": "pass"},
+            "kafka_topic_prefix": "prod-",
+            "kafka2grpc": {
+                "kafka_topic": "grpc1",
+                "listen": ["127.0.0.1:10001", "unix:/tmp/grpc.sock"],
+            },
+            "grpc2kafka": {
+                "endpoint": "http://127.0.0.1:10000",
+                "x_token": "token",
+                "request": {},
+                "kafka_topic": "grpc1",
+            }
+        }))
+        .unwrap();
+        config.apply_kafka_topic_prefix().unwrap();
+        let mut value = serde_json::to_value(&config).unwrap();
+        redact_secrets(&mut value);
+
+        assert_eq!(value["kafka"]["sasl.password"], "<redacted>");
+        assert_eq!(value["grpc2kafka"]["x_token"], "<redacted>");
+        assert_eq!(value["grpc2kafka"]["kafka_topic"], "prod-grpc1");
+        assert_eq!(value["kafka2grpc"]["kafka_topic"], "prod-grpc1");
+        assert_eq!(
+            value["kafka2grpc"]["listen"],
+            serde_json::json!(["127.0.0.1:10001", "unix:/tmp/grpc.sock"])
+        );
+        // serde defaults
+        assert_eq!(
+            value["kafka2grpc"]["channel_capacity"],
+            ConfigKafka2Grpc::channel_capacity_default()
+        );
+        assert_eq!(
+            value["grpc2kafka"]["keepalive"]["http2_keep_alive_interval"],
+            30_000
+        );
+        assert!(serde_json::from_value::<Config>(value).is_ok());
+    }
+
+    #[test]
+    fn kafka_topic_prefix() {
+        let config = |prefix: &str, kafka2grpc_topic: &str| {
+            serde_json::from_value::<Config>(serde_json::json!({
+                "kafka_topic_prefix": prefix,
+                "dedup": {
+                    "kafka_input": "grpc1",
+                    "kafka_output": "grpc2",
+                    "backend": {"type": "memory"},
+                },
+                "kafka2grpc": {
+                    "kafka_topic": kafka2grpc_topic,
+                    "listen": "127.0.0.1:10001",
+                }
+            }))
+            .unwrap()
+        };
+
+        let mut prefixed = config("prod-", "^grpc.*");
+        prefixed.apply_kafka_topic_prefix().unwrap();
+        let dedup = prefixed.dedup.unwrap();
+        assert_eq!(dedup.kafka_input, "prod-grpc1");
+        assert_eq!(dedup.kafka_output.as_deref(), Some("prod-grpc2"));
+        assert_eq!(prefixed.kafka2grpc.unwrap().kafka_topic, "^prod\\-grpc.*");
+
+        let mut prefixed = config("prod.", "grpc2");
+        prefixed.apply_kafka_topic_prefix().unwrap();
+        assert_eq!(prefixed.kafka2grpc.unwrap().kafka_topic, "prod.grpc2");
+
+        assert!(config("prod/", "grpc2").apply_kafka_topic_prefix().is_err());
+        assert!(config(&"a".repeat(249), "grpc2")
+            .apply_kafka_topic_prefix()
+            .is_err());
+    }
+
+    #[test]
+    fn consumer_timeouts() {
+        let config = |timeouts: serde_json::Value, kafka: serde_json::Value| {
+            serde_json::from_value::<Config>(serde_json::json!({
+                "kafka2grpc": {
+                    "kafka": kafka,
+                    "kafka_topic": "grpc1",
+                    "listen": "127.0.0.1:10001",
+                    "consumer_timeouts": timeouts,
+                }
+            }))
+            .unwrap()
+        };
+        let empty = serde_json::json!({});
+        assert!(config(empty.clone(), empty.clone()).validate().is_ok());
+
+        let timeouts = serde_json::json!({"max_poll_interval_ms": 600_000});
+        let properties = config(timeouts.clone(), empty.clone())
+            .kafka2grpc
+            .unwrap()
+            .consumer_timeouts
+            .properties()
+            .collect::<Vec<_>>();
+        assert_eq!(properties, [("max.poll.interval.ms", "600000".to_owned())]);
+        assert!(config(timeouts, empty.clone()).validate().is_ok());
+
+        // librdkafka default session timeout is greater
+        let timeouts = serde_json::json!({"max_poll_interval_ms": 30_000});
+        assert!(config(timeouts.clone(), empty.clone()).validate().is_err());
+        let kafka = serde_json::json!({"session.timeout.ms": "10000"});
+        assert!(config(timeouts.clone(), kafka.clone()).validate().is_ok());
+
+        // global `kafka` is overlaid by section `kafka`, as for the consumer
+        let mut global = config(timeouts.clone(), empty.clone());
+        global
+            .kafka
+            .insert("session.timeout.ms".to_owned(), "10000".to_owned());
+        assert!(global.validate().is_ok());
+        let mut global = config(timeouts, kafka);
+        global
+            .kafka
+            .insert("session.timeout.ms".to_owned(), "60000".to_owned());
+        assert!(global.validate().is_ok());
+        let mut global = config(empty.clone(), empty.clone());
+        global
+            .kafka
+            .insert("heartbeat.interval.ms".to_owned(), "60000".to_owned());
+        assert!(global.validate().is_err());
+
+        let timeouts =
+            serde_json::json!({"session_timeout_ms": 10_000, "heartbeat_interval_ms": 10_000});
+        assert!(config(timeouts, empty).validate().is_err());
+    }
+
+    #[test]
+    fn group_id_suffix() {
+        assert_eq!(ConfigGroupIdSuffix::None.render("dedup"), "dedup");
+        assert_eq!(
+            ConfigGroupIdSuffix::Pid.render("dedup"),
+            format!("dedup-{}", std::process::id())
+        );
+        let uuid = ConfigGroupIdSuffix::Uuid.render("dedup");
+        let parts = uuid.split('-').collect::<Vec<_>>();
+        assert_eq!(
+            parts.iter().map(|part| part.len()).collect::<Vec<_>>(),
+            [5, 8, 4, 4, 4, 12]
+        );
+        assert!(parts[3].starts_with('4'));
+        assert_ne!(uuid, ConfigGroupIdSuffix::Uuid.render("dedup"));
+    }
+
+    #[test]
+    fn grpc_listen_list() {
+        let listen = |value: serde_json::Value| {
+            serde_json::from_value::<Config>(serde_json::json!({
+                "kafka2grpc": {
+                    "kafka_topic": "grpc1",
+                    "listen": value,
+                }
+            }))
+            .map(|config| config.kafka2grpc.unwrap().listen)
+        };
+        assert_eq!(
+            listen(serde_json::json!("127.0.0.1:10001")).unwrap(),
+            vec![ConfigGrpcListen::Tcp("127.0.0.1:10001".parse().unwrap())]
+        );
+        assert_eq!(
+            listen(serde_json::json!(["[::1]:10001", "unix:/tmp/grpc.sock"])).unwrap(),
+            vec![
+                ConfigGrpcListen::Tcp("[::1]:10001".parse().unwrap()),
+                ConfigGrpcListen::Unix(PathBuf::from("/tmp/grpc.sock")),
+            ]
+        );
+        assert!(listen(serde_json::json!([])).is_err());
+        assert!(listen(serde_json::json!("unix:")).is_err());
+        assert!(listen(serde_json::json!("localhost")).is_err());
+    }
 }
@@ -0,0 +1,222 @@
+use {
+    super::metrics,
+    futures::{
+        future::{pending, BoxFuture, FutureExt},
+        stream::{FuturesUnordered, StreamExt},
+    },
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+    tokio::{
+        sync::Notify,
+        time::{timeout_at, Instant},
+    },
+};
+
+/// Confirmed slots kept to pass through late messages without waiting
+const MAX_CONFIRMED_SLOTS: usize = 10_000;
+
+#[derive(Debug, Default)]
+struct SlotNotifier {
+    notify: Notify,
+    confirmed: AtomicBool,
+    /// Released without confirmation because of `max_pending`
+    released: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+struct SlotWaiters {
+    notifier: Arc<SlotNotifier>,
+    messages: usize,
+}
+
+/// Holds messages until their slot is confirmed, messages are released
+/// anyway after `timeout`. With more than `max_pending` waiting messages
+/// the oldest slot is released early.
+pub struct SlotConfirmationWait<T> {
+    timeout: Duration,
+    max_pending: usize,
+    slots: BTreeMap<u64, SlotWaiters>,
+    /// Messages of `slots`, released messages not yielded by `next` yet are
+    /// not counted
+    waiting: usize,
+    confirmed: BTreeSet<u64>,
+    pending: FuturesUnordered<BoxFuture<'static, (u64, Arc<SlotNotifier>, T)>>,
+}
+
+impl<T> std::fmt::Debug for SlotConfirmationWait<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SlotConfirmationWait")
+            .field("timeout", &self.timeout)
+            .field("max_pending", &self.max_pending)
+            .field("waiting", &self.waiting)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl<T: Send + 'static> SlotConfirmationWait<T> {
+    pub fn new(timeout: Duration, max_pending: usize) -> Self {
+        Self {
+            timeout,
+            max_pending: max_pending.max(1),
+            slots: BTreeMap::new(),
+            waiting: 0,
+            confirmed: BTreeSet::new(),
+            pending: FuturesUnordered::new(),
+        }
+    }
+
+    /// Returns message back if the slot is already confirmed
+    pub fn push(&mut self, slot: u64, message: T) -> Option<T> {
+        if self.confirmed.contains(&slot) {
+            return Some(message);
+        }
+        while self.waiting >= self.max_pending {
+            let Some((_slot, waiters)) = self.slots.pop_first() else {
+                break;
+            };
+            self.waiting -= waiters.messages;
+            waiters.notifier.released.store(true, Ordering::Relaxed);
+            waiters.notifier.notify.notify_waiters();
+        }
+
+        let waiters = self.slots.entry(slot).or_default();
+        waiters.messages += 1;
+        self.waiting += 1;
+        let notifier = Arc::clone(&waiters.notifier);
+        let deadline = Instant::now() + self.timeout;
+        self.pending.push(
+            async move {
+                {
+                    let notified = notifier.notify.notified();
+                    tokio::pin!(notified);
+                    notified.as_mut().enable();
+                    if !notifier.confirmed.load(Ordering::Relaxed)
+                        && !notifier.released.load(Ordering::Relaxed)
+                    {
+                        let _ = timeout_at(deadline, notified).await;
+                    }
+                }
+                (slot, notifier, message)
+            }
+            .boxed(),
+        );
+        None
+    }
+
+    /// Release pending messages of the slot
+    pub fn confirm(&mut self, slot: u64) {
+        if let Some(waiters) = self.slots.remove(&slot) {
+            self.waiting -= waiters.messages;
+            waiters.notifier.confirmed.store(true, Ordering::Relaxed);
+            waiters.notifier.notify.notify_waiters();
+        }
+        self.confirmed.insert(slot);
+        while self.confirmed.len() > MAX_CONFIRMED_SLOTS {
+            self.confirmed.pop_first();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Next confirmed or timed out message, never resolves without pending
+    /// messages. Cancel safe.
+    pub async fn next(&mut self) -> T {
+        let Some((slot, notifier, message)) = self.pending.next().await else {
+            return pending().await;
+        };
+        if notifier.released.load(Ordering::Relaxed) {
+            metrics::confirmation_overflow_inc();
+        } else if !notifier.confirmed.load(Ordering::Relaxed) {
+            metrics::confirmation_timeout_inc();
+            // slot is still in the map, it's removed only on confirm or release
+            if let Some(waiters) = self.slots.get_mut(&slot) {
+                waiters.messages -= 1;
+                self.waiting -= 1;
+                if waiters.messages == 0 {
+                    self.slots.remove(&slot);
+                }
+            }
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::SlotConfirmationWait,
+        std::time::Duration,
+        tokio::time::{timeout, Instant},
+    };
+
+    #[tokio::test]
+    async fn confirmation_wait() {
+        let mut wait = SlotConfirmationWait::new(Duration::from_secs(60), 100);
+        assert!(wait.push(10, "a").is_none());
+        assert!(wait.push(11, "b").is_none());
+        assert!(timeout(Duration::from_millis(10), wait.next())
+            .await
+            .is_err());
+
+        wait.confirm(11);
+        assert_eq!(wait.next().await, "b");
+        assert_eq!(wait.len(), 1);
+        // confirmed slots are passed through
+        assert_eq!(wait.push(11, "c"), Some("c"));
+
+        // confirmed before the first poll of the pending message
+        assert!(wait.push(12, "d").is_none());
+        wait.confirm(12);
+        assert_eq!(wait.next().await, "d");
+    }
+
+    #[tokio::test]
+    async fn confirmation_timeout() {
+        let mut wait = SlotConfirmationWait::new(Duration::from_millis(20), 100);
+        assert!(wait.push(10, "a").is_none());
+        let ts = Instant::now();
+        assert_eq!(wait.next().await, "a");
+        assert!(ts.elapsed() >= Duration::from_millis(20));
+        assert!(wait.is_empty());
+        assert!(wait.slots.is_empty());
+        assert_eq!(wait.waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn confirmation_max_pending() {
+        let mut wait = SlotConfirmationWait::new(Duration::from_secs(60), 3);
+        assert!(wait.push(10, "a").is_none());
+        assert!(wait.push(10, "b").is_none());
+        assert!(wait.push(11, "c").is_none());
+        // slot 10 is the oldest, both messages are released
+        assert!(wait.push(12, "d").is_none());
+        assert_eq!(wait.waiting, 2);
+        let mut released = vec![wait.next().await, wait.next().await];
+        released.sort();
+        assert_eq!(released, ["a", "b"]);
+        assert!(timeout(Duration::from_millis(10), wait.next())
+            .await
+            .is_err());
+
+        // message of the released slot waits again
+        assert!(wait.push(10, "e").is_none());
+        assert_eq!(wait.waiting, 3);
+        wait.confirm(11);
+        assert_eq!(wait.next().await, "c");
+        assert_eq!(wait.waiting, 2);
+        assert_eq!(wait.len(), 2);
+    }
+}
@@ -0,0 +1,194 @@
+use {
+    super::metrics::{self, StatsContext},
+    rdkafka::{
+        error::KafkaError,
+        message::{Header, Message, OwnedHeaders, OwnedMessage, ToBytes},
+        producer::{FutureProducer, FutureRecord},
+    },
+    std::{sync::Arc, time::Duration},
+    tokio::time::sleep,
+    tracing::warn,
+};
+
+/// Owned copy of the record which failed to send
+#[derive(Debug, Clone)]
+pub struct FailedRecord {
+    topic: String,
+    key: Option<Vec<u8>>,
+    payload: Option<Vec<u8>>,
+    headers: Option<OwnedHeaders>,
+    partition: Option<i32>,
+}
+
+impl FailedRecord {
+    /// Record returned by the delivery future
+    pub fn from_message(message: OwnedMessage) -> Self {
+        Self {
+            topic: message.topic().to_owned(),
+            key: message.key().map(<[u8]>::to_vec),
+            payload: message.payload().map(<[u8]>::to_vec),
+            headers: message.headers().cloned(),
+            partition: Some(message.partition()).filter(|partition| *partition >= 0),
+        }
+    }
+
+    /// Record rejected by the producer queue
+    pub fn from_record<K, P>(record: FutureRecord<'_, K, P>) -> Self
+    where
+        K: ToBytes + ?Sized,
+        P: ToBytes + ?Sized,
+    {
+        Self {
+            topic: record.topic.to_owned(),
+            key: record.key.map(|key| key.to_bytes().to_vec()),
+            payload: record.payload.map(|payload| payload.to_bytes().to_vec()),
+            headers: record.headers,
+            partition: record.partition,
+        }
+    }
+}
+
+/// Retry failed sends `retries` times, then route the record to the dead
+/// letter topic if it's set
+#[derive(Clone)]
+pub struct FailedSendHandler {
+    kafka: FutureProducer<StatsContext>,
+    retries: u32,
+    dlq_topic: Option<Arc<String>>,
+}
+
+impl FailedSendHandler {
+    pub fn new(
+        kafka: FutureProducer<StatsContext>,
+        retries: u32,
+        dlq_topic: Option<String>,
+    ) -> Self {
+        Self {
+            kafka,
+            retries,
+            dlq_topic: dlq_topic.map(Arc::new),
+        }
+    }
+
+    /// Returns `true` if the record was delivered to the original topic and
+    /// `false` if it was routed to the dead letter topic, error is returned
+    /// only when both failed
+    pub async fn handle(
+        &self,
+        record: FailedRecord,
+        mut error: KafkaError,
+    ) -> anyhow::Result<bool> {
+        for attempt in 1..=self.retries {
+            warn!(
+                "failed to send message to {}: {error}, retry {attempt}/{}",
+                record.topic, self.retries
+            );
+            sleep(Duration::from_millis(100 * attempt as u64)).await;
+            match self
+                .send(
+                    &record.topic,
+                    &record,
+                    record.partition,
+                    record.headers.clone(),
+                )
+                .await
+            {
+                Ok(()) => return Ok(true),
+                Err(send_error) => error = send_error,
+            }
+        }
+
+        let Some(dlq_topic) = &self.dlq_topic else {
+            return Err(error.into());
+        };
+        warn!(
+            "failed to send message to {} after {} retries: {error}, route to {dlq_topic}",
+            record.topic, self.retries
+        );
+        let retries = self.retries.to_string();
+        let error_message = error.to_string();
+        let headers = record
+            .headers
+            .clone()
+            .unwrap_or_default()
+            .insert(Header {
+                key: "x-original-topic",
+                value: Some(&record.topic),
+            })
+            .insert(Header {
+                key: "x-retry-count",
+                value: Some(&retries),
+            })
+            .insert(Header {
+                key: "x-error",
+                value: Some(&error_message),
+            });
+        self.send(dlq_topic, &record, None, Some(headers))
+            .await
+            .map_err(|dlq_error| {
+                anyhow::anyhow!(
+                    "failed to send message to {}: {error}, dead letter topic {dlq_topic}: {dlq_error}",
+                    record.topic
+                )
+            })?;
+        metrics::dead_letter_inc(&record.topic);
+        Ok(false)
+    }
+
+    async fn send(
+        &self,
+        topic: &str,
+        record: &FailedRecord,
+        partition: Option<i32>,
+        headers: Option<OwnedHeaders>,
+    ) -> Result<(), KafkaError> {
+        let future_record = FutureRecord::<[u8], [u8]> {
+            topic,
+            partition,
+            payload: record.payload.as_deref(),
+            key: record.key.as_deref(),
+            timestamp: None,
+            headers,
+        };
+        match self.kafka.send_result(future_record) {
+            Ok(future) => match future.await {
+                Ok(Ok(_delivery)) => Ok(()),
+                Ok(Err((error, _message))) => Err(error),
+                Err(_canceled) => Err(KafkaError::Canceled),
+            },
+            Err((error, _record)) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::FailedRecord,
+        rdkafka::{
+            message::{Header, Headers, OwnedHeaders},
+            producer::FutureRecord,
+        },
+    };
+
+    #[test]
+    fn failed_record_from_record() {
+        let key = "1_abc".to_owned();
+        let payload = vec![1, 2, 3];
+        let mut record = FutureRecord::to("topic")
+            .key(&key)
+            .payload(&payload)
+            .headers(OwnedHeaders::new().insert(Header {
+                key: "x-kind",
+                value: Some("account"),
+            }));
+        record.partition = Some(2);
+
+        let failed = FailedRecord::from_record(record);
+        assert_eq!(failed.topic, "topic");
+        assert_eq!(failed.key.as_deref(), Some(key.as_bytes()));
+        assert_eq!(failed.payload, Some(payload));
+        assert_eq!(failed.partition, Some(2));
+        assert_eq!(failed.headers.map(|headers| headers.count()), Some(1));
+    }
+}
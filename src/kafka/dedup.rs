@@ -1,24 +1,37 @@
 use {
     std::{
-        collections::{btree_map::Entry, BTreeMap, HashSet},
+        collections::{btree_map::Entry, BTreeMap, HashMap, HashSet},
         sync::Arc,
     },
     tokio::sync::Mutex,
 };
 
+pub const SLOTS_PER_EPOCH: u64 = 432_000;
+
+pub const fn slot_epoch(slot: u64) -> u64 {
+    slot / SLOTS_PER_EPOCH
+}
+
 #[async_trait::async_trait]
 pub trait KafkaDedup: Clone {
-    async fn allowed(&self, slot: u64, hash: [u8; 32]) -> bool;
+    /// `hash` length depends on `payload_hash_algorithm` of the producer
+    async fn allowed(&self, slot: u64, hash: &[u8]) -> bool;
+
+    /// Remove all stored hashes
+    async fn clear(&self);
 }
 
+/// Hashes of a slot
+type SlotHashes = HashSet<Box<[u8]>>;
+
 #[derive(Debug, Default, Clone)]
 pub struct KafkaDedupMemory {
-    inner: Arc<Mutex<BTreeMap<u64, HashSet<[u8; 32]>>>>,
+    inner: Arc<Mutex<BTreeMap<u64, SlotHashes>>>,
 }
 
 #[async_trait::async_trait]
 impl KafkaDedup for KafkaDedupMemory {
-    async fn allowed(&self, slot: u64, hash: [u8; 32]) -> bool {
+    async fn allowed(&self, slot: u64, hash: &[u8]) -> bool {
         let mut map = self.inner.lock().await;
 
         if let Some(key_slot) = map.keys().next().cloned() {
@@ -29,11 +42,11 @@ impl KafkaDedup for KafkaDedupMemory {
 
         match map.entry(slot) {
             Entry::Vacant(entry) => {
-                entry.insert(HashSet::new()).insert(hash);
+                entry.insert(HashSet::new()).insert(hash.into());
 
                 // remove old sets, keep ~30sec log
                 while let Some(key_slot) = map.keys().next().cloned() {
-                    if key_slot < slot - 75 {
+                    if key_slot < slot.saturating_sub(75) {
                         map.remove(&key_slot);
                     } else {
                         break;
@@ -42,7 +55,188 @@ impl KafkaDedup for KafkaDedupMemory {
 
                 true
             }
-            Entry::Occupied(entry) => entry.into_mut().insert(hash),
+            Entry::Occupied(entry) => {
+                let hashes = entry.into_mut();
+                !hashes.contains(hash) && hashes.insert(hash.into())
+            }
+        }
+    }
+
+    async fn clear(&self) {
+        self.inner.lock().await.clear();
+    }
+}
+
+/// 64-bit SimHash of the payload, tokens are 8-byte chunks and features are
+/// shingles of `shingle_size` consecutive tokens
+pub fn simhash(payload: &[u8], shingle_size: usize) -> u64 {
+    let tokens = payload.chunks(8).collect::<Vec<_>>();
+    let mut weights = [0i64; 64];
+    for shingle in tokens.windows(shingle_size.clamp(1, tokens.len().max(1))) {
+        // FNV-1a
+        let mut hash = 0xcbf29ce484222325u64;
+        for byte in shingle.iter().flat_map(|token| token.iter()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_bit, weight)| **weight > 0)
+        .fold(0, |hash, (bit, _weight)| hash | (1 << bit))
+}
+
+/// Fingerprints of a slot bucketed by `(band, band bits)`
+type SlotBands = HashMap<(usize, u64), Vec<u64>>;
+
+/// Near-duplicate detection by SimHash. Fingerprints are split into
+/// `bit_diff_threshold + 1` bands, fingerprints within the threshold share
+/// at least one band, so only fingerprints of the same slot with a common
+/// band are compared.
+#[derive(Debug, Clone)]
+pub struct KafkaDedupNearMemory {
+    inner: Arc<Mutex<BTreeMap<u64, SlotBands>>>,
+    bit_diff_threshold: u32,
+    /// `(shift, mask)` of every band
+    bands: Vec<(u32, u64)>,
+}
+
+impl KafkaDedupNearMemory {
+    pub fn new(bit_diff_threshold: u8) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bit_diff_threshold < 64,
+            "`bit_diff_threshold` should be less than 64"
+        );
+        let count = bit_diff_threshold as u32 + 1;
+        let mut shift = 0;
+        let bands = (0..count)
+            .map(|band| {
+                let width = 64 / count + u32::from(band < 64 % count);
+                let band = (shift, u64::MAX >> (64 - width));
+                shift += width;
+                band
+            })
+            .collect();
+        Ok(Self {
+            inner: Arc::default(),
+            bit_diff_threshold: bit_diff_threshold as u32,
+            bands,
+        })
+    }
+
+    fn band_keys(&self, fingerprint: u64) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.bands
+            .iter()
+            .enumerate()
+            .map(move |(band, (shift, mask))| (band, (fingerprint >> shift) & mask))
+    }
+
+    /// Returns `false` if Hamming distance to any stored fingerprint of the
+    /// slot is within threshold
+    pub async fn allowed(&self, slot: u64, fingerprint: u64) -> bool {
+        let mut map = self.inner.lock().await;
+        let buckets = map.entry(slot).or_default();
+        let duplicate = self.band_keys(fingerprint).any(|key| {
+            buckets.get(&key).is_some_and(|fingerprints| {
+                fingerprints
+                    .iter()
+                    .any(|stored| (stored ^ fingerprint).count_ones() <= self.bit_diff_threshold)
+            })
+        });
+        if duplicate {
+            return false;
+        }
+        for key in self.band_keys(fingerprint) {
+            buckets.entry(key).or_default().push(fingerprint);
+        }
+
+        // same ~30sec window as exact dedup
+        while let Some(key_slot) = map.keys().next().cloned() {
+            if key_slot < slot.saturating_sub(75) {
+                map.remove(&key_slot);
+            } else {
+                break;
+            }
+        }
+        true
+    }
+
+    pub async fn clear(&self) {
+        self.inner.lock().await.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simhash, KafkaDedup, KafkaDedupMemory, KafkaDedupNearMemory};
+
+    #[tokio::test]
+    async fn memory_clear() {
+        let dedup = KafkaDedupMemory::default();
+        assert!(dedup.allowed(100, &[1; 32]).await);
+        assert!(!dedup.allowed(100, &[1; 32]).await);
+        assert!(!dedup.allowed(99, &[2; 32]).await);
+
+        dedup.clear().await;
+        assert!(dedup.allowed(99, &[2; 32]).await);
+        assert!(dedup.allowed(100, &[1; 32]).await);
+    }
+
+    #[tokio::test]
+    async fn near_duplicates() {
+        let mut rng = fastrand::Rng::with_seed(42);
+        let mut payload = std::iter::repeat_with(|| rng.u8(..))
+            .take(16 * 1024)
+            .collect::<Vec<_>>();
+        let original = simhash(&payload, 2);
+        assert_eq!(original, simhash(&payload, 2));
+        payload[1000] ^= 0xff;
+        let changed = simhash(&payload, 2);
+        let other = simhash(&payload[..8 * 1024], 2);
+
+        let dedup = KafkaDedupNearMemory::new(6).unwrap();
+        assert!(dedup.allowed(100, original).await);
+        assert!(!dedup.allowed(100, changed).await);
+        assert!(dedup.allowed(100, other).await);
+        assert!(dedup.allowed(101, changed).await);
+    }
+
+    #[tokio::test]
+    async fn near_duplicates_bands() {
+        assert!(KafkaDedupNearMemory::new(64).is_err());
+        for threshold in [0, 3, 6, 20, 63] {
+            let dedup = KafkaDedupNearMemory::new(threshold).unwrap();
+            let mask = dedup
+                .bands
+                .iter()
+                .fold(0, |bits, (shift, mask)| bits | (mask << shift));
+            assert_eq!(mask, u64::MAX);
+
+            // bands give the same result as comparing with every fingerprint
+            let mut rng = fastrand::Rng::with_seed(threshold as u64);
+            let mut stored = vec![];
+            for _ in 0..500 {
+                let fingerprint = match stored.last() {
+                    Some(last) if rng.bool() => (0..rng.u32(0..=threshold as u32 + 1))
+                        .fold(*last, |fingerprint, _| fingerprint ^ (1 << rng.u32(0..64))),
+                    _ => rng.u64(..),
+                };
+                let expected = !stored
+                    .iter()
+                    .any(|stored: &u64| (stored ^ fingerprint).count_ones() <= threshold as u32);
+                assert_eq!(dedup.allowed(100, fingerprint).await, expected);
+                if expected {
+                    stored.push(fingerprint);
+                }
+            }
         }
     }
 }
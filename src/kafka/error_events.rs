@@ -0,0 +1,75 @@
+use {
+    super::metrics::StatsContext,
+    rdkafka::producer::{FutureProducer, FutureRecord},
+    serde::Serialize,
+    std::time::{SystemTime, UNIX_EPOCH},
+    tokio::{sync::mpsc, task::JoinHandle},
+    tracing::warn,
+};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorEventKind {
+    Decode,
+    Produce,
+    Reconnect,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorEvent {
+    /// Unix timestamp in milliseconds
+    timestamp: u64,
+    kind: ErrorEventKind,
+    message: String,
+}
+
+/// Best-effort publisher of pipeline errors as JSON to the error topic,
+/// events are dropped if the queue is full or produce failed.
+#[derive(Debug, Default, Clone)]
+pub struct ErrorEvents {
+    events_tx: Option<mpsc::Sender<ErrorEvent>>,
+}
+
+impl ErrorEvents {
+    pub fn spawn(kafka: FutureProducer<StatsContext>, topic: String) -> (Self, JoinHandle<()>) {
+        let (events_tx, mut events_rx) = mpsc::channel::<ErrorEvent>(1_024);
+        let task = tokio::spawn(async move {
+            while let Some(event) = events_rx.recv().await {
+                let Ok(payload) = serde_json::to_vec(&event) else {
+                    continue;
+                };
+                let record = FutureRecord::<(), _>::to(&topic).payload(&payload);
+                let result = match kafka.send_result(record) {
+                    Ok(future) => future
+                        .await
+                        .map_err(|_canceled| "canceled".to_owned())
+                        .and_then(|result| result.map_err(|(error, _message)| error.to_string())),
+                    Err((error, _record)) => Err(error.to_string()),
+                };
+                if let Err(error) = result {
+                    warn!("failed to send error event to {topic}: {error}");
+                }
+            }
+        });
+        (
+            Self {
+                events_tx: Some(events_tx),
+            },
+            task,
+        )
+    }
+
+    pub fn emit(&self, kind: ErrorEventKind, message: impl Into<String>) {
+        if let Some(events_tx) = &self.events_tx {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|ts| ts.as_millis() as u64)
+                .unwrap_or_default();
+            let _ = events_tx.try_send(ErrorEvent {
+                timestamp,
+                kind,
+                message: message.into(),
+            });
+        }
+    }
+}
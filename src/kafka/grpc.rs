@@ -1,8 +1,22 @@
 use {
+    super::{
+        config::ConfigGrpcListen,
+        metrics,
+        rate_limit::TokenBucket,
+        replay::{
+            ClientCursor, KafkaReplay, ReplayCursor, INCLUDE_CURSOR_HEADER, RESUME_CURSOR_HEADER,
+        },
+    },
     crate::version::VERSION,
-    futures::future::{BoxFuture, FutureExt},
+    futures::{
+        future::{try_join_all, BoxFuture, FutureExt},
+        stream,
+    },
+    rdkafka::message::{BorrowedHeaders, Headers},
     std::{
-        net::SocketAddr,
+        collections::HashSet,
+        fs,
+        os::unix::fs::FileTypeExt,
         sync::{
             atomic::{AtomicUsize, Ordering},
             Arc,
@@ -10,9 +24,10 @@ use {
         time::SystemTime,
     },
     tokio::{
-        sync::{broadcast, mpsc, Notify},
+        net::UnixListener,
+        sync::{broadcast, mpsc, watch, Notify},
         task::JoinError,
-        time::{sleep, Duration},
+        time::{sleep, timeout, Duration},
     },
     tokio_stream::wrappers::ReceiverStream,
     tonic::{
@@ -21,10 +36,10 @@ use {
             server::{Server, TcpIncoming},
             Error as TransportError,
         },
-        Request, Response, Result as TonicResult, Status,
+        Code, Request, Response, Result as TonicResult, Status,
     },
     tonic_health::server::health_reporter,
-    tracing::{error, info},
+    tracing::{error, info, warn},
     yellowstone_grpc_proto::prelude::{
         geyser_server::{Geyser, GeyserServer},
         subscribe_update::UpdateOneof,
@@ -35,69 +50,249 @@ use {
     },
 };
 
+/// Kafka header with account pubkey, used with `filter_from_headers`
+pub const ACCOUNT_FILTER_HEADER: &str = "x-account-filter";
+
+/// Account from [`ACCOUNT_FILTER_HEADER`] of the Kafka message
+pub fn account_filter_header(headers: Option<&BorrowedHeaders>) -> Option<String> {
+    headers?
+        .iter()
+        .find(|header| header.key == ACCOUNT_FILTER_HEADER)
+        .and_then(|header| header.value)
+        .and_then(|value| std::str::from_utf8(value).ok())
+        .map(|value| value.trim().to_owned())
+}
+
+/// Update for gRPC clients with optional account to narrow receivers and
+/// position of the source Kafka message
+#[derive(Debug, Clone)]
+pub struct GrpcBroadcast {
+    pub update: SubscribeUpdate,
+    pub account: Option<String>,
+    pub cursor: Option<ReplayCursor>,
+}
+
+/// Sends updates to all clients of [`GrpcService`]
+#[derive(Debug, Clone)]
+pub struct GrpcBroadcastSender(broadcast::Sender<GrpcBroadcast>);
+
+impl GrpcBroadcastSender {
+    /// Send update to every client, returns `false` without clients
+    pub fn broadcast(&self, update: SubscribeUpdate) -> bool {
+        self.broadcast_with_filter(update, None)
+    }
+
+    /// Number of connected clients
+    pub fn receiver_count(&self) -> usize {
+        self.0.receiver_count()
+    }
+
+    /// Send update with all options, returns `false` without clients
+    pub fn send(&self, message: GrpcBroadcast) -> bool {
+        self.0.send(message).is_ok()
+    }
+
+    /// Send update only to clients with `account` in the subscription, see
+    /// [`ClientAccounts`]. Returns `false` without clients.
+    pub fn broadcast_with_filter(&self, update: SubscribeUpdate, account: Option<String>) -> bool {
+        self.send(GrpcBroadcast {
+            update,
+            account,
+            cursor: None,
+        })
+    }
+}
+
+/// Accounts from `accounts` filters of the latest client subscribe request,
+/// `None` if the client can receive any account: no request yet, no
+/// `accounts` filters or a filter without explicit account list (owner or
+/// data filters are not evaluated)
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientAccounts(Option<HashSet<String>>);
+
+impl ClientAccounts {
+    pub fn new(request: &SubscribeRequest) -> Self {
+        let mut accounts = HashSet::new();
+        for filter in request.accounts.values() {
+            if filter.account.is_empty() {
+                return Self(None);
+            }
+            accounts.extend(filter.account.iter().cloned());
+        }
+        Self((!accounts.is_empty()).then_some(accounts))
+    }
+
+    pub fn allows(&self, account: Option<&str>) -> bool {
+        match (&self.0, account) {
+            (Some(accounts), Some(account)) => accounts.contains(account),
+            _ => true,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GrpcService {
     subscribe_id: AtomicUsize,
     channel_capacity: usize,
-    broadcast_tx: broadcast::Sender<SubscribeUpdate>,
+    broadcast_tx: broadcast::Sender<GrpcBroadcast>,
+    shutdown_rx: watch::Receiver<bool>,
+    rate_limit_per_second: Option<u64>,
+    replay: Option<KafkaReplay>,
 }
 
 impl GrpcService {
+    /// Same service is served on every `listen` address. On shutdown clients
+    /// receive already queued updates and clean end-of-stream, server is
+    /// stopped after `drain_timeout` anyway
     #[allow(clippy::type_complexity)]
     pub fn run(
-        listen: SocketAddr,
+        listen: &[ConfigGrpcListen],
         channel_capacity: usize,
+        drain_timeout: Duration,
     ) -> anyhow::Result<(
-        broadcast::Sender<SubscribeUpdate>,
+        GrpcBroadcastSender,
         BoxFuture<'static, Result<Result<(), TransportError>, JoinError>>,
     )> {
-        // Bind service address
-        let incoming = TcpIncoming::new(
+        Self::run_with(listen, channel_capacity, drain_timeout, None, None)
+    }
+
+    /// Same as [`GrpcService::run`], but updates to every client are limited
+    /// by `rate_limit_per_second`, so a fast client can't starve others
+    #[allow(clippy::type_complexity)]
+    pub fn with_rate_limiter(
+        listen: &[ConfigGrpcListen],
+        channel_capacity: usize,
+        drain_timeout: Duration,
+        rate_limit_per_second: u64,
+    ) -> anyhow::Result<(
+        GrpcBroadcastSender,
+        BoxFuture<'static, Result<Result<(), TransportError>, JoinError>>,
+    )> {
+        Self::run_with(
             listen,
-            true,                          // tcp_nodelay
-            Some(Duration::from_secs(20)), // tcp_keepalive
+            channel_capacity,
+            drain_timeout,
+            Some(rate_limit_per_second),
+            None,
         )
-        .map_err(|error| anyhow::anyhow!(format!("{error:?}")))?;
+    }
 
+    /// All options: optional per-client rate limit and replay of recent
+    /// messages to new clients before live updates
+    #[allow(clippy::type_complexity)]
+    pub fn run_with(
+        listen: &[ConfigGrpcListen],
+        channel_capacity: usize,
+        drain_timeout: Duration,
+        rate_limit_per_second: Option<u64>,
+        replay: Option<KafkaReplay>,
+    ) -> anyhow::Result<(
+        GrpcBroadcastSender,
+        BoxFuture<'static, Result<Result<(), TransportError>, JoinError>>,
+    )> {
         // Messages to clients combined by commitment
         let (broadcast_tx, _) = broadcast::channel(channel_capacity);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
         // Run Server
         let service = GeyserServer::new(Self {
             subscribe_id: AtomicUsize::new(0),
             channel_capacity,
             broadcast_tx: broadcast_tx.clone(),
+            shutdown_rx,
+            rate_limit_per_second,
+            replay,
         })
         .accept_compressed(CompressionEncoding::Gzip)
         .send_compressed(CompressionEncoding::Gzip)
         .accept_compressed(CompressionEncoding::Zstd)
         .send_compressed(CompressionEncoding::Zstd);
 
-        let shutdown = Arc::new(Notify::new());
-        let shutdown_grpc = Arc::clone(&shutdown);
+        // gRPC Health check service
+        let (mut health_reporter, health_service) = health_reporter();
+        let router = || {
+            Server::builder()
+                .http2_keepalive_interval(Some(Duration::from_secs(5)))
+                .add_service(health_service.clone())
+                .add_service(service.clone())
+        };
+
+        // Bind service addresses, all of them should be available
+        let mut servers: Vec<BoxFuture<'static, Result<(), TransportError>>> = vec![];
+        for addr in listen {
+            let mut shutdown_rx = shutdown_tx.subscribe();
+            let signal = async move {
+                let _ = shutdown_rx.wait_for(|shutdown| *shutdown).await;
+            };
+            let server = match addr {
+                ConfigGrpcListen::Tcp(tcp_addr) => {
+                    let incoming = TcpIncoming::new(
+                        *tcp_addr,
+                        true,                          // tcp_nodelay
+                        Some(Duration::from_secs(20)), // tcp_keepalive
+                    )
+                    .map_err(|error| anyhow::anyhow!("failed to bind {addr}: {error:?}"))?;
+                    router()
+                        .serve_with_incoming_shutdown(incoming, signal)
+                        .boxed()
+                }
+                ConfigGrpcListen::Unix(path) => {
+                    // socket file is not removed on exit
+                    if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                        fs::remove_file(path).map_err(|error| {
+                            anyhow::anyhow!("failed to remove stale socket {addr}: {error}")
+                        })?;
+                    }
+                    let listener = UnixListener::bind(path)
+                        .map_err(|error| anyhow::anyhow!("failed to bind {addr}: {error}"))?;
+                    let incoming = stream::unfold(listener, |listener| async move {
+                        let stream = listener.accept().await.map(|(stream, _addr)| stream);
+                        Some((stream, listener))
+                    });
+                    router()
+                        .serve_with_incoming_shutdown(incoming, signal)
+                        .boxed()
+                }
+            };
+            info!("gRPC server listening on {addr}");
+            servers.push(server);
+        }
 
         let server = tokio::spawn(async move {
-            // gRPC Health check service
-            let (mut health_reporter, health_service) = health_reporter();
             health_reporter.set_serving::<GeyserServer<Self>>().await;
-
-            Server::builder()
-                .http2_keepalive_interval(Some(Duration::from_secs(5)))
-                .add_service(health_service)
-                .add_service(service)
-                .serve_with_incoming_shutdown(incoming, shutdown_grpc.notified())
-                .await
+            try_join_all(servers).await.map(|_| ())
         });
         let shutdown = async move {
-            shutdown.notify_one();
-            server.await
+            let _ = shutdown_tx.send(true);
+            let mut server = server;
+            match timeout(drain_timeout, &mut server).await {
+                Ok(result) => result,
+                Err(_elapsed) => {
+                    warn!("gRPC clients are not drained in {drain_timeout:?}, stop server");
+                    server.abort();
+                    Ok(Ok(()))
+                }
+            }
         }
         .boxed();
 
-        Ok((broadcast_tx, shutdown))
+        Ok((GrpcBroadcastSender(broadcast_tx), shutdown))
     }
 }
 
+/// Wait for a token from the client limiter and send update
+async fn rate_limited_send(
+    id: usize,
+    sender: &mpsc::Sender<TonicResult<SubscribeUpdate>>,
+    message: SubscribeUpdate,
+    limiter: &mut TokenBucket,
+) -> Result<(), mpsc::error::TrySendError<TonicResult<SubscribeUpdate>>> {
+    if limiter.acquire().await {
+        metrics::client_rate_limited_inc(id);
+    }
+    sender.try_send(Ok(message))
+}
+
 #[tonic::async_trait]
 impl Geyser for GrpcService {
     type SubscribeStream = ReceiverStream<TonicResult<SubscribeUpdate>>;
@@ -106,6 +301,34 @@ impl Geyser for GrpcService {
         &self,
         mut request: Request<Streaming<SubscribeRequest>>,
     ) -> TonicResult<Response<Self::SubscribeStream>> {
+        let cursor = request
+            .metadata()
+            .get(RESUME_CURSOR_HEADER)
+            .map(|value| {
+                value
+                    .to_str()
+                    .map_err(anyhow::Error::from)
+                    .and_then(str::parse::<ReplayCursor>)
+                    .map_err(|error| {
+                        Status::invalid_argument(format!(
+                            "invalid `{RESUME_CURSOR_HEADER}`: {error}"
+                        ))
+                    })
+            })
+            .transpose()?;
+        let include_cursor = request
+            .metadata()
+            .get(INCLUDE_CURSOR_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value == "true");
+        if let Some(cursor) = &cursor {
+            match &self.replay {
+                Some(replay) if replay.topic == cursor.topic => {}
+                Some(_) => return Err(Status::invalid_argument("cursor of another topic")),
+                None => return Err(Status::failed_precondition("replay is not enabled")),
+            }
+        }
+
         let id = self.subscribe_id.fetch_add(1, Ordering::Relaxed);
         let (stream_tx, stream_rx) = mpsc::channel(self.channel_capacity);
         let notify_client = Arc::new(Notify::new());
@@ -142,6 +365,7 @@ impl Geyser for GrpcService {
             }
         });
 
+        let (accounts_tx, accounts_rx) = watch::channel(ClientAccounts::default());
         let incoming_client = Arc::clone(&notify_client);
         let incoming_exit = Arc::clone(&notify_exit2);
         tokio::spawn(async move {
@@ -152,7 +376,9 @@ impl Geyser for GrpcService {
                 tokio::select! {
                     _ = &mut exit => break,
                     message = request.get_mut().message() => match message {
-                        Ok(Some(_request)) => {}
+                        Ok(Some(request)) => {
+                            let _ = accounts_tx.send(ClientAccounts::new(&request));
+                        }
                         Ok(None) => break,
                         Err(_error) => {
                             let _ = incoming_client.notify_one();
@@ -164,42 +390,96 @@ impl Geyser for GrpcService {
         });
 
         let mut messages_rx = self.broadcast_tx.subscribe();
+        let mut shutdown_rx = self.shutdown_rx.clone();
+        let mut limiter = self.rate_limit_per_second.map(TokenBucket::new);
+        let replay = self.replay.clone();
         tokio::spawn(async move {
             info!("client #{id}: new");
+            metrics::active_subscribers_inc();
+            let mut client_cursor = ClientCursor::new(include_cursor);
+            // live updates are buffered by `messages_rx` while replay is running,
+            // some messages can be sent twice
+            if let Some(replay) = replay {
+                match replay
+                    .replay(
+                        id,
+                        cursor.as_ref(),
+                        &accounts_rx,
+                        &mut client_cursor,
+                        &stream_tx,
+                    )
+                    .await
+                {
+                    Ok(count) => info!("client #{id}: replayed {count} messages"),
+                    Err(error) => warn!("client #{id}: failed to replay messages: {error}"),
+                }
+            }
             loop {
-                tokio::select! {
+                let message = tokio::select! {
                     _ = notify_client.notified() => break,
-                    message = messages_rx.recv() => {
-                        match message {
-                            Ok(message) => {
-                                match stream_tx.try_send(Ok(message)) {
-                                    Ok(()) => {}
-                                    Err(mpsc::error::TrySendError::Full(_)) => {
-                                        error!("client #{id}: lagged to send update");
-                                        tokio::spawn(async move {
-                                            let _ = stream_tx.send(Err(Status::internal("lagged"))).await;
-                                        });
-                                        break;
-                                    }
-                                    Err(mpsc::error::TrySendError::Closed(_)) => {
-                                        error!("client #{id}: stream closed");
-                                        break;
-                                    }
-                                }
+                    _ = shutdown_rx.wait_for(|shutdown| *shutdown) => {
+                        // send queued updates, stream is closed with `OK` status
+                        while let Ok(mut message) = messages_rx.try_recv() {
+                            if !accounts_rx.borrow().allows(message.account.as_deref()) {
+                                continue;
                             }
-                            Err(broadcast::error::RecvError::Closed) => break,
-                            Err(broadcast::error::RecvError::Lagged(_)) => {
-                                info!("client #{id}: lagged to receive geyser messages");
+                            client_cursor.attach(&mut message.update, message.cursor.as_ref());
+                            if stream_tx.try_send(Ok(message.update)).is_err() {
+                                break;
+                            }
+                            client_cursor.sent(message.cursor);
+                        }
+                        if client_cursor.last().is_some() {
+                            let status = client_cursor.trailer(Status::new(Code::Ok, "shutdown"));
+                            let _ = stream_tx.try_send(Err(status));
+                        }
+                        info!("client #{id}: drained on shutdown");
+                        break;
+                    }
+                    message = messages_rx.recv() => message,
+                };
+                match message {
+                    Ok(mut message) => {
+                        if !accounts_rx.borrow().allows(message.account.as_deref()) {
+                            continue;
+                        }
+                        client_cursor.attach(&mut message.update, message.cursor.as_ref());
+                        let result = match &mut limiter {
+                            Some(limiter) => {
+                                rate_limited_send(id, &stream_tx, message.update, limiter).await
+                            }
+                            None => stream_tx.try_send(Ok(message.update)),
+                        };
+                        match result {
+                            Ok(()) => client_cursor.sent(message.cursor),
+                            Err(mpsc::error::TrySendError::Full(_)) => {
+                                error!("client #{id}: lagged to send update");
+                                let status = client_cursor.trailer(Status::internal("lagged"));
                                 tokio::spawn(async move {
-                                    let _ = stream_tx.send(Err(Status::internal("lagged"))).await;
+                                    let _ = stream_tx.send(Err(status)).await;
                                 });
                                 break;
                             }
+                            Err(mpsc::error::TrySendError::Closed(_)) => {
+                                error!("client #{id}: stream closed");
+                                break;
+                            }
                         }
                     }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        info!("client #{id}: lagged to receive geyser messages");
+                        let status = client_cursor.trailer(Status::internal("lagged"));
+                        tokio::spawn(async move {
+                            let _ = stream_tx.send(Err(status)).await;
+                        });
+                        break;
+                    }
                 }
             }
             info!("client #{id}: removed");
+            metrics::active_subscribers_dec();
+            metrics::client_rate_limited_remove(id);
             notify_exit1.notify_one();
             notify_exit2.notify_one();
         });
@@ -250,3 +530,51 @@ impl Geyser for GrpcService {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::ClientAccounts,
+        std::collections::HashMap,
+        yellowstone_grpc_proto::prelude::{SubscribeRequest, SubscribeRequestFilterAccounts},
+    };
+
+    fn request(filters: &[&[&str]]) -> SubscribeRequest {
+        SubscribeRequest {
+            accounts: filters
+                .iter()
+                .enumerate()
+                .map(|(idx, accounts)| {
+                    let filter = SubscribeRequestFilterAccounts {
+                        account: accounts.iter().map(|account| account.to_string()).collect(),
+                        ..Default::default()
+                    };
+                    (idx.to_string(), filter)
+                })
+                .collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn client_accounts() {
+        // no subscribe request yet
+        let accounts = ClientAccounts::default();
+        assert!(accounts.allows(Some("A")));
+        assert!(accounts.allows(None));
+
+        let accounts = ClientAccounts::new(&request(&[&["A", "B"], &["C"]]));
+        assert!(accounts.allows(Some("A")));
+        assert!(accounts.allows(Some("C")));
+        assert!(!accounts.allows(Some("D")));
+        // messages without header are sent to everyone
+        assert!(accounts.allows(None));
+
+        // filter without account list, e.g. by owner
+        let accounts = ClientAccounts::new(&request(&[&["A"], &[]]));
+        assert!(accounts.allows(Some("D")));
+
+        let accounts = ClientAccounts::new(&request(&[]));
+        assert!(accounts.allows(Some("D")));
+    }
+}
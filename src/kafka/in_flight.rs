@@ -0,0 +1,193 @@
+use {
+    crate::{kafka::metrics, metrics::GprcMessageKind},
+    dashmap::DashMap,
+    prometheus::IntGauge,
+    std::{collections::HashMap, sync::Arc},
+    tokio::sync::{OwnedSemaphorePermit, Semaphore},
+};
+
+/// Limits number of in-flight tasks globally and per slot, per slot
+/// semaphores are removed once no task of the slot is in-flight
+#[derive(Debug)]
+pub struct InFlightLimiter {
+    global: Arc<Semaphore>,
+    per_slot: Arc<DashMap<u64, Arc<Semaphore>>>,
+    max_in_flight_per_slot: usize,
+}
+
+impl InFlightLimiter {
+    pub fn new(max_in_flight: usize, max_in_flight_per_slot: usize) -> Self {
+        Self {
+            global: Arc::new(Semaphore::new(max_in_flight)),
+            per_slot: Arc::new(DashMap::new()),
+            max_in_flight_per_slot,
+        }
+    }
+
+    /// Wait for both global and slot permits, task is in-flight until the
+    /// returned permit is dropped
+    pub async fn acquire(&self, slot: u64) -> InFlightPermit {
+        // declared first to be dropped last if cancelled while waiting
+        let mut permit = InFlightPermit {
+            slot,
+            per_slot: Arc::clone(&self.per_slot),
+            permits: None,
+        };
+        let slot_permit = Arc::clone(
+            self.per_slot
+                .entry(slot)
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max_in_flight_per_slot)))
+                .value(),
+        )
+        .acquire_owned()
+        .await
+        .expect("semaphore never closed");
+        let global_permit = Arc::clone(&self.global)
+            .acquire_owned()
+            .await
+            .expect("semaphore never closed");
+        metrics::in_flight_tasks_inc();
+        permit.permits = Some((slot_permit, global_permit));
+        permit
+    }
+
+    pub fn slots(&self) -> usize {
+        self.per_slot.len()
+    }
+}
+
+#[derive(Debug)]
+pub struct InFlightPermit {
+    slot: u64,
+    per_slot: Arc<DashMap<u64, Arc<Semaphore>>>,
+    permits: Option<(OwnedSemaphorePermit, OwnedSemaphorePermit)>,
+}
+
+impl Drop for InFlightPermit {
+    fn drop(&mut self) {
+        if self.permits.take().is_some() {
+            metrics::in_flight_tasks_dec();
+        }
+        // only the map holds the semaphore when there are no permits and waiters
+        self.per_slot.remove_if(&self.slot, |_slot, semaphore| {
+            Arc::strong_count(semaphore) == 1
+        });
+    }
+}
+
+/// Limits number of in-flight produces per kind, so a flood of one kind
+/// can't take the whole queue. Every kind is counted, limited or not.
+/// Callers wait for the permit in order of messages, so a kind at its limit
+/// delays messages of other kinds behind it.
+#[derive(Debug, Default)]
+pub struct KindInFlightLimiter {
+    limits: HashMap<&'static str, Arc<Semaphore>>,
+}
+
+impl KindInFlightLimiter {
+    pub fn new(limits: &HashMap<String, usize>) -> anyhow::Result<Self> {
+        let limits = limits
+            .iter()
+            .map(|(kind, limit)| {
+                let kind = GprcMessageKind::from_name(kind)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("unknown kind in `kafka_queue_size_per_kind`: {kind}")
+                    })?
+                    .as_str();
+                anyhow::ensure!(
+                    *limit > 0,
+                    "`kafka_queue_size_per_kind` for {kind} should be greater than 0"
+                );
+                Ok((kind, Arc::new(Semaphore::new(*limit))))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { limits })
+    }
+
+    /// Wait for a permit if the kind is limited, produce is in-flight until
+    /// the returned permit is dropped
+    pub async fn acquire(&self, kind: GprcMessageKind) -> KindInFlightPermit {
+        let permit = match self.limits.get(kind.as_str()) {
+            Some(semaphore) => Some(
+                Arc::clone(semaphore)
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore never closed"),
+            ),
+            None => None,
+        };
+        let gauge = metrics::in_flight_by_kind(kind);
+        gauge.inc();
+        KindInFlightPermit {
+            gauge,
+            _permit: permit,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct KindInFlightPermit {
+    gauge: IntGauge,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for KindInFlightPermit {
+    fn drop(&mut self) {
+        self.gauge.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{InFlightLimiter, KindInFlightLimiter},
+        crate::metrics::GprcMessageKind,
+        std::{collections::HashMap, time::Duration},
+        tokio::time::timeout,
+    };
+
+    #[tokio::test]
+    async fn in_flight_limits() {
+        let limiter = InFlightLimiter::new(3, 2);
+        let first = limiter.acquire(10).await;
+        let _second = limiter.acquire(10).await;
+        // slot limit reached
+        assert!(timeout(Duration::from_millis(10), limiter.acquire(10))
+            .await
+            .is_err());
+        let third = limiter.acquire(11).await;
+        // global limit reached
+        assert!(timeout(Duration::from_millis(10), limiter.acquire(12))
+            .await
+            .is_err());
+
+        drop(third);
+        assert_eq!(limiter.slots(), 1);
+        drop(first);
+        let _first = limiter.acquire(10).await;
+        assert_eq!(limiter.slots(), 1);
+    }
+
+    #[tokio::test]
+    async fn kind_in_flight_limits() {
+        let limits = HashMap::from([("account".to_owned(), 1)]);
+        let limiter = KindInFlightLimiter::new(&limits).unwrap();
+        let account = limiter.acquire(GprcMessageKind::Account).await;
+        assert!(timeout(
+            Duration::from_millis(10),
+            limiter.acquire(GprcMessageKind::Account)
+        )
+        .await
+        .is_err());
+        // other kinds are not blocked by accounts
+        let _transaction = limiter.acquire(GprcMessageKind::Transaction).await;
+        let _transaction = limiter.acquire(GprcMessageKind::Transaction).await;
+        drop(account);
+        let _account = limiter.acquire(GprcMessageKind::Account).await;
+
+        let limits = HashMap::from([("unknown".to_owned(), 1)]);
+        assert!(KindInFlightLimiter::new(&limits).is_err());
+        let limits = HashMap::from([("account".to_owned(), 0)]);
+        assert!(KindInFlightLimiter::new(&limits).is_err());
+    }
+}
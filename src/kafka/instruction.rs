@@ -0,0 +1,300 @@
+use {
+    crate::generated::prelude::SubscribeUpdateTransactionInfo as JsonTransactionInfo,
+    serde_json::{json, Value},
+    std::collections::HashMap,
+};
+
+pub const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
+pub const SPL_TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+/// Decoder of instructions of one program, `accounts` are base58 pubkeys of
+/// instruction accounts in order
+pub trait InstructionDecoder: Send + Sync {
+    fn program_id(&self) -> &str;
+
+    fn decode(&self, data: &[u8], accounts: &[String]) -> Option<Value>;
+}
+
+/// Decoders by program id
+#[derive(Default)]
+pub struct InstructionDecoders {
+    decoders: HashMap<String, Box<dyn InstructionDecoder>>,
+}
+
+impl InstructionDecoders {
+    /// Registry with System Program and SPL Token decoders
+    pub fn with_builtin() -> Self {
+        let mut decoders = Self::default();
+        decoders.register(Box::new(SystemProgramDecoder));
+        decoders.register(Box::new(SplTokenDecoder));
+        decoders
+    }
+
+    /// Register decoder, replaces decoder with the same program id
+    pub fn register(&mut self, decoder: Box<dyn InstructionDecoder>) {
+        self.decoders
+            .insert(decoder.program_id().to_owned(), decoder);
+    }
+
+    /// Add `decoded_instruction` to every instruction of the transaction JSON
+    /// with known program, account keys include loaded addresses
+    pub fn decode_transaction(&self, tx: &JsonTransactionInfo, value: &mut Value) {
+        let Some(message) = tx
+            .transaction
+            .as_ref()
+            .and_then(|transaction| transaction.message.as_ref())
+        else {
+            return;
+        };
+        let mut account_keys = message.account_keys.iter().collect::<Vec<_>>();
+        if let Some(meta) = &tx.meta {
+            account_keys.extend(meta.loaded_writable_addresses.iter());
+            account_keys.extend(meta.loaded_readonly_addresses.iter());
+        }
+        let pubkey = |index: usize| {
+            account_keys
+                .get(index)
+                .map(|key| bs58::encode(key).into_string())
+        };
+
+        let Some(Value::Array(instructions)) =
+            value.pointer_mut("/transaction/message/instructions")
+        else {
+            return;
+        };
+        for (instruction, json) in message.instructions.iter().zip(instructions.iter_mut()) {
+            let Some(decoder) = pubkey(instruction.program_id_index as usize)
+                .and_then(|program_id| self.decoders.get(&program_id))
+            else {
+                continue;
+            };
+            let Some(accounts) = instruction
+                .accounts
+                .iter()
+                .map(|index| pubkey(*index as usize))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            if let (Some(decoded), Value::Object(object)) =
+                (decoder.decode(&instruction.data, &accounts), json)
+            {
+                object.insert("decoded_instruction".to_owned(), decoded);
+            }
+        }
+    }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Option<String> {
+    data.get(offset..offset + 32)
+        .map(|bytes| bs58::encode(bytes).into_string())
+}
+
+/// `createAccount`, `assign`, `transfer` and `allocate`
+struct SystemProgramDecoder;
+
+impl InstructionDecoder for SystemProgramDecoder {
+    fn program_id(&self) -> &str {
+        SYSTEM_PROGRAM_ID
+    }
+
+    fn decode(&self, data: &[u8], accounts: &[String]) -> Option<Value> {
+        let tag = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?);
+        let (kind, info) = match tag {
+            0 => (
+                "createAccount",
+                json!({
+                    "source": accounts.first()?,
+                    "newAccount": accounts.get(1)?,
+                    "lamports": read_u64(data, 4)?,
+                    "space": read_u64(data, 12)?,
+                    "owner": read_pubkey(data, 20)?,
+                }),
+            ),
+            1 => (
+                "assign",
+                json!({"account": accounts.first()?, "owner": read_pubkey(data, 4)?}),
+            ),
+            2 => (
+                "transfer",
+                json!({
+                    "source": accounts.first()?,
+                    "destination": accounts.get(1)?,
+                    "lamports": read_u64(data, 4)?,
+                }),
+            ),
+            8 => (
+                "allocate",
+                json!({"account": accounts.first()?, "space": read_u64(data, 4)?}),
+            ),
+            _ => return None,
+        };
+        Some(json!({"program": "system", "type": kind, "info": info}))
+    }
+}
+
+/// `initializeAccount`, `transfer`, `approve`, `mintTo`, `burn`,
+/// `closeAccount` and `transferChecked`
+struct SplTokenDecoder;
+
+impl InstructionDecoder for SplTokenDecoder {
+    fn program_id(&self) -> &str {
+        SPL_TOKEN_PROGRAM_ID
+    }
+
+    fn decode(&self, data: &[u8], accounts: &[String]) -> Option<Value> {
+        let (kind, info) = match data.first()? {
+            1 => (
+                "initializeAccount",
+                json!({
+                    "account": accounts.first()?,
+                    "mint": accounts.get(1)?,
+                    "owner": accounts.get(2)?,
+                }),
+            ),
+            3 => (
+                "transfer",
+                json!({
+                    "source": accounts.first()?,
+                    "destination": accounts.get(1)?,
+                    "authority": accounts.get(2)?,
+                    "amount": read_u64(data, 1)?.to_string(),
+                }),
+            ),
+            4 => (
+                "approve",
+                json!({
+                    "source": accounts.first()?,
+                    "delegate": accounts.get(1)?,
+                    "owner": accounts.get(2)?,
+                    "amount": read_u64(data, 1)?.to_string(),
+                }),
+            ),
+            7 => (
+                "mintTo",
+                json!({
+                    "mint": accounts.first()?,
+                    "account": accounts.get(1)?,
+                    "mintAuthority": accounts.get(2)?,
+                    "amount": read_u64(data, 1)?.to_string(),
+                }),
+            ),
+            8 => (
+                "burn",
+                json!({
+                    "account": accounts.first()?,
+                    "mint": accounts.get(1)?,
+                    "authority": accounts.get(2)?,
+                    "amount": read_u64(data, 1)?.to_string(),
+                }),
+            ),
+            9 => (
+                "closeAccount",
+                json!({
+                    "account": accounts.first()?,
+                    "destination": accounts.get(1)?,
+                    "owner": accounts.get(2)?,
+                }),
+            ),
+            12 => (
+                "transferChecked",
+                json!({
+                    "source": accounts.first()?,
+                    "mint": accounts.get(1)?,
+                    "destination": accounts.get(2)?,
+                    "authority": accounts.get(3)?,
+                    "amount": read_u64(data, 1)?.to_string(),
+                    "decimals": data.get(9)?,
+                }),
+            ),
+            _ => return None,
+        };
+        Some(json!({"program": "spl-token", "type": kind, "info": info}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{InstructionDecoders, SPL_TOKEN_PROGRAM_ID, SYSTEM_PROGRAM_ID},
+        crate::generated::{
+            prelude::SubscribeUpdateTransactionInfo as JsonTransactionInfo,
+            solana::storage::confirmed_block::{
+                CompiledInstruction, Message, Transaction, TransactionStatusMeta,
+            },
+        },
+        serde_json::json,
+    };
+
+    #[test]
+    fn decode_builtin() {
+        let system = bs58::decode(SYSTEM_PROGRAM_ID).into_vec().unwrap();
+        let token = bs58::decode(SPL_TOKEN_PROGRAM_ID).into_vec().unwrap();
+        let mut transfer = 2u32.to_le_bytes().to_vec();
+        transfer.extend_from_slice(&5_000u64.to_le_bytes());
+        let mut transfer_checked = vec![12];
+        transfer_checked.extend_from_slice(&1_000_000u64.to_le_bytes());
+        transfer_checked.push(6);
+        let tx = JsonTransactionInfo {
+            transaction: Some(Transaction {
+                signatures: vec![],
+                message: Some(Message {
+                    account_keys: vec![vec![1; 32], vec![2; 32], system, token],
+                    instructions: vec![
+                        CompiledInstruction {
+                            program_id_index: 2,
+                            accounts: vec![0, 1],
+                            data: transfer,
+                        },
+                        CompiledInstruction {
+                            program_id_index: 3,
+                            accounts: vec![0, 4, 1, 0],
+                            data: transfer_checked,
+                        },
+                        CompiledInstruction {
+                            program_id_index: 0,
+                            accounts: vec![],
+                            data: vec![1, 2, 3],
+                        },
+                    ],
+                    ..Default::default()
+                }),
+            }),
+            meta: Some(TransactionStatusMeta {
+                loaded_readonly_addresses: vec![vec![3; 32]],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut value = serde_json::to_value(&tx).unwrap();
+        InstructionDecoders::with_builtin().decode_transaction(&tx, &mut value);
+
+        let key = |byte: u8| bs58::encode([byte; 32]).into_string();
+        let instructions = &value["transaction"]["message"]["instructions"];
+        assert_eq!(
+            instructions[0]["decoded_instruction"],
+            json!({
+                "program": "system",
+                "type": "transfer",
+                "info": {"source": key(1), "destination": key(2), "lamports": 5_000},
+            })
+        );
+        assert_eq!(
+            instructions[1]["decoded_instruction"]["info"],
+            json!({
+                "source": key(1),
+                "mint": key(3),
+                "destination": key(2),
+                "authority": key(1),
+                "amount": "1000000",
+                "decimals": 6,
+            })
+        );
+        assert!(instructions[2].get("decoded_instruction").is_none());
+    }
+}
@@ -0,0 +1,122 @@
+use {
+    super::{config::ConfigLeaderElection, metrics},
+    rdkafka::{
+        client::ClientContext,
+        config::{ClientConfig, FromClientConfigAndContext},
+        consumer::{Consumer, ConsumerContext, Rebalance, StreamConsumer},
+    },
+    std::sync::atomic::{AtomicU8, Ordering},
+    tokio::{sync::watch, task::JoinHandle},
+    tracing::{info, warn},
+};
+
+static ROLE: AtomicU8 = AtomicU8::new(LeaderRole::Disabled as u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LeaderRole {
+    Disabled = 0,
+    Leader = 1,
+    Standby = 2,
+}
+
+impl LeaderRole {
+    /// Current role of the process
+    pub fn get() -> Self {
+        match ROLE.load(Ordering::Relaxed) {
+            1 => Self::Leader,
+            2 => Self::Standby,
+            _ => Self::Disabled,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::Leader => "leader",
+            Self::Standby => "standby",
+        }
+    }
+}
+
+struct LeaderContext {
+    leader_tx: watch::Sender<bool>,
+}
+
+impl LeaderContext {
+    fn set_leader(&self, leader: bool) {
+        let role = if leader {
+            LeaderRole::Leader
+        } else {
+            LeaderRole::Standby
+        };
+        if LeaderRole::get() != role {
+            info!("leader election: role changed to {}", role.as_str());
+        }
+        ROLE.store(role as u8, Ordering::Relaxed);
+        metrics::leader_set(leader);
+        let _ = self.leader_tx.send(leader);
+    }
+}
+
+impl ClientContext for LeaderContext {}
+
+impl ConsumerContext for LeaderContext {
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        // stop producing before partition is handed over to other instance
+        if let Rebalance::Revoke(_) = rebalance {
+            self.set_leader(false);
+        }
+    }
+
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        match rebalance {
+            Rebalance::Assign(partitions) => self.set_leader(partitions.count() > 0),
+            Rebalance::Revoke(_) => {}
+            Rebalance::Error(error) => {
+                warn!("leader election: rebalance error: {error}");
+                self.set_leader(false);
+            }
+        }
+    }
+}
+
+/// Leader is the group member with assigned partition of the single-partition
+/// topic. Standby takes over once leader leaves the group or misses
+/// `session.timeout.ms`.
+#[derive(Debug)]
+pub struct LeaderElection;
+
+impl LeaderElection {
+    pub fn spawn(
+        kafka_config: &ClientConfig,
+        config: &ConfigLeaderElection,
+    ) -> anyhow::Result<(watch::Receiver<bool>, JoinHandle<()>)> {
+        let mut kafka_config = kafka_config.clone();
+        kafka_config
+            .set("group.id", &config.group_id)
+            .set("enable.auto.commit", "false")
+            .set("session.timeout.ms", config.session_timeout_ms.to_string());
+
+        let (leader_tx, leader_rx) = watch::channel(false);
+        let context = LeaderContext { leader_tx };
+        context.set_leader(false);
+        let consumer: StreamConsumer<_> =
+            StreamConsumer::from_config_and_context(&kafka_config, context)?;
+        consumer.subscribe(&[&config.topic])?;
+        info!(
+            "leader election: joined group {} on topic {}",
+            config.group_id, config.topic
+        );
+
+        let task = tokio::spawn(async move {
+            // rebalance callbacks are triggered only while consumer is polled
+            loop {
+                if let Err(error) = consumer.recv().await {
+                    warn!("leader election: consumer error: {error}");
+                }
+            }
+        });
+        Ok((leader_rx, task))
+    }
+}
@@ -1,18 +1,103 @@
 use {
+    super::{config::ConfigKafkaStartupRetry, readiness},
     crate::metrics::GprcMessageKind,
-    prometheus::{GaugeVec, IntCounter, IntCounterVec, Opts},
+    prometheus::{
+        GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
+        IntGaugeVec, Opts,
+    },
     rdkafka::{
         client::{ClientContext, DefaultClientContext},
         config::{ClientConfig, FromClientConfigAndContext, RDKafkaLogLevel},
-        consumer::{ConsumerContext, StreamConsumer},
+        consumer::{Consumer, ConsumerContext, Rebalance, StreamConsumer},
         error::{KafkaError, KafkaResult},
         producer::FutureProducer,
         statistics::Statistics,
+        topic_partition_list::{Offset, TopicPartitionList},
+    },
+    serde_json::Value,
+    std::{
+        collections::{BTreeMap, HashMap},
+        sync::{
+            atomic::{AtomicU64, AtomicUsize, Ordering},
+            Arc, Mutex, OnceLock, Weak,
+        },
+        time::{Duration, Instant},
     },
-    std::sync::Mutex,
-    tokio::sync::oneshot,
+    tokio::{
+        sync::oneshot,
+        task::{self, JoinHandle},
+        time::{interval, sleep, MissedTickBehavior},
+    },
+    tracing::{info, trace, warn},
 };
 
+/// Gap above this size means that downstream probably missed data
+pub const SLOT_GAP_ALERT: u64 = 100;
+
+static LAST_SEEN_SLOT: AtomicU64 = AtomicU64::new(0);
+
+/// Default buckets for latency histograms: Solana latencies range from
+/// sub-millisecond (in-memory dedup) to seconds (Kafka acks under load),
+/// prometheus defaults start at 5ms and miss the lower end
+pub const DEFAULT_LATENCY_BUCKETS: [f64; 10] =
+    [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// Subscription can take seconds to minutes to yield data with backfill
+pub const DEFAULT_TTFM_BUCKETS: [f64; 10] =
+    [0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+const HISTOGRAMS: [&str; 3] = [
+    "message_latency_seconds",
+    "dedup_backend_latency_seconds",
+    "reconnect_ttfm_seconds",
+];
+
+static HISTOGRAM_BUCKETS: OnceLock<HashMap<String, Vec<f64>>> = OnceLock::new();
+
+/// Override histogram buckets by metric name, should be called before
+/// metrics are used or registered
+pub fn set_histogram_buckets(buckets: HashMap<String, Vec<f64>>) -> anyhow::Result<()> {
+    for (name, values) in buckets.iter() {
+        anyhow::ensure!(
+            HISTOGRAMS.contains(&name.as_str()),
+            "unknown histogram in `histogram_buckets`: {name}"
+        );
+        anyhow::ensure!(!values.is_empty(), "buckets for {name} should not be empty");
+        anyhow::ensure!(
+            values.windows(2).all(|pair| pair[0] < pair[1]),
+            "buckets for {name} should be sorted"
+        );
+    }
+    HISTOGRAM_BUCKETS
+        .set(buckets)
+        .map_err(|_| anyhow::anyhow!("histogram buckets already set"))
+}
+
+fn histogram_opts(name: &str, help: &str, default_buckets: &[f64]) -> HistogramOpts {
+    let buckets = HISTOGRAM_BUCKETS
+        .get()
+        .and_then(|buckets| buckets.get(name))
+        .cloned()
+        .unwrap_or_else(|| default_buckets.to_vec());
+    HistogramOpts::new(name, help).buckets(buckets)
+}
+
+/// Last librdkafka statistics by client name, served on `/stats`
+static KAFKA_STATS_LAST: Mutex<BTreeMap<String, Value>> = Mutex::new(BTreeMap::new());
+
+/// Last librdkafka statistics of every client, requires
+/// `statistics.interval.ms`
+pub fn kafka_stats_last() -> Value {
+    Value::Object(
+        KAFKA_STATS_LAST
+            .lock()
+            .expect("unpoisoned")
+            .iter()
+            .map(|(name, stats)| (name.clone(), stats.clone()))
+            .collect(),
+    )
+}
+
 lazy_static::lazy_static! {
     pub(crate) static ref KAFKA_STATS: GaugeVec = GaugeVec::new(
         Opts::new("kafka_stats", "librdkafka metrics"),
@@ -23,20 +108,214 @@ lazy_static::lazy_static! {
         "kafka_dedup_total", "Total number of deduplicated messages"
     ).unwrap();
 
+    pub(crate) static ref NEAR_DUPLICATE_DEDUP_TOTAL: IntCounter = IntCounter::new(
+        "near_duplicate_dedup_total", "Total number of near-duplicate messages skipped by dedup"
+    ).unwrap();
+
+    pub(crate) static ref DEDUP_LAG_SKIP_TOTAL: IntCounter = IntCounter::new(
+        "dedup_lag_skip_total", "Total number of messages skipped by dedup due to consumer lag"
+    ).unwrap();
+
+    pub(crate) static ref DEDUP_EPOCH_RESETS_TOTAL: IntCounter = IntCounter::new(
+        "dedup_epoch_resets_total", "Total number of dedup state resets on epoch transition"
+    ).unwrap();
+
+    pub(crate) static ref PER_SLOT_LIMIT_EXCEEDED_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("per_slot_limit_exceeded_total", "Total number of messages skipped by `max_messages_per_slot`"),
+        &["kind"]
+    ).unwrap();
+
+    pub(crate) static ref PIPELINE_RESTARTS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("pipeline_restarts_total", "Total number of action restarts after failure"),
+        &["action"]
+    ).unwrap();
+
+    pub(crate) static ref CLIENT_RATE_LIMITED_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("client_rate_limited_total", "Total number of updates delayed by per-client rate limit"),
+        &["client_id"]
+    ).unwrap();
+
+    pub(crate) static ref SERIALIZE_ERRORS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("serialize_errors_total", "Total number of JSON fields replaced with placeholder by field"),
+        &["field"]
+    ).unwrap();
+
+    pub(crate) static ref DECODE_ERRORS_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("decode_errors_total", "Total number of messages failed to decode by error kind"),
+        &["kind"]
+    ).unwrap();
+
     pub(crate) static ref KAFKA_RECV_TOTAL: IntCounter = IntCounter::new(
         "kafka_recv_total", "Total number of received messages"
     ).unwrap();
 
+    pub(crate) static ref KAFKA_REBALANCE_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("kafka_rebalance_total", "Total number of consumer group rebalance events by type"),
+        &["event"]
+    ).unwrap();
+
     pub(crate) static ref KAFKA_SENT_TOTAL: IntCounterVec = IntCounterVec::new(
         Opts::new("kafka_sent_total", "Total number of uploaded messages by type"),
         &["kind"]
     ).unwrap();
+
+    pub(crate) static ref KAFKA_BYTES_PRODUCED_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("kafka_bytes_produced_total", "Total size of uploaded payloads by type"),
+        &["kind"]
+    ).unwrap();
+
+    pub(crate) static ref KAFKA_DUAL_WRITE_SENT_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("kafka_dual_write_sent_total", "Total number of uploaded messages in dual write mode by format"),
+        &["format"]
+    ).unwrap();
+
+    pub(crate) static ref KAFKA_DEAD_LETTER_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("kafka_dead_letter_total", "Total number of messages routed to dead letter topic by original topic"),
+        &["topic"]
+    ).unwrap();
+
+    pub(crate) static ref KAFKA_HEARTBEAT_SENT_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("kafka_heartbeat_sent_total", "Total number of uploaded heartbeats by gRPC source"),
+        &["source"]
+    ).unwrap();
+
+    pub(crate) static ref KAFKA_SAMPLED_OUT_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("kafka_sampled_out_total", "Total number of messages dropped by sampling by type"),
+        &["kind"]
+    ).unwrap();
+
+    pub(crate) static ref SKIPPED_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("skipped_total", "Total number of skipped messages by reason"),
+        &["reason"]
+    ).unwrap();
+
+    pub(crate) static ref FAILED_TRANSACTIONS_SKIPPED_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("failed_transactions_skipped_total", "Total number of failed transactions skipped or routed to failed transaction topic by action"),
+        &["action"]
+    ).unwrap();
+
+    pub(crate) static ref CACHE_COLLAPSED_MESSAGES_TOTAL: IntCounter = IntCounter::new(
+        "cache_collapsed_messages_total", "Total number of account updates superseded in write-behind cache"
+    ).unwrap();
+
+    pub(crate) static ref GRPC_BYTES_RECEIVED_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("grpc_bytes_received_total", "Total size of received gRPC updates by endpoint and type"),
+        &["endpoint", "kind"]
+    ).unwrap();
+
+    pub(crate) static ref GRPC_CONNECTIONS_ACTIVE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("grpc_connections_active", "Number of subscribed gRPC connections by endpoint"),
+        &["endpoint"]
+    ).unwrap();
+
+    pub(crate) static ref RECONNECT_REASON_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("reconnect_reason_total", "Total number of gRPC stream reconnects by reason"),
+        &["reason"]
+    ).unwrap();
+
+    pub(crate) static ref GRPC_FAILOVER_ACTIVE: IntGauge = IntGauge::new(
+        "grpc_failover_active", "Secondary gRPC subscription is active"
+    ).unwrap();
+
+    pub(crate) static ref IN_FLIGHT_TASKS: IntGauge = IntGauge::new(
+        "in_flight_tasks", "Number of dedup tasks holding in-flight permits"
+    ).unwrap();
+
+    pub(crate) static ref CONFIRMATION_TIMEOUT_TOTAL: IntCounter = IntCounter::new(
+        "confirmation_timeout_total", "Total number of messages sent without slot confirmation after timeout"
+    ).unwrap();
+
+    pub(crate) static ref CONFIRMATION_OVERFLOW_TOTAL: IntCounter = IntCounter::new(
+        "confirmation_overflow_total", "Total number of messages sent without slot confirmation because too many messages waited"
+    ).unwrap();
+
+    pub(crate) static ref IN_FLIGHT_BY_KIND: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("kafka_in_flight_messages", "Number of in-flight produces by kind"),
+        &["kind"]
+    ).unwrap();
+
+    pub(crate) static ref ACTIVE_SUBSCRIBERS: IntGauge = IntGauge::new(
+        "active_subscribers", "Number of connected kafka2grpc subscribers"
+    ).unwrap();
+
+    pub(crate) static ref SLOT_COMMITMENT_WATERMARK: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("slot_commitment_watermark", "Highest seen slot by commitment"),
+        &["commitment"]
+    ).unwrap();
+
+    pub(crate) static ref MAX_SLOT_GAP: IntGauge = IntGauge::new(
+        "max_slot_gap", "Largest gap between consecutively seen slots"
+    ).unwrap();
+
+    pub(crate) static ref SLOT_GAP_TOTAL: IntCounterVec = IntCounterVec::new(
+        Opts::new("slot_gap_total", "Total number of gaps between consecutively seen slots by size"),
+        &["gap_size_bucket"]
+    ).unwrap();
+
+    pub(crate) static ref MESSAGE_LATENCY_SECONDS: HistogramVec = HistogramVec::new(
+        histogram_opts(
+            "message_latency_seconds",
+            "Time from gRPC receive to Kafka delivery by type",
+            &DEFAULT_LATENCY_BUCKETS
+        ),
+        &["kind"]
+    ).unwrap();
+
+    pub(crate) static ref DEDUP_BACKEND_LATENCY_SECONDS: Histogram = Histogram::with_opts(
+        histogram_opts(
+            "dedup_backend_latency_seconds",
+            "Dedup backend check latency",
+            &DEFAULT_LATENCY_BUCKETS
+        )
+    ).unwrap();
+
+    pub(crate) static ref RECONNECT_TTFM_SECONDS: HistogramVec = HistogramVec::new(
+        histogram_opts(
+            "reconnect_ttfm_seconds",
+            "Time from subscribe to the first received update by endpoint",
+            &DEFAULT_TTFM_BUCKETS
+        ),
+        &["endpoint"]
+    ).unwrap();
+
+    pub(crate) static ref PARTITION_HIGH_WATERMARK: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("partition_high_watermark", "High watermark of assigned partition"),
+        &["partition"]
+    ).unwrap();
+
+    pub(crate) static ref PARTITION_CURRENT_OFFSET: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("partition_current_offset", "Consumer position in assigned partition"),
+        &["partition"]
+    ).unwrap();
+
+    pub(crate) static ref CONSUMER_LAG_GAUGE: IntGaugeVec = IntGaugeVec::new(
+        Opts::new("partition_consumer_lag", "Difference between high watermark and consumer position by partition"),
+        &["partition"]
+    ).unwrap();
+
+    pub(crate) static ref LEADER_ELECTION_LEADER: IntGauge = IntGauge::new(
+        "leader_election_leader", "Process is leader and produces messages"
+    ).unwrap();
 }
 
-#[derive(Debug)]
+/// Max time to wait for in-flight messages before partitions are revoked
+const REBALANCE_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+type RevokeHook = Box<dyn Fn() + Send + Sync>;
+
 pub struct StatsContext {
     default: DefaultClientContext,
     error_tx: Mutex<Option<oneshot::Sender<()>>>,
+    in_flight: AtomicUsize,
+    revoke_hook: Mutex<Option<RevokeHook>>,
+}
+
+impl std::fmt::Debug for StatsContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StatsContext")
+            .field("in_flight", &self.in_flight)
+            .finish()
+    }
 }
 
 impl StatsContext {
@@ -46,6 +325,8 @@ impl StatsContext {
             Self {
                 default: DefaultClientContext,
                 error_tx: Mutex::new(Some(error_tx)),
+                in_flight: AtomicUsize::new(0),
+                revoke_hook: Mutex::new(None),
             },
             error_rx,
         )
@@ -59,8 +340,50 @@ impl StatsContext {
 }
 
 impl ClientContext for StatsContext {
+    fn stats_raw(&self, statistics: &[u8]) {
+        match serde_json::from_slice::<Statistics>(statistics) {
+            Ok(stats) => {
+                if let Ok(value) = serde_json::from_slice(statistics) {
+                    KAFKA_STATS_LAST
+                        .lock()
+                        .expect("unpoisoned")
+                        .insert(stats.name.clone(), value);
+                }
+                self.stats(stats);
+            }
+            Err(error) => warn!("failed to parse kafka statistics: {error}"),
+        }
+    }
+
     fn stats(&self, statistics: Statistics) {
+        trace!(
+            client = %statistics.name,
+            client_type = %statistics.client_type,
+            broker_count = statistics.brokers.len(),
+            replyq = statistics.replyq,
+            msg_cnt = statistics.msg_cnt,
+            msg_size = statistics.msg_size,
+            tx = statistics.tx,
+            txmsgs = statistics.txmsgs,
+            rx = statistics.rx,
+            rxmsgs = statistics.rxmsgs,
+            "kafka statistics"
+        );
         for (name, broker) in statistics.brokers {
+            trace!(
+                client = %statistics.name,
+                broker = %name,
+                state = %broker.state,
+                outbuf_cnt = broker.outbuf_cnt,
+                outbuf_msg_cnt = broker.outbuf_msg_cnt,
+                waitresp_cnt = broker.waitresp_cnt,
+                waitresp_msg_cnt = broker.waitresp_msg_cnt,
+                txerrs = broker.txerrs,
+                txretries = broker.txretries,
+                req_timeouts = broker.req_timeouts,
+                "kafka broker statistics"
+            );
+
             macro_rules! set_value {
                 ($name:expr, $value:expr) => {
                     KAFKA_STATS
@@ -129,13 +452,80 @@ impl ClientContext for StatsContext {
 
     fn error(&self, error: KafkaError, reason: &str) {
         self.default.error(error, reason);
+        readiness::set_kafka_error();
         self.send_error()
     }
 }
 
-impl ConsumerContext for StatsContext {}
+impl ConsumerContext for StatsContext {
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        match rebalance {
+            Rebalance::Assign(partitions) => {
+                info!("kafka rebalance: assign {}", format_partitions(partitions));
+                readiness::set_partitions_assigned(partitions.count());
+                KAFKA_REBALANCE_TOTAL.with_label_values(&["assign"]).inc();
+            }
+            Rebalance::Revoke(partitions) => {
+                info!("kafka rebalance: revoke {}", format_partitions(partitions));
+                KAFKA_REBALANCE_TOTAL.with_label_values(&["revoke"]).inc();
+                readiness::set_partitions_assigned(0);
+                // callback is called from the polling task, in-flight messages
+                // are processed by other tasks and we can block here; offsets
+                // are committed by librdkafka on revoke if auto-commit enabled,
+                // otherwise by the revoke hook
+                let deadline = Instant::now() + REBALANCE_DRAIN_TIMEOUT;
+                while self.in_flight.load(Ordering::Relaxed) > 0 && Instant::now() < deadline {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                let in_flight = self.in_flight.load(Ordering::Relaxed);
+                if in_flight > 0 {
+                    warn!("kafka rebalance: revoke with {in_flight} in-flight messages");
+                }
+                if let Some(hook) = self.revoke_hook.lock().expect("alive mutex").as_ref() {
+                    hook();
+                }
+            }
+            Rebalance::Error(error) => {
+                warn!("kafka rebalance: error: {error}");
+                KAFKA_REBALANCE_TOTAL.with_label_values(&["error"]).inc();
+            }
+        }
+    }
+}
+
+fn format_partitions(partitions: &TopicPartitionList) -> String {
+    partitions
+        .elements()
+        .iter()
+        .map(|elem| format!("{}[{}]", elem.topic(), elem.partition()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Message is in-flight until guard is dropped, see [`StatsContext::in_flight`]
+#[derive(Debug)]
+pub struct InFlightGuard(Arc<StatsContext>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 impl StatsContext {
+    /// Track consumed message until it's processed, partitions revoke waits
+    /// for all in-flight messages
+    pub fn in_flight(self: &Arc<Self>) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(Arc::clone(self))
+    }
+
+    /// Called on partitions revoke after in-flight messages are drained,
+    /// e.g. to flush output and commit offsets without auto-commit
+    pub fn set_revoke_hook(&self, hook: impl Fn() + Send + Sync + 'static) {
+        *self.revoke_hook.lock().expect("alive mutex") = Some(Box::new(hook));
+    }
+
     pub fn create_future_producer(
         config: &ClientConfig,
     ) -> KafkaResult<(FutureProducer<Self>, oneshot::Receiver<()>)> {
@@ -151,12 +541,148 @@ impl StatsContext {
         StreamConsumer::from_config_and_context(config, context)
             .map(|consumer| (consumer, error_rx))
     }
+
+    /// Call `create` until success or `retry.attempts` failed attempts
+    pub async fn create_with_retry<T>(
+        retry: ConfigKafkaStartupRetry,
+        name: &str,
+        mut create: impl FnMut() -> KafkaResult<T>,
+    ) -> KafkaResult<T> {
+        let mut backoff = Duration::from_millis(retry.backoff_ms);
+        let mut attempt = 1;
+        loop {
+            match create() {
+                Ok(client) => return Ok(client),
+                Err(error) if attempt < retry.attempts => {
+                    warn!(
+                        "failed to create kafka {name} (attempt {attempt}/{}): {error}, retry in {backoff:?}",
+                        retry.attempts
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_millis(retry.max_backoff_ms));
+                    attempt += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Poll high watermark and position of every assigned partition, gauges of
+/// revoked partitions are removed on the next poll. Task finishes when the
+/// consumer is dropped.
+pub fn spawn_partition_watermark_monitor(
+    consumer: Weak<StreamConsumer<StatsContext>>,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut poll_interval = interval(period);
+        poll_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            poll_interval.tick().await;
+            let Some(consumer) = consumer.upgrade() else {
+                break;
+            };
+            let result = task::spawn_blocking(move || {
+                let positions = consumer.position()?;
+                positions
+                    .elements()
+                    .iter()
+                    .map(|elem| {
+                        let (_low, high) = consumer.fetch_watermarks(
+                            elem.topic(),
+                            elem.partition(),
+                            Duration::from_secs(10),
+                        )?;
+                        let position = match elem.offset() {
+                            Offset::Offset(offset) => Some(offset),
+                            _ => None,
+                        };
+                        Ok((elem.partition(), high, position))
+                    })
+                    .collect::<KafkaResult<Vec<_>>>()
+            })
+            .await;
+            let partitions = match result {
+                Ok(Ok(partitions)) => partitions,
+                Ok(Err(error)) => {
+                    warn!("failed to fetch partition watermarks: {error}");
+                    continue;
+                }
+                Err(error) => {
+                    warn!("partition watermarks task failed: {error}");
+                    continue;
+                }
+            };
+
+            PARTITION_HIGH_WATERMARK.reset();
+            PARTITION_CURRENT_OFFSET.reset();
+            CONSUMER_LAG_GAUGE.reset();
+            for (partition, high, position) in partitions {
+                let partition = partition.to_string();
+                PARTITION_HIGH_WATERMARK
+                    .with_label_values(&[&partition])
+                    .set(high);
+                // position is unknown until the first message is consumed
+                if let Some(position) = position {
+                    PARTITION_CURRENT_OFFSET
+                        .with_label_values(&[&partition])
+                        .set(position);
+                    CONSUMER_LAG_GAUGE
+                        .with_label_values(&[&partition])
+                        .set((high - position).max(0));
+                }
+            }
+        }
+    })
 }
 
 pub fn dedup_inc() {
     KAFKA_DEDUP_TOTAL.inc();
 }
 
+pub fn client_rate_limited_inc(client_id: usize) {
+    CLIENT_RATE_LIMITED_TOTAL
+        .with_label_values(&[&client_id.to_string()])
+        .inc()
+}
+
+/// Client ids are not reused, series of disconnected clients are removed
+pub fn client_rate_limited_remove(client_id: usize) {
+    let _ = CLIENT_RATE_LIMITED_TOTAL.remove_label_values(&[&client_id.to_string()]);
+}
+
+pub fn grpc_bytes_received_inc(endpoint: &str, kind: GprcMessageKind, bytes: usize) {
+    GRPC_BYTES_RECEIVED_TOTAL
+        .with_label_values(&[endpoint, kind.as_str()])
+        .inc_by(bytes as u64)
+}
+
+/// Gauge of subscribed connections to the endpoint
+pub fn grpc_connections_active(endpoint: &str) -> IntGauge {
+    GRPC_CONNECTIONS_ACTIVE.with_label_values(&[endpoint])
+}
+
+pub fn decode_error_inc(kind: &str) {
+    DECODE_ERRORS_TOTAL.with_label_values(&[kind]).inc()
+}
+
+pub fn serialize_error_inc(field: &str) {
+    SERIALIZE_ERRORS_TOTAL.with_label_values(&[field]).inc()
+}
+
+pub fn near_duplicate_dedup_inc() {
+    NEAR_DUPLICATE_DEDUP_TOTAL.inc();
+}
+
+pub fn dedup_lag_skip_inc() {
+    DEDUP_LAG_SKIP_TOTAL.inc();
+}
+
+pub fn dedup_epoch_reset_inc() {
+    DEDUP_EPOCH_RESETS_TOTAL.inc();
+}
+
 pub fn recv_inc() {
     KAFKA_RECV_TOTAL.inc();
 }
@@ -164,3 +690,209 @@ pub fn recv_inc() {
 pub fn sent_inc(kind: GprcMessageKind) {
     KAFKA_SENT_TOTAL.with_label_values(&[kind.as_str()]).inc()
 }
+
+pub fn bytes_produced_inc(kind: GprcMessageKind, bytes: usize) {
+    KAFKA_BYTES_PRODUCED_TOTAL
+        .with_label_values(&[kind.as_str()])
+        .inc_by(bytes as u64)
+}
+
+pub fn message_latency_observe(kind: GprcMessageKind, latency: Duration) {
+    MESSAGE_LATENCY_SECONDS
+        .with_label_values(&[kind.as_str()])
+        .observe(latency.as_secs_f64())
+}
+
+pub fn reconnect_ttfm_observe(endpoint: &str, ttfm: Duration) {
+    RECONNECT_TTFM_SECONDS
+        .with_label_values(&[endpoint])
+        .observe(ttfm.as_secs_f64())
+}
+
+pub fn dedup_backend_latency_observe(latency: Duration) {
+    DEDUP_BACKEND_LATENCY_SECONDS.observe(latency.as_secs_f64())
+}
+
+pub fn dual_write_sent_inc(format: &str) {
+    KAFKA_DUAL_WRITE_SENT_TOTAL
+        .with_label_values(&[format])
+        .inc()
+}
+
+pub fn dead_letter_inc(topic: &str) {
+    KAFKA_DEAD_LETTER_TOTAL.with_label_values(&[topic]).inc()
+}
+
+pub fn heartbeat_sent_inc(source: &str) {
+    KAFKA_HEARTBEAT_SENT_TOTAL
+        .with_label_values(&[source])
+        .inc()
+}
+
+pub fn cache_collapsed_inc() {
+    CACHE_COLLAPSED_MESSAGES_TOTAL.inc();
+}
+
+pub fn sampled_out_inc(kind: GprcMessageKind) {
+    KAFKA_SAMPLED_OUT_TOTAL
+        .with_label_values(&[kind.as_str()])
+        .inc()
+}
+
+pub fn per_slot_limit_exceeded_inc(kind: GprcMessageKind) {
+    PER_SLOT_LIMIT_EXCEEDED_TOTAL
+        .with_label_values(&[kind.as_str()])
+        .inc()
+}
+
+pub fn pipeline_restart_inc(action: &str) {
+    PIPELINE_RESTARTS_TOTAL.with_label_values(&[action]).inc()
+}
+
+pub fn reconnect_reason_inc(reason: &str) {
+    RECONNECT_REASON_TOTAL.with_label_values(&[reason]).inc()
+}
+
+pub fn skipped_inc(reason: &str) {
+    SKIPPED_TOTAL.with_label_values(&[reason]).inc()
+}
+
+pub fn failed_transaction_skipped_inc(action: &str) {
+    FAILED_TRANSACTIONS_SKIPPED_TOTAL
+        .with_label_values(&[action])
+        .inc()
+}
+
+pub fn failover_active_set(active: bool) {
+    GRPC_FAILOVER_ACTIVE.set(active as i64)
+}
+
+pub fn leader_set(leader: bool) {
+    LEADER_ELECTION_LEADER.set(leader as i64)
+}
+
+pub fn in_flight_tasks_inc() {
+    IN_FLIGHT_TASKS.inc()
+}
+
+pub fn in_flight_tasks_dec() {
+    IN_FLIGHT_TASKS.dec()
+}
+
+/// Gauge of in-flight produces of the kind
+pub fn in_flight_by_kind(kind: GprcMessageKind) -> IntGauge {
+    IN_FLIGHT_BY_KIND.with_label_values(&[kind.as_str()])
+}
+
+pub fn confirmation_timeout_inc() {
+    CONFIRMATION_TIMEOUT_TOTAL.inc()
+}
+
+pub fn confirmation_overflow_inc() {
+    CONFIRMATION_OVERFLOW_TOTAL.inc()
+}
+
+pub fn active_subscribers_inc() {
+    ACTIVE_SUBSCRIBERS.inc()
+}
+
+pub fn active_subscribers_dec() {
+    ACTIVE_SUBSCRIBERS.dec()
+}
+
+pub fn slot_commitment_watermark_set(commitment: &str, slot: u64) {
+    SLOT_COMMITMENT_WATERMARK
+        .with_label_values(&[commitment])
+        .set(slot as i64)
+}
+
+/// Update slot gap metrics, returns previously seen max slot (`0` if none)
+/// and number of skipped slots
+pub fn slot_seen(slot: u64) -> (u64, u64) {
+    let last_seen = LAST_SEEN_SLOT.fetch_max(slot, Ordering::Relaxed);
+    if last_seen == 0 || slot <= last_seen + 1 {
+        return (last_seen, 0);
+    }
+
+    let gap = slot - last_seen - 1;
+    if gap as i64 > MAX_SLOT_GAP.get() {
+        MAX_SLOT_GAP.set(gap as i64);
+    }
+    let bucket = match gap {
+        1 => "1",
+        2..=10 => "2-10",
+        11..=SLOT_GAP_ALERT => "11-100",
+        _ => "100+",
+    };
+    SLOT_GAP_TOTAL.with_label_values(&[bucket]).inc();
+    (last_seen, gap)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            client_rate_limited_inc, client_rate_limited_remove, StatsContext,
+            CLIENT_RATE_LIMITED_TOTAL,
+        },
+        rdkafka::{
+            consumer::{ConsumerContext, Rebalance},
+            topic_partition_list::TopicPartitionList,
+        },
+        std::{
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Arc,
+            },
+            thread,
+            time::Duration,
+        },
+    };
+
+    #[test]
+    fn revoke_waits_in_flight_and_runs_hook() {
+        let (context, _error_rx) = StatsContext::new();
+        let context = Arc::new(context);
+        let in_flight_done = Arc::new(AtomicBool::new(false));
+        let hook_called_after_drain = Arc::new(AtomicBool::new(false));
+        context.set_revoke_hook({
+            let in_flight_done = Arc::clone(&in_flight_done);
+            let hook_called_after_drain = Arc::clone(&hook_called_after_drain);
+            move || {
+                hook_called_after_drain
+                    .store(in_flight_done.load(Ordering::Relaxed), Ordering::Relaxed)
+            }
+        });
+
+        let in_flight = context.in_flight();
+        let in_flight_task = {
+            let in_flight_done = Arc::clone(&in_flight_done);
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                in_flight_done.store(true, Ordering::Relaxed);
+                drop(in_flight);
+            })
+        };
+
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition("grpc1", 0);
+        context.pre_rebalance(&Rebalance::Revoke(&partitions));
+        assert!(hook_called_after_drain.load(Ordering::Relaxed));
+        in_flight_task.join().unwrap();
+    }
+
+    #[test]
+    fn client_rate_limited_removed() {
+        client_rate_limited_inc(usize::MAX);
+        assert_eq!(
+            CLIENT_RATE_LIMITED_TOTAL
+                .with_label_values(&[&usize::MAX.to_string()])
+                .get(),
+            1
+        );
+        client_rate_limited_remove(usize::MAX);
+        assert!(CLIENT_RATE_LIMITED_TOTAL
+            .remove_label_values(&[&usize::MAX.to_string()])
+            .is_err());
+    }
+}
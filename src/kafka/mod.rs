@@ -1,4 +1,33 @@
+pub mod checkpoint;
+pub mod commitment;
+pub mod compression;
 pub mod config;
+pub mod confirmation;
+pub mod dead_letter;
 pub mod dedup;
+pub mod error_events;
 pub mod grpc;
+pub mod in_flight;
+#[cfg(feature = "instruction-decoder")]
+pub mod instruction;
+pub mod leader;
 pub mod metrics;
+pub mod monotonic;
+#[cfg(feature = "parquet-sink")]
+pub mod parquet;
+pub mod partition;
+pub mod payload;
+pub mod rate_limit;
+pub mod readiness;
+pub mod reorder;
+pub mod replay;
+pub mod sampler;
+#[cfg(feature = "script-transform")]
+pub mod script;
+pub mod shard;
+pub mod slot_limit;
+pub mod subscriber;
+pub mod transform;
+pub mod watchdog;
+pub mod webhook;
+pub mod write_behind;
@@ -0,0 +1,87 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// Keeps produced slots non-decreasing across reconnects. Last produced slot
+/// is tracked per key (message kind and commitment), after reconnect or
+/// failover messages below the slot of their key are dropped until the key
+/// catches up. Outside of this window messages are not checked, so slots of
+/// different commitments can interleave.
+#[derive(Debug)]
+pub struct MonotonicSlots<K> {
+    last_produced: HashMap<K, u64>,
+    catch_up: HashMap<K, u64>,
+}
+
+impl<K> Default for MonotonicSlots<K> {
+    fn default() -> Self {
+        Self {
+            last_produced: HashMap::new(),
+            catch_up: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> MonotonicSlots<K> {
+    /// Start the window after reconnect or failover
+    pub fn reconnected(&mut self) {
+        self.catch_up = self.last_produced.clone();
+    }
+
+    /// Returns `false` if the message should be dropped
+    pub fn allow(&mut self, key: K, slot: u64) -> bool {
+        if let Some(last_produced) = self.catch_up.get(&key) {
+            if slot < *last_produced {
+                return false;
+            }
+            self.catch_up.remove(&key);
+        }
+        self.last_produced
+            .entry(key)
+            .and_modify(|last_produced| *last_produced = (*last_produced).max(slot))
+            .or_insert(slot);
+        true
+    }
+
+    /// Some keys are below their last produced slot after reconnect
+    pub fn is_catching_up(&self) -> bool {
+        !self.catch_up.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MonotonicSlots;
+
+    const PROCESSED: i32 = 0;
+    const CONFIRMED: i32 = 1;
+
+    #[test]
+    fn monotonic_slots_interleaved_commitments() {
+        let mut slots = MonotonicSlots::default();
+        // confirmed slots are behind processed ones without reconnect
+        for (processed, confirmed) in [(100, 70), (101, 71), (102, 72)] {
+            assert!(slots.allow(("slot", PROCESSED), processed));
+            assert!(slots.allow(("slot", CONFIRMED), confirmed));
+            assert!(slots.allow(("account", PROCESSED), processed));
+        }
+        // messages are not dropped outside of the reconnect window
+        assert!(slots.allow(("account", PROCESSED), 90));
+        assert!(!slots.is_catching_up());
+
+        // new stream starts from older slots
+        slots.reconnected();
+        assert!(slots.is_catching_up());
+        assert!(!slots.allow(("slot", PROCESSED), 99));
+        assert!(slots.allow(("slot", CONFIRMED), 72));
+        assert!(!slots.allow(("account", PROCESSED), 101));
+        assert!(slots.allow(("slot", PROCESSED), 102));
+        // unknown keys are not checked
+        assert!(slots.allow(("transaction", PROCESSED), 1));
+        assert!(slots.is_catching_up());
+        assert!(slots.allow(("account", PROCESSED), 103));
+        assert!(!slots.is_catching_up());
+
+        // window is closed for every key
+        assert!(slots.allow(("slot", CONFIRMED), 71));
+        assert!(slots.allow(("slot", PROCESSED), 101));
+    }
+}
@@ -0,0 +1,435 @@
+use {
+    super::config::ConfigParquetSink,
+    arrow_array::{
+        builder::{BinaryBuilder, BooleanBuilder, StringBuilder, UInt64Builder},
+        ArrayRef, RecordBatch,
+    },
+    arrow_schema::{DataType, Field, Schema, SchemaRef},
+    object_store::{parse_url_opts, path::Path, ObjectStore},
+    parquet::{
+        arrow::ArrowWriter,
+        basic::{Compression, ZstdLevel},
+        file::properties::WriterProperties,
+    },
+    std::{
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+    tokio::{
+        sync::mpsc,
+        task::JoinHandle,
+        time::{interval, MissedTickBehavior},
+    },
+    tracing::info,
+    url::Url,
+    yellowstone_grpc_proto::prelude::subscribe_update::UpdateOneof,
+};
+
+/// One message, transaction columns are `null` for other kinds
+#[derive(Debug)]
+pub struct ParquetRow {
+    slot: u64,
+    kind: &'static str,
+    key: String,
+    signature: Option<Vec<u8>>,
+    is_vote: Option<bool>,
+    index: Option<u64>,
+    fee: Option<u64>,
+    failed: Option<bool>,
+    payload: Vec<u8>,
+}
+
+impl ParquetRow {
+    pub fn new(
+        slot: u64,
+        kind: &'static str,
+        key: String,
+        update: &UpdateOneof,
+        payload: Vec<u8>,
+    ) -> Self {
+        let mut row = Self {
+            slot,
+            kind,
+            key,
+            signature: None,
+            is_vote: None,
+            index: None,
+            fee: None,
+            failed: None,
+            payload,
+        };
+        if let UpdateOneof::Transaction(msg) = update {
+            if let Some(tx) = &msg.transaction {
+                row.signature = Some(tx.signature.clone());
+                row.is_vote = Some(tx.is_vote);
+                row.index = Some(tx.index);
+                if let Some(meta) = &tx.meta {
+                    row.fee = Some(meta.fee);
+                    row.failed = Some(meta.err.is_some());
+                }
+            }
+        }
+        row
+    }
+}
+
+#[derive(Debug)]
+pub struct ParquetSink {
+    rows_tx: mpsc::Sender<ParquetRow>,
+}
+
+impl ParquetSink {
+    pub fn spawn(
+        config: ConfigParquetSink,
+    ) -> anyhow::Result<(Self, JoinHandle<anyhow::Result<()>>)> {
+        let url = Url::parse(&config.url)?;
+        let (store, prefix) = parse_url_opts(&url, config.options.clone())?;
+        let (rows_tx, rows_rx) = mpsc::channel(config.max_rows.max(1));
+        let writer = ParquetWriter::new(store.into(), prefix);
+        let task = tokio::spawn(writer.run(config, rows_rx));
+        Ok((Self { rows_tx }, task))
+    }
+
+    pub async fn push(&self, row: ParquetRow) -> anyhow::Result<()> {
+        self.rows_tx
+            .send(row)
+            .await
+            .map_err(|_| anyhow::anyhow!("parquet sink is closed"))
+    }
+}
+
+struct ParquetWriter {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    schema: SchemaRef,
+    /// Number of written files, part of the file name so files with the
+    /// same slots flushed in the same millisecond are not overwritten
+    files: u64,
+}
+
+impl ParquetWriter {
+    fn new(store: Arc<dyn ObjectStore>, prefix: Path) -> Self {
+        Self {
+            store,
+            prefix,
+            schema: Self::schema(),
+            files: 0,
+        }
+    }
+    fn schema() -> SchemaRef {
+        Arc::new(Schema::new(vec![
+            Field::new("slot", DataType::UInt64, false),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("key", DataType::Utf8, false),
+            Field::new("signature", DataType::Binary, true),
+            Field::new("is_vote", DataType::Boolean, true),
+            Field::new("index", DataType::UInt64, true),
+            Field::new("fee", DataType::UInt64, true),
+            Field::new("failed", DataType::Boolean, true),
+            Field::new("payload", DataType::Binary, false),
+        ]))
+    }
+
+    async fn run(
+        mut self,
+        config: ConfigParquetSink,
+        mut rows_rx: mpsc::Receiver<ParquetRow>,
+    ) -> anyhow::Result<()> {
+        let mut rows = Vec::with_capacity(config.max_rows);
+        let mut bytes = 0;
+        let mut flush_interval = interval(config.flush_interval);
+        flush_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        flush_interval.tick().await;
+
+        loop {
+            tokio::select! {
+                maybe_row = rows_rx.recv() => match maybe_row {
+                    Some(row) => {
+                        bytes += row.payload.len();
+                        rows.push(row);
+                        if rows.len() >= config.max_rows || bytes >= config.max_bytes {
+                            self.flush(std::mem::take(&mut rows)).await?;
+                            bytes = 0;
+                            flush_interval.reset();
+                        }
+                    }
+                    None => break,
+                },
+                _ = flush_interval.tick() => {
+                    if !rows.is_empty() {
+                        self.flush(std::mem::take(&mut rows)).await?;
+                        bytes = 0;
+                    }
+                }
+            }
+        }
+
+        if !rows.is_empty() {
+            self.flush(rows).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self, rows: Vec<ParquetRow>) -> anyhow::Result<()> {
+        let mut slot = UInt64Builder::with_capacity(rows.len());
+        let mut kind = StringBuilder::new();
+        let mut key = StringBuilder::new();
+        let mut signature = BinaryBuilder::new();
+        let mut is_vote = BooleanBuilder::with_capacity(rows.len());
+        let mut index = UInt64Builder::with_capacity(rows.len());
+        let mut fee = UInt64Builder::with_capacity(rows.len());
+        let mut failed = BooleanBuilder::with_capacity(rows.len());
+        let mut payload = BinaryBuilder::new();
+        let (mut slot_min, mut slot_max) = (u64::MAX, u64::MIN);
+        for row in rows.iter() {
+            slot_min = slot_min.min(row.slot);
+            slot_max = slot_max.max(row.slot);
+            slot.append_value(row.slot);
+            kind.append_value(row.kind);
+            key.append_value(&row.key);
+            signature.append_option(row.signature.as_ref());
+            is_vote.append_option(row.is_vote);
+            index.append_option(row.index);
+            fee.append_option(row.fee);
+            failed.append_option(row.failed);
+            payload.append_value(&row.payload);
+        }
+        let batch = RecordBatch::try_new(
+            Arc::clone(&self.schema),
+            vec![
+                Arc::new(slot.finish()) as ArrayRef,
+                Arc::new(kind.finish()),
+                Arc::new(key.finish()),
+                Arc::new(signature.finish()),
+                Arc::new(is_vote.finish()),
+                Arc::new(index.finish()),
+                Arc::new(fee.finish()),
+                Arc::new(failed.finish()),
+                Arc::new(payload.finish()),
+            ],
+        )?;
+
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(Vec::new(), Arc::clone(&self.schema), Some(properties))?;
+        writer.write(&batch)?;
+        let data = writer.into_inner()?;
+
+        let ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = self
+            .prefix
+            .child(format!("{slot_min}-{slot_max}-{ts}-{}.parquet", self.files));
+        self.files += 1;
+        let size = data.len();
+        self.store.put(&path, data.into()).await?;
+        info!(
+            "parquet: written {} rows ({size} bytes) to {path}",
+            rows.len()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{ParquetRow, ParquetWriter},
+        crate::kafka::config::ConfigParquetSink,
+        arrow_array::{Array, BinaryArray, BooleanArray, RecordBatch, StringArray, UInt64Array},
+        futures::stream::TryStreamExt,
+        object_store::{memory::InMemory, path::Path, ObjectStore},
+        parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder,
+        std::{collections::HashMap, sync::Arc, time::Duration},
+        tokio::sync::mpsc,
+        yellowstone_grpc_proto::prelude::{
+            subscribe_update::UpdateOneof, SubscribeUpdateSlot, SubscribeUpdateTransaction,
+            SubscribeUpdateTransactionInfo, TransactionError, TransactionStatusMeta,
+        },
+    };
+
+    fn transaction(slot: u64, index: u64, failed: bool) -> UpdateOneof {
+        UpdateOneof::Transaction(SubscribeUpdateTransaction {
+            transaction: Some(SubscribeUpdateTransactionInfo {
+                signature: vec![index as u8; 64],
+                is_vote: index % 2 == 0,
+                index,
+                meta: Some(TransactionStatusMeta {
+                    fee: 5_000 + index,
+                    err: failed.then(|| TransactionError { err: vec![1] }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            slot,
+        })
+    }
+
+    fn config(max_rows: usize, flush_interval: Duration) -> ConfigParquetSink {
+        ConfigParquetSink {
+            url: "memory:///".to_owned(),
+            options: HashMap::new(),
+            max_rows,
+            max_bytes: usize::MAX,
+            flush_interval,
+        }
+    }
+
+    /// Batches of all written files ordered by file name
+    async fn read_files(store: &InMemory) -> Vec<RecordBatch> {
+        let mut files = store.list(None).try_collect::<Vec<_>>().await.unwrap();
+        files.sort_by(|a, b| a.location.cmp(&b.location));
+        let mut batches = vec![];
+        for file in files {
+            let data = store
+                .get(&file.location)
+                .await
+                .unwrap()
+                .bytes()
+                .await
+                .unwrap();
+            let reader = ParquetRecordBatchReaderBuilder::try_new(data)
+                .unwrap()
+                .build()
+                .unwrap();
+            batches.extend(reader.map(Result::unwrap));
+        }
+        batches
+    }
+
+    fn column<T: Clone + 'static>(batch: &RecordBatch, name: &str) -> T {
+        batch
+            .column_by_name(name)
+            .unwrap()
+            .as_any()
+            .downcast_ref::<T>()
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn row_mapping() {
+        let row = ParquetRow::new(
+            10,
+            "transaction",
+            "key".to_owned(),
+            &transaction(10, 3, true),
+            vec![1, 2, 3],
+        );
+        assert_eq!(row.slot, 10);
+        assert_eq!(row.kind, "transaction");
+        assert_eq!(row.key, "key");
+        assert_eq!(row.signature, Some(vec![3; 64]));
+        assert_eq!(row.is_vote, Some(false));
+        assert_eq!(row.index, Some(3));
+        assert_eq!(row.fee, Some(5_003));
+        assert_eq!(row.failed, Some(true));
+        assert_eq!(row.payload, [1, 2, 3]);
+
+        // transaction columns are null for other kinds
+        let row = ParquetRow::new(
+            11,
+            "slot",
+            "key".to_owned(),
+            &UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot: 11,
+                ..Default::default()
+            }),
+            vec![],
+        );
+        assert_eq!(row.slot, 11);
+        assert_eq!(row.signature, None);
+        assert_eq!(row.is_vote, None);
+        assert_eq!(row.index, None);
+        assert_eq!(row.fee, None);
+        assert_eq!(row.failed, None);
+    }
+
+    #[tokio::test]
+    async fn write_read_round_trip() {
+        let store = Arc::new(InMemory::new());
+        let writer = ParquetWriter::new(
+            Arc::clone(&store) as Arc<dyn ObjectStore>,
+            Path::from("prefix"),
+        );
+        let (rows_tx, rows_rx) = mpsc::channel(16);
+        let task = tokio::spawn(writer.run(config(2, Duration::from_secs(3600)), rows_rx));
+        // rows of the same slot are rotated into different files
+        for index in 0..5 {
+            let row = ParquetRow::new(
+                10,
+                "transaction",
+                format!("key-{index}"),
+                &transaction(10, index, index == 1),
+                vec![index as u8; 8],
+            );
+            rows_tx.send(row).await.unwrap();
+        }
+        drop(rows_tx);
+        task.await.unwrap().unwrap();
+
+        let batches = read_files(&store).await;
+        assert_eq!(
+            batches
+                .iter()
+                .map(RecordBatch::num_rows)
+                .collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+        let mut index = 0;
+        for batch in batches.iter() {
+            let slots = column::<UInt64Array>(batch, "slot");
+            let keys = column::<StringArray>(batch, "key");
+            let signatures = column::<BinaryArray>(batch, "signature");
+            let failed = column::<BooleanArray>(batch, "failed");
+            let fees = column::<UInt64Array>(batch, "fee");
+            let payloads = column::<BinaryArray>(batch, "payload");
+            for row in 0..batch.num_rows() {
+                assert_eq!(slots.value(row), 10);
+                assert_eq!(keys.value(row), format!("key-{index}"));
+                assert_eq!(signatures.value(row), [index as u8; 64]);
+                assert_eq!(failed.value(row), index == 1);
+                assert_eq!(fees.value(row), 5_000 + index);
+                assert_eq!(payloads.value(row), [index as u8; 8]);
+                index += 1;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn flush_on_interval() {
+        let store = Arc::new(InMemory::new());
+        let writer = ParquetWriter::new(
+            Arc::clone(&store) as Arc<dyn ObjectStore>,
+            Path::from("prefix"),
+        );
+        let (rows_tx, rows_rx) = mpsc::channel(16);
+        let task = tokio::spawn(writer.run(config(100, Duration::from_millis(50)), rows_rx));
+        let update = UpdateOneof::Slot(SubscribeUpdateSlot {
+            slot: 20,
+            ..Default::default()
+        });
+        rows_tx
+            .send(ParquetRow::new(
+                20,
+                "slot",
+                "key".to_owned(),
+                &update,
+                vec![],
+            ))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // written before the sink is closed
+        let batches = read_files(&store).await;
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 1);
+        assert!(column::<UInt64Array>(&batches[0], "fee").is_null(0));
+
+        drop(rows_tx);
+        task.await.unwrap().unwrap();
+        assert_eq!(read_files(&store).await.len(), 1);
+    }
+}
@@ -0,0 +1,177 @@
+use {
+    super::{config::ConfigPartitionKeyOverride, metrics::StatsContext, transform::TransformVars},
+    crate::metrics::GprcMessageKind,
+    rdkafka::producer::{FutureProducer, Producer},
+    sha2::{Digest, Sha256},
+    std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicI32, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+    tokio::{
+        task::{self, JoinHandle},
+        time::{interval, MissedTickBehavior},
+    },
+    tracing::{info, warn},
+};
+
+/// Explicit partition for configured message kinds, other kinds use default
+/// partitioner (by key). Partition count is shared by clones and updated by
+/// [`spawn_partition_count_refresh`].
+#[derive(Debug, Clone)]
+pub struct PartitionOverrides {
+    overrides: HashMap<&'static str, ConfigPartitionKeyOverride>,
+    num_partitions: Arc<AtomicI32>,
+}
+
+impl PartitionOverrides {
+    pub fn new(
+        overrides: &HashMap<String, ConfigPartitionKeyOverride>,
+        num_partitions: i32,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(num_partitions > 0, "topic should have partitions");
+        let overrides = overrides
+            .iter()
+            .map(|(kind, value)| {
+                let kind = GprcMessageKind::from_name(kind)
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("unknown kind in `partition_key_overrides`: {kind}")
+                    })?
+                    .as_str();
+                if let ConfigPartitionKeyOverride::Fixed(partition) = value {
+                    anyhow::ensure!(
+                        (0..num_partitions).contains(partition),
+                        "partition {partition} for {kind} out of range [0, {num_partitions})"
+                    );
+                }
+                Ok((kind, value.clone()))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            overrides,
+            num_partitions: Arc::new(AtomicI32::new(num_partitions)),
+        })
+    }
+
+    pub fn num_partitions(&self) -> i32 {
+        self.num_partitions.load(Ordering::Relaxed)
+    }
+
+    /// Update partition count after the topic is scaled
+    pub fn set_num_partitions(&self, topic: &str, num_partitions: i32) {
+        if num_partitions <= 0 {
+            return;
+        }
+        let old = self.num_partitions.swap(num_partitions, Ordering::Relaxed);
+        if old != num_partitions {
+            info!("topic {topic} partition count changed from {old} to {num_partitions}");
+        }
+    }
+
+    /// At least one kind is pinned to explicit partition
+    pub fn has_fixed(&self) -> bool {
+        self.overrides
+            .values()
+            .any(|value| matches!(value, ConfigPartitionKeyOverride::Fixed(_)))
+    }
+
+    pub fn partition(&self, vars: &TransformVars<'_>) -> Option<i32> {
+        self.overrides.get(vars.kind).map(|value| match value {
+            ConfigPartitionKeyOverride::Fixed(partition) => *partition,
+            ConfigPartitionKeyOverride::Template(template) => {
+                let hash = Sha256::digest(vars.render(template));
+                let hash = u64::from_be_bytes(hash[..8].try_into().expect("valid length"));
+                (hash % self.num_partitions() as u64) as i32
+            }
+        })
+    }
+}
+
+/// Re-read partition count of the topic from metadata every `period`
+pub fn spawn_partition_count_refresh(
+    kafka: FutureProducer<StatsContext>,
+    topic: String,
+    overrides: PartitionOverrides,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut refresh_interval = interval(period);
+        refresh_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // partition count is already fetched on start
+        refresh_interval.tick().await;
+        loop {
+            refresh_interval.tick().await;
+            let kafka = kafka.clone();
+            let fetch_topic = topic.clone();
+            let result = task::spawn_blocking(move || {
+                let metadata = kafka
+                    .client()
+                    .fetch_metadata(Some(&fetch_topic), Duration::from_secs(10))?;
+                Ok::<_, rdkafka::error::KafkaError>(
+                    metadata
+                        .topics()
+                        .iter()
+                        .find(|metadata| metadata.name() == fetch_topic)
+                        .filter(|metadata| metadata.error().is_none())
+                        .map(|metadata| metadata.partitions().len() as i32),
+                )
+            })
+            .await;
+            match result {
+                Ok(Ok(Some(num_partitions))) => {
+                    overrides.set_num_partitions(&topic, num_partitions)
+                }
+                Ok(Ok(None)) => warn!("topic {topic} not found in metadata"),
+                Ok(Err(error)) => warn!("failed to fetch metadata for topic {topic}: {error}"),
+                Err(error) => warn!("metadata refresh task failed: {error}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::PartitionOverrides,
+        crate::kafka::{config::ConfigPartitionKeyOverride, transform::TransformVars},
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn partition_overrides() {
+        let overrides = HashMap::from([
+            ("slot".to_owned(), ConfigPartitionKeyOverride::Fixed(0)),
+            (
+                "block".to_owned(),
+                ConfigPartitionKeyOverride::Template("{slot}".to_owned()),
+            ),
+        ]);
+        let overrides = PartitionOverrides::new(&overrides, 4).unwrap();
+        let mut vars = TransformVars {
+            slot: 42,
+            kind: "slot",
+            hash: "abcd",
+            key: "42_abcd",
+        };
+        assert_eq!(overrides.partition(&vars), Some(0));
+        vars.kind = "block";
+        let partition = overrides.partition(&vars).unwrap();
+        assert!((0..4).contains(&partition));
+        vars.hash = "dcba";
+        assert_eq!(overrides.partition(&vars), Some(partition));
+        vars.kind = "transaction";
+        assert_eq!(overrides.partition(&vars), None);
+
+        // clones share partition count
+        overrides.clone().set_num_partitions("topic", 1);
+        assert_eq!(overrides.num_partitions(), 1);
+        vars.kind = "block";
+        assert_eq!(overrides.partition(&vars), Some(0));
+
+        let overrides = HashMap::from([("slot".to_owned(), ConfigPartitionKeyOverride::Fixed(4))]);
+        assert!(PartitionOverrides::new(&overrides, 4).is_err());
+    }
+}
@@ -0,0 +1,509 @@
+use {
+    super::{
+        config::{
+            ConfigAccountDataEncoding, ConfigAccountProjection, ConfigPayloadHashAlgorithm,
+            ConfigSignatureEncoding,
+        },
+        metrics,
+    },
+    crate::generated::prelude::SubscribeUpdateTransactionInfo as JsonTransactionInfo,
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+    prost::{DecodeError, Message},
+    serde_json::Value,
+    sha2::{Digest, Sha256},
+    tracing::debug,
+    yellowstone_grpc_proto::prelude::{
+        subscribe_update::UpdateOneof, SubscribeUpdate, SubscribeUpdateAccountInfo,
+        SubscribeUpdateTransactionInfo,
+    },
+};
+
+/// Convert transaction to the generated type with serde support, used for
+/// JSON payloads
+pub fn decode_transaction(
+    transaction: &SubscribeUpdateTransactionInfo,
+) -> Result<JsonTransactionInfo, DecodeError> {
+    JsonTransactionInfo::decode(transaction.encode_to_vec().as_slice())
+}
+
+/// Replaces fields which can't be serialized to JSON
+pub const SERIALIZE_ERROR_PLACEHOLDER: &str = "<serialize error>";
+
+/// Serialize decoded transaction to JSON with signatures in `encoding`. If
+/// serialization fails, fields are serialized one by one and failed fields
+/// are replaced with [`SERIALIZE_ERROR_PLACEHOLDER`].
+pub fn transaction_to_value(tx: &JsonTransactionInfo, encoding: ConfigSignatureEncoding) -> Value {
+    let mut value = serde_json::to_value(tx).unwrap_or_else(|_error| {
+        fields_to_value([
+            ("signature", serde_json::to_value(&tx.signature)),
+            ("is_vote", serde_json::to_value(tx.is_vote)),
+            ("transaction", serde_json::to_value(&tx.transaction)),
+            ("meta", serde_json::to_value(&tx.meta)),
+            ("index", serde_json::to_value(tx.index)),
+        ])
+    });
+    let encode = |signature: &[u8]| match encoding {
+        ConfigSignatureEncoding::Base58 => Value::String(bs58::encode(signature).into_string()),
+        ConfigSignatureEncoding::Base64 => Value::String(BASE64.encode(signature)),
+        ConfigSignatureEncoding::Bytes => Value::from(signature),
+    };
+    if encoding != ConfigSignatureEncoding::Bytes {
+        value["signature"] = encode(&tx.signature);
+        if let (Some(transaction), Some(Value::Object(object))) =
+            (&tx.transaction, value.get_mut("transaction"))
+        {
+            let signatures = transaction
+                .signatures
+                .iter()
+                .map(|signature| encode(signature))
+                .collect();
+            object.insert("signatures".to_owned(), Value::Array(signatures));
+        }
+    }
+    value
+}
+
+/// Collect serialized fields to JSON object, failed fields are replaced with
+/// [`SERIALIZE_ERROR_PLACEHOLDER`] and counted
+fn fields_to_value(
+    fields: impl IntoIterator<Item = (&'static str, serde_json::Result<Value>)>,
+) -> Value {
+    let object = fields
+        .into_iter()
+        .map(|(field, result)| {
+            let value = result.unwrap_or_else(|error| {
+                debug!("failed to serialize transaction field {field}: {error}");
+                metrics::serialize_error_inc(field);
+                Value::String(SERIALIZE_ERROR_PLACEHOLDER.to_owned())
+            });
+            (field.to_owned(), value)
+        })
+        .collect();
+    Value::Object(object)
+}
+
+/// Re-encode `data` of instructions, inner instructions and return data,
+/// no-op for [`ConfigAccountDataEncoding::Raw`]
+pub fn encode_account_data(
+    tx: &JsonTransactionInfo,
+    value: &mut Value,
+    encoding: ConfigAccountDataEncoding,
+) {
+    let encode = |data: &[u8]| match encoding {
+        ConfigAccountDataEncoding::Base64 => Value::String(BASE64.encode(data)),
+        ConfigAccountDataEncoding::Base58 => Value::String(bs58::encode(data).into_string()),
+        ConfigAccountDataEncoding::Hex => Value::String(const_hex::encode(data)),
+        ConfigAccountDataEncoding::Raw => Value::from(data),
+    };
+    if encoding == ConfigAccountDataEncoding::Raw {
+        return;
+    }
+    let mut set_data = |pointer: &str, data: &[u8]| {
+        if let Some(Value::Object(object)) = value.pointer_mut(pointer) {
+            object.insert("data".to_owned(), encode(data));
+        }
+    };
+
+    if let Some(message) = tx
+        .transaction
+        .as_ref()
+        .and_then(|transaction| transaction.message.as_ref())
+    {
+        for (idx, instruction) in message.instructions.iter().enumerate() {
+            set_data(
+                &format!("/transaction/message/instructions/{idx}"),
+                &instruction.data,
+            );
+        }
+    }
+    if let Some(meta) = &tx.meta {
+        for (idx, inner) in meta.inner_instructions.iter().enumerate() {
+            for (inner_idx, instruction) in inner.instructions.iter().enumerate() {
+                set_data(
+                    &format!("/meta/inner_instructions/{idx}/instructions/{inner_idx}"),
+                    &instruction.data,
+                );
+            }
+        }
+        if let Some(return_data) = &meta.return_data {
+            set_data("/meta/return_data", &return_data.data);
+        }
+    }
+}
+
+/// Add accounts loaded from address table lookups as top-level
+/// `loaded_addresses` object with base58 `writable` and `readonly` lists
+pub fn insert_loaded_addresses(tx: &JsonTransactionInfo, value: &mut Value) {
+    let Value::Object(object) = value else {
+        return;
+    };
+    let encode = |addresses: &[Vec<u8>]| {
+        addresses
+            .iter()
+            .map(|address| Value::String(bs58::encode(address).into_string()))
+            .collect::<Vec<_>>()
+    };
+    let (writable, readonly) = tx
+        .meta
+        .as_ref()
+        .map(|meta| {
+            (
+                encode(&meta.loaded_writable_addresses),
+                encode(&meta.loaded_readonly_addresses),
+            )
+        })
+        .unwrap_or_default();
+    object.insert(
+        "loaded_addresses".to_owned(),
+        serde_json::json!({"writable": writable, "readonly": readonly}),
+    );
+}
+
+/// Clear account fields excluded by projection, in place to avoid copies of
+/// account data
+pub fn project_account(
+    account: &mut SubscribeUpdateAccountInfo,
+    projection: ConfigAccountProjection,
+) {
+    if !projection.include_data {
+        account.data = Vec::new();
+    }
+    if !projection.include_write_version {
+        account.write_version = 0;
+    }
+    if !projection.include_txn_signature {
+        account.txn_signature = None;
+    }
+}
+
+/// Slot of the update, `None` for `Ping` / `Pong`
+pub const fn update_slot(update: &UpdateOneof) -> Option<u64> {
+    match update {
+        UpdateOneof::Account(msg) => Some(msg.slot),
+        UpdateOneof::Slot(msg) => Some(msg.slot),
+        UpdateOneof::Transaction(msg) => Some(msg.slot),
+        UpdateOneof::TransactionStatus(msg) => Some(msg.slot),
+        UpdateOneof::Block(msg) => Some(msg.slot),
+        UpdateOneof::Ping(_) | UpdateOneof::Pong(_) => None,
+        UpdateOneof::BlockMeta(msg) => Some(msg.slot),
+        UpdateOneof::Entry(msg) => Some(msg.slot),
+    }
+}
+
+/// Sha256 of the encoded update without `created_at`, same for copies of the
+/// update received over different connections
+pub fn update_hash(update: &UpdateOneof) -> [u8; 32] {
+    let mut buf = Vec::with_capacity(update.encoded_len());
+    update.encode(&mut buf);
+    Sha256::digest(&buf).into()
+}
+
+/// Hex encoded sha256 of the payload, used in message key `{slot}_{hash}`
+pub fn payload_hash(payload: &[u8]) -> String {
+    const_hex::encode(Sha256::digest(payload))
+}
+
+/// Hex encoded hash of the payload with `algorithm`
+pub fn payload_hash_with(payload: &[u8], algorithm: ConfigPayloadHashAlgorithm) -> String {
+    match algorithm {
+        ConfigPayloadHashAlgorithm::Sha256 => payload_hash(payload),
+        ConfigPayloadHashAlgorithm::Blake3 => const_hex::encode(blake3::hash(payload).as_bytes()),
+        ConfigPayloadHashAlgorithm::Xxhash64 => {
+            const_hex::encode(xxhash_rust::xxh64::xxh64(payload, 0).to_be_bytes())
+        }
+    }
+}
+
+/// Slot and hash of message key `{slot}_{hash}`, hash is hex of any length,
+/// see [`payload_hash_with`]
+pub fn parse_message_key(key: &str) -> Option<(u64, Vec<u8>)> {
+    let (slot, hash) = key.split_once('_')?;
+    let slot = slot.parse::<u64>().ok()?;
+    let hash = const_hex::decode(hash).ok()?;
+    (!hash.is_empty()).then_some((slot, hash))
+}
+
+/// Base58 pubkey if payload is protobuf account update
+pub fn account_pubkey(payload: &[u8]) -> Option<String> {
+    match SubscribeUpdate::decode(payload).ok()?.update_oneof? {
+        UpdateOneof::Account(msg) => msg
+            .account
+            .map(|account| bs58::encode(account.pubkey).into_string()),
+        _ => None,
+    }
+}
+
+/// Coarse category of protobuf decode error for metrics
+pub fn decode_error_kind(error: &DecodeError) -> &'static str {
+    let message = error.to_string();
+    if message.contains("buffer underflow") || message.contains("length exceeded") {
+        "truncated"
+    } else if message.contains("invalid") || message.contains("unexpected") {
+        "invalid_field"
+    } else {
+        "unknown"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            decode_error_kind, encode_account_data, fields_to_value, insert_loaded_addresses,
+            metrics, parse_message_key, payload_hash, payload_hash_with, project_account,
+            transaction_to_value, SERIALIZE_ERROR_PLACEHOLDER,
+        },
+        crate::{
+            generated::{
+                prelude::SubscribeUpdateTransactionInfo as JsonTransactionInfo,
+                solana::storage::confirmed_block::{
+                    CompiledInstruction, InnerInstruction, InnerInstructions,
+                    Message as TransactionMessage, ReturnData, Transaction, TransactionStatusMeta,
+                },
+            },
+            kafka::config::{
+                ConfigAccountDataEncoding, ConfigAccountProjection, ConfigPayloadHashAlgorithm,
+                ConfigSignatureEncoding,
+            },
+            kafka::dedup::{KafkaDedup, KafkaDedupMemory},
+        },
+        prost::Message,
+        serde_json::json,
+        std::collections::HashMap,
+        yellowstone_grpc_proto::prelude::{
+            subscribe_update::UpdateOneof, SubscribeUpdate, SubscribeUpdateAccountInfo,
+            SubscribeUpdateSlot,
+        },
+    };
+
+    #[test]
+    fn transaction_signature_encoding() {
+        let tx = JsonTransactionInfo {
+            signature: vec![1, 2, 3],
+            transaction: Some(Transaction {
+                signatures: vec![vec![1, 2, 3], vec![255]],
+                message: None,
+            }),
+            ..Default::default()
+        };
+
+        let value = transaction_to_value(&tx, ConfigSignatureEncoding::Base58);
+        assert_eq!(value["signature"], json!("Ldp"));
+        assert_eq!(value["transaction"]["signatures"], json!(["Ldp", "5Q"]));
+
+        let value = transaction_to_value(&tx, ConfigSignatureEncoding::Base64);
+        assert_eq!(value["signature"], json!("AQID"));
+        assert_eq!(value["transaction"]["signatures"], json!(["AQID", "/w=="]));
+
+        let value = transaction_to_value(&tx, ConfigSignatureEncoding::Bytes);
+        assert_eq!(value["signature"], json!([1, 2, 3]));
+        assert_eq!(
+            value["transaction"]["signatures"],
+            json!([[1, 2, 3], [255]])
+        );
+    }
+
+    #[test]
+    fn serialize_error_placeholder() {
+        // generated types always serialize, map with non-string keys does not
+        let failed = serde_json::to_value(HashMap::from([((1u8, 2u8), 3u8)]));
+        assert!(failed.is_err());
+
+        let errors = metrics::SERIALIZE_ERRORS_TOTAL.with_label_values(&["meta"]);
+        let errors_before = errors.get();
+        let value = fields_to_value([
+            ("signature", serde_json::to_value([1u8, 2, 3])),
+            ("meta", failed),
+            ("index", serde_json::to_value(7u64)),
+        ]);
+        assert_eq!(
+            value,
+            json!({
+                "signature": [1, 2, 3],
+                "meta": SERIALIZE_ERROR_PLACEHOLDER,
+                "index": 7,
+            })
+        );
+        assert_eq!(errors.get(), errors_before + 1);
+    }
+
+    #[test]
+    fn account_data_encoding() {
+        let tx = JsonTransactionInfo {
+            transaction: Some(Transaction {
+                signatures: vec![],
+                message: Some(TransactionMessage {
+                    instructions: vec![CompiledInstruction {
+                        data: vec![1, 2, 3],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+            }),
+            meta: Some(TransactionStatusMeta {
+                inner_instructions: vec![InnerInstructions {
+                    index: 0,
+                    instructions: vec![InnerInstruction {
+                        data: vec![255],
+                        ..Default::default()
+                    }],
+                }],
+                return_data: Some(ReturnData {
+                    program_id: vec![],
+                    data: vec![1, 2, 3],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let encoded = |encoding| {
+            let mut value = transaction_to_value(&tx, ConfigSignatureEncoding::Bytes);
+            encode_account_data(&tx, &mut value, encoding);
+            [
+                value["transaction"]["message"]["instructions"][0]["data"].clone(),
+                value["meta"]["inner_instructions"][0]["instructions"][0]["data"].clone(),
+                value["meta"]["return_data"]["data"].clone(),
+            ]
+        };
+
+        assert_eq!(
+            encoded(ConfigAccountDataEncoding::Raw),
+            [json!([1, 2, 3]), json!([255]), json!([1, 2, 3])]
+        );
+        assert_eq!(
+            encoded(ConfigAccountDataEncoding::Base64),
+            [json!("AQID"), json!("/w=="), json!("AQID")]
+        );
+        assert_eq!(
+            encoded(ConfigAccountDataEncoding::Base58),
+            [json!("Ldp"), json!("5Q"), json!("Ldp")]
+        );
+        assert_eq!(
+            encoded(ConfigAccountDataEncoding::Hex),
+            [json!("010203"), json!("ff"), json!("010203")]
+        );
+    }
+
+    #[test]
+    fn loaded_addresses() {
+        let tx = JsonTransactionInfo {
+            meta: Some(TransactionStatusMeta {
+                loaded_writable_addresses: vec![vec![1, 2, 3]],
+                loaded_readonly_addresses: vec![vec![255], vec![1, 2, 3]],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut value = transaction_to_value(&tx, ConfigSignatureEncoding::Base58);
+        insert_loaded_addresses(&tx, &mut value);
+        assert_eq!(
+            value["loaded_addresses"],
+            json!({"writable": ["Ldp"], "readonly": ["5Q", "Ldp"]})
+        );
+
+        let tx = JsonTransactionInfo::default();
+        let mut value = transaction_to_value(&tx, ConfigSignatureEncoding::Base58);
+        insert_loaded_addresses(&tx, &mut value);
+        assert_eq!(
+            value["loaded_addresses"],
+            json!({"writable": [], "readonly": []})
+        );
+    }
+
+    #[test]
+    fn account_projection() {
+        let account = SubscribeUpdateAccountInfo {
+            pubkey: vec![1; 32],
+            lamports: 42,
+            owner: vec![2; 32],
+            executable: false,
+            rent_epoch: 7,
+            data: vec![0; 1024],
+            write_version: 100,
+            txn_signature: Some(vec![3; 64]),
+        };
+
+        let mut projected = account.clone();
+        project_account(&mut projected, ConfigAccountProjection::default());
+        assert_eq!(
+            projected,
+            SubscribeUpdateAccountInfo {
+                data: vec![],
+                write_version: 0,
+                txn_signature: None,
+                ..account.clone()
+            }
+        );
+
+        let mut projected = account.clone();
+        project_account(
+            &mut projected,
+            ConfigAccountProjection {
+                include_data: true,
+                include_write_version: true,
+                include_txn_signature: true,
+            },
+        );
+        assert_eq!(projected, account);
+    }
+
+    #[test]
+    fn payload_hash_algorithms() {
+        assert_eq!(
+            payload_hash_with(b"", ConfigPayloadHashAlgorithm::Sha256),
+            payload_hash(b"")
+        );
+        assert_eq!(
+            payload_hash_with(b"", ConfigPayloadHashAlgorithm::Blake3),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            payload_hash_with(b"", ConfigPayloadHashAlgorithm::Xxhash64),
+            "ef46db3751d8e999"
+        );
+    }
+
+    #[tokio::test]
+    async fn message_key_parse() {
+        // keys are `{slot}_{hash}` as produced by grpc2kafka
+        for algorithm in [
+            ConfigPayloadHashAlgorithm::Sha256,
+            ConfigPayloadHashAlgorithm::Blake3,
+            ConfigPayloadHashAlgorithm::Xxhash64,
+        ] {
+            let key = format!("42_{}", payload_hash_with(b"payload", algorithm));
+            let (slot, hash) = parse_message_key(&key).unwrap();
+            assert_eq!(slot, 42);
+            assert_eq!(const_hex::encode(&hash), key[3..]);
+
+            let dedup = KafkaDedupMemory::default();
+            assert!(dedup.allowed(slot, &hash).await);
+            assert!(!dedup.allowed(slot, &hash).await);
+        }
+        let (_slot, hash) = parse_message_key("42_ef46db3751d8e999").unwrap();
+        assert_eq!(hash.len(), 8);
+
+        for key in ["42", "slot_ef46db3751d8e999", "42_", "42_abc", "42_zz"] {
+            assert_eq!(parse_message_key(key), None, "{key}");
+        }
+    }
+
+    #[test]
+    fn decode_error_kinds() {
+        let payload = SubscribeUpdate {
+            filters: vec!["client".to_owned()],
+            update_oneof: Some(UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot: 42,
+                ..Default::default()
+            })),
+            created_at: None,
+        }
+        .encode_to_vec();
+
+        let error = SubscribeUpdate::decode(&payload[..payload.len() - 1]).unwrap_err();
+        assert_eq!(decode_error_kind(&error), "truncated");
+
+        let error = SubscribeUpdate::decode(&[0x00][..]).unwrap_err();
+        assert_eq!(decode_error_kind(&error), "invalid_field");
+    }
+}
@@ -0,0 +1,64 @@
+use tokio::time::{sleep, Duration, Instant};
+
+/// Token bucket with burst equal to one second of the rate, tokens can go
+/// negative: every caller reserves a token and waits for its turn
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate_per_second: u64) -> Self {
+        let rate = rate_per_second.max(1) as f64;
+        Self {
+            rate,
+            tokens: rate,
+            updated_at: Instant::now(),
+        }
+    }
+
+    /// Take one token, returns time to wait if bucket is empty
+    fn take(&mut self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate) - 1.0;
+        self.updated_at = now;
+        (self.tokens < 0.0).then(|| Duration::from_secs_f64(-self.tokens / self.rate))
+    }
+
+    /// Wait for a token, returns `true` if caller was delayed
+    pub async fn acquire(&mut self) -> bool {
+        match self.take(Instant::now()) {
+            Some(delay) => {
+                sleep(delay).await;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::TokenBucket,
+        tokio::time::{Duration, Instant},
+    };
+
+    #[test]
+    fn token_bucket() {
+        let mut bucket = TokenBucket::new(2);
+        let now = bucket.updated_at;
+        assert_eq!(bucket.take(now), None);
+        assert_eq!(bucket.take(now), None);
+        assert_eq!(bucket.take(now), Some(Duration::from_millis(500)));
+        assert_eq!(bucket.take(now), Some(Duration::from_secs(1)));
+
+        // refill is capped by burst
+        let now: Instant = now + Duration::from_secs(10);
+        assert_eq!(bucket.take(now), None);
+        assert_eq!(bucket.take(now), None);
+        assert!(bucket.take(now).is_some());
+    }
+}
@@ -0,0 +1,124 @@
+use {
+    serde::Serialize,
+    std::{
+        collections::BTreeMap,
+        sync::atomic::{AtomicBool, AtomicI64, AtomicU8, Ordering},
+    },
+};
+
+static REQUIRED: AtomicU8 = AtomicU8::new(0);
+static KAFKA_ERROR: AtomicBool = AtomicBool::new(false);
+static GRPC_CONNECTIONS: AtomicI64 = AtomicI64::new(0);
+static PARTITIONS_ASSIGNED: AtomicI64 = AtomicI64::new(0);
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Dependency checked by `/ready`, only required dependencies are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Dependency {
+    /// Kafka client error callback was not fired
+    Kafka = 1,
+    /// At least one subscribed gRPC connection
+    Grpc = 2,
+    /// Consumer has assigned partitions
+    Partitions = 4,
+}
+
+impl Dependency {
+    const ALL: [Self; 3] = [Self::Kafka, Self::Grpc, Self::Partitions];
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Kafka => "kafka",
+            Self::Grpc => "grpc",
+            Self::Partitions => "partitions",
+        }
+    }
+
+    /// Mark dependency as critical for readiness of the process
+    pub fn require(self) {
+        REQUIRED.fetch_or(self as u8, Ordering::Relaxed);
+    }
+
+    fn status(self) -> DependencyStatus {
+        match self {
+            Self::Kafka => {
+                let error = KAFKA_ERROR.load(Ordering::Relaxed);
+                DependencyStatus {
+                    ok: !error,
+                    detail: if error { "client error" } else { "ok" }.to_owned(),
+                }
+            }
+            Self::Grpc => {
+                let connections = GRPC_CONNECTIONS.load(Ordering::Relaxed);
+                DependencyStatus {
+                    ok: connections > 0,
+                    detail: format!("{connections} connections"),
+                }
+            }
+            Self::Partitions => {
+                let partitions = PARTITIONS_ASSIGNED.load(Ordering::Relaxed);
+                DependencyStatus {
+                    ok: partitions > 0,
+                    detail: format!("{partitions} partitions assigned"),
+                }
+            }
+        }
+    }
+}
+
+pub fn set_kafka_error() {
+    KAFKA_ERROR.store(true, Ordering::Relaxed);
+}
+
+pub fn grpc_connected() {
+    GRPC_CONNECTIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn grpc_disconnected() {
+    GRPC_CONNECTIONS.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Shutdown signal received, process is not ready regardless of dependencies
+pub fn set_shutting_down() {
+    SHUTTING_DOWN.store(true, Ordering::Relaxed);
+}
+
+pub fn is_shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Relaxed)
+}
+
+pub fn set_partitions_assigned(count: usize) {
+    PARTITIONS_ASSIGNED.store(count as i64, Ordering::Relaxed);
+}
+
+#[derive(Debug, Serialize)]
+pub struct DependencyStatus {
+    pub ok: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub shutting_down: bool,
+    pub dependencies: BTreeMap<&'static str, DependencyStatus>,
+}
+
+impl Readiness {
+    /// Status of required dependencies, ready if all of them are up
+    pub fn check() -> Self {
+        let required = REQUIRED.load(Ordering::Relaxed);
+        let dependencies = Dependency::ALL
+            .into_iter()
+            .filter(|dependency| required & *dependency as u8 != 0)
+            .map(|dependency| (dependency.as_str(), dependency.status()))
+            .collect::<BTreeMap<_, _>>();
+        let shutting_down = is_shutting_down();
+        Self {
+            ready: !shutting_down && dependencies.values().all(|status| status.ok),
+            shutting_down,
+            dependencies,
+        }
+    }
+}
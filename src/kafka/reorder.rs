@@ -0,0 +1,136 @@
+use {
+    std::collections::{BTreeMap, VecDeque},
+    tracing::{info, warn},
+};
+
+/// Sorts messages by slot during backfill from `from_slot`, when historical
+/// and live messages can be interleaved. Message is released when its slot
+/// is `window_slots` behind the max seen slot. After `settle_messages`
+/// consecutive messages within the window the buffer is flushed and messages
+/// are passed through.
+#[derive(Debug)]
+pub struct SlotReorderBuffer<T> {
+    window_slots: u64,
+    max_buffered_messages: usize,
+    settle_messages: usize,
+    buffer: BTreeMap<(u64, u64), T>,
+    ready: VecDeque<T>,
+    seq: u64,
+    max_slot: u64,
+    released_slot: u64,
+    settled: usize,
+    passthrough: bool,
+}
+
+impl<T> SlotReorderBuffer<T> {
+    pub const fn new(
+        window_slots: u64,
+        max_buffered_messages: usize,
+        settle_messages: usize,
+    ) -> Self {
+        Self {
+            window_slots,
+            max_buffered_messages,
+            settle_messages,
+            buffer: BTreeMap::new(),
+            ready: VecDeque::new(),
+            seq: 0,
+            max_slot: 0,
+            released_slot: 0,
+            settled: 0,
+            passthrough: false,
+        }
+    }
+
+    /// Messages without slot and messages below already released slot are
+    /// passed through
+    pub fn push(&mut self, slot: Option<u64>, message: T) {
+        let Some(slot) = slot.filter(|slot| !self.passthrough && *slot >= self.released_slot)
+        else {
+            self.ready.push_back(message);
+            return;
+        };
+
+        if slot + self.window_slots >= self.max_slot {
+            self.settled += 1;
+        } else {
+            self.settled = 0;
+        }
+        self.max_slot = self.max_slot.max(slot);
+        self.buffer.insert((slot, self.seq), message);
+        self.seq += 1;
+
+        if self.settled >= self.settle_messages {
+            info!(
+                "backfill caught up at slot {}, {} buffered messages flushed, switch to passthrough",
+                self.max_slot,
+                self.buffer.len()
+            );
+            self.passthrough = true;
+            self.ready
+                .extend(std::mem::take(&mut self.buffer).into_values());
+            return;
+        }
+
+        let mut len = self.buffer.len();
+        while let Some(entry) = self.buffer.first_entry() {
+            let (slot, _seq) = *entry.key();
+            if slot + self.window_slots > self.max_slot && len <= self.max_buffered_messages {
+                break;
+            }
+            if slot + self.window_slots > self.max_slot {
+                warn!("backfill reorder buffer is full, release slot {slot} early");
+            }
+            self.released_slot = slot;
+            self.ready.push_back(entry.remove());
+            len -= 1;
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.ready.pop_front()
+    }
+
+    pub const fn is_passthrough(&self) -> bool {
+        self.passthrough
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SlotReorderBuffer;
+
+    fn drain(buffer: &mut SlotReorderBuffer<u64>) -> Vec<u64> {
+        std::iter::from_fn(|| buffer.pop()).collect()
+    }
+
+    #[test]
+    fn reorder_backfill() {
+        let mut buffer = SlotReorderBuffer::new(2, 100, 3);
+        for slot in [10, 20, 15, 19, 18] {
+            buffer.push(Some(slot), slot);
+        }
+        assert_eq!(drain(&mut buffer), [10, 15, 18]);
+
+        buffer.push(None, 0);
+        buffer.push(Some(16), 16);
+        assert_eq!(drain(&mut buffer), [0, 16]);
+
+        // three consecutive messages within the window switch to passthrough
+        assert!(!buffer.is_passthrough());
+        buffer.push(Some(21), 21);
+        assert!(buffer.is_passthrough());
+        assert_eq!(drain(&mut buffer), [19, 20, 21]);
+        buffer.push(Some(1), 1);
+        assert_eq!(drain(&mut buffer), [1]);
+    }
+
+    #[test]
+    fn reorder_bounded() {
+        let mut buffer = SlotReorderBuffer::new(100, 2, 1_000);
+        for slot in [5, 3, 4] {
+            buffer.push(Some(slot), slot);
+        }
+        assert_eq!(drain(&mut buffer), [3]);
+    }
+}
@@ -0,0 +1,444 @@
+use {
+    super::{
+        grpc::{account_filter_header, ClientAccounts},
+        metrics, payload,
+    },
+    base64::{engine::general_purpose::STANDARD as BASE64, Engine},
+    rdkafka::{
+        config::ClientConfig,
+        consumer::{Consumer, StreamConsumer},
+        message::{Message, Timestamp},
+        topic_partition_list::{Offset, TopicPartitionList},
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        fmt,
+        str::FromStr,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tokio::{
+        sync::{mpsc, watch},
+        task,
+        time::{timeout, Instant},
+    },
+    tonic::{metadata::MetadataValue, Result as TonicResult, Status},
+    tracing::warn,
+    yellowstone_grpc_proto::{prelude::SubscribeUpdate, prost::Message as _},
+};
+
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+/// Check consumer position if no messages received in this interval
+const REPLAY_IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Stop replay if no messages received and end is not reached
+const REPLAY_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Request metadata with cursor to resume replay after
+pub const RESUME_CURSOR_HEADER: &str = "x-resume-cursor";
+/// Request metadata to receive cursor of every message in `filters`
+pub const INCLUDE_CURSOR_HEADER: &str = "x-include-cursor";
+/// Prefix of the cursor added to `filters` with [`INCLUDE_CURSOR_HEADER`]
+pub const CURSOR_FILTER_PREFIX: &str = "x-cursor:";
+/// Trailer with cursor of the last sent message, only present if the
+/// server ends the stream (shutdown or lag)
+pub const CURSOR_TRAILER: &str = "x-cursor";
+
+/// Position of a replayed message, opaque base64 for clients
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayCursor {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    /// Message timestamp in milliseconds
+    pub timestamp: i64,
+}
+
+impl fmt::Display for ReplayCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_vec(self).map_err(|_error| fmt::Error)?;
+        f.write_str(&BASE64.encode(json))
+    }
+}
+
+impl ReplayCursor {
+    pub fn from_message<M: Message>(message: &M) -> Self {
+        Self {
+            topic: message.topic().to_owned(),
+            partition: message.partition(),
+            offset: message.offset(),
+            timestamp: match message.timestamp() {
+                Timestamp::NotAvailable => 0,
+                Timestamp::CreateTime(ts) | Timestamp::LogAppendTime(ts) => ts,
+            },
+        }
+    }
+}
+
+impl FromStr for ReplayCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json = BASE64.decode(s.trim())?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+/// Cursors of messages sent to one client
+#[derive(Debug, Default)]
+pub struct ClientCursor {
+    include_in_filters: bool,
+    last: Option<ReplayCursor>,
+}
+
+impl ClientCursor {
+    pub const fn new(include_in_filters: bool) -> Self {
+        Self {
+            include_in_filters,
+            last: None,
+        }
+    }
+
+    /// Add cursor to `filters` if the client asked for it
+    pub fn attach(&self, update: &mut SubscribeUpdate, cursor: Option<&ReplayCursor>) {
+        if let (true, Some(cursor)) = (self.include_in_filters, cursor) {
+            update
+                .filters
+                .push(format!("{CURSOR_FILTER_PREFIX}{cursor}"));
+        }
+    }
+
+    /// Remember cursor of the message accepted by the client stream
+    pub fn sent(&mut self, cursor: Option<ReplayCursor>) {
+        if cursor.is_some() {
+            self.last = cursor;
+        }
+    }
+
+    pub const fn last(&self) -> Option<&ReplayCursor> {
+        self.last.as_ref()
+    }
+
+    /// Add [`CURSOR_TRAILER`] with the last sent cursor to the final status
+    pub fn trailer(&self, mut status: Status) -> Status {
+        if let Some(value) = self
+            .last
+            .as_ref()
+            .and_then(|cursor| cursor.to_string().parse::<MetadataValue<_>>().ok())
+        {
+            status.metadata_mut().insert(CURSOR_TRAILER, value);
+        }
+        status
+    }
+}
+
+/// Historical messages from the topic for new gRPC clients, every client
+/// reads with own consumer without group commits
+#[derive(Debug, Clone)]
+pub struct KafkaReplay {
+    pub kafka_config: ClientConfig,
+    pub topic: String,
+    pub window: Duration,
+    /// Skip messages with account header not in the client subscription
+    pub filter_from_headers: bool,
+}
+
+impl KafkaReplay {
+    /// Send messages from `now - window` up to the current end of the topic,
+    /// returns number of sent messages. With `cursor` the cursor partition
+    /// is resumed after the cursor offset and other partitions from the
+    /// cursor timestamp. Cursors of sent messages are tracked by
+    /// `client_cursor`.
+    pub async fn replay(
+        &self,
+        client_id: usize,
+        cursor: Option<&ReplayCursor>,
+        accounts: &watch::Receiver<ClientAccounts>,
+        client_cursor: &mut ClientCursor,
+        stream_tx: &mpsc::Sender<TonicResult<SubscribeUpdate>>,
+    ) -> anyhow::Result<usize> {
+        if let Some(cursor) = cursor {
+            anyhow::ensure!(
+                cursor.topic == self.topic,
+                "cursor topic {} doesn't match {}",
+                cursor.topic,
+                self.topic
+            );
+        }
+        let consumer: StreamConsumer = self
+            .kafka_config
+            .clone()
+            .set("group.id", format!("kafka2grpc-replay-{client_id}"))
+            .set("enable.auto.commit", "false")
+            .create()?;
+
+        let since = match cursor {
+            Some(cursor) => cursor.timestamp,
+            None => SystemTime::now()
+                .checked_sub(self.window)
+                .unwrap_or(UNIX_EPOCH)
+                .duration_since(UNIX_EPOCH)?
+                .as_millis() as i64,
+        };
+        // metadata requests block on the broker
+        let topic = self.topic.clone();
+        let (consumer, watermarks, since_offsets) = task::spawn_blocking(move || {
+            let metadata = consumer.fetch_metadata(Some(&topic), METADATA_TIMEOUT)?;
+            let mut timestamps = TopicPartitionList::new();
+            let mut watermarks = HashMap::new();
+            for partition in metadata
+                .topics()
+                .iter()
+                .filter(|metadata| metadata.name() == topic)
+                .flat_map(|metadata| metadata.partitions())
+            {
+                let (low, high) =
+                    consumer.fetch_watermarks(&topic, partition.id(), METADATA_TIMEOUT)?;
+                if high > low {
+                    timestamps.add_partition_offset(
+                        &topic,
+                        partition.id(),
+                        Offset::Offset(since),
+                    )?;
+                    watermarks.insert(partition.id(), (low, high));
+                }
+            }
+            // partitions without messages after `since` are resolved to `End`
+            let since_offsets = consumer
+                .offsets_for_times(timestamps, METADATA_TIMEOUT)?
+                .elements()
+                .iter()
+                .map(|elem| (elem.partition(), elem.offset()))
+                .collect::<Vec<_>>();
+            Ok::<_, anyhow::Error>((consumer, watermarks, since_offsets))
+        })
+        .await??;
+
+        let mut assignment = TopicPartitionList::new();
+        let mut end_offsets = ReplayEnd::default();
+        for (partition, start, end) in replay_assignment(&watermarks, &since_offsets, cursor) {
+            assignment.add_partition_offset(&self.topic, partition, Offset::Offset(start))?;
+            end_offsets.insert(partition, end);
+        }
+        consumer.assign(&assignment)?;
+
+        let mut count = 0;
+        let mut idle_since = Instant::now();
+        while !end_offsets.is_done() {
+            let message = match timeout(REPLAY_IDLE_CHECK_INTERVAL, consumer.recv()).await {
+                Ok(message) => message?,
+                Err(_elapsed) => {
+                    // offsets of transaction markers and compacted messages
+                    // are never received, use consumer position instead
+                    for elem in consumer.position()?.elements() {
+                        if let Offset::Offset(position) = elem.offset() {
+                            end_offsets.advance(elem.partition(), position);
+                        }
+                    }
+                    if !end_offsets.is_done() && idle_since.elapsed() >= REPLAY_IDLE_TIMEOUT {
+                        warn!(
+                            "client #{client_id}: replay stopped, no messages in {REPLAY_IDLE_TIMEOUT:?}"
+                        );
+                        break;
+                    }
+                    continue;
+                }
+            };
+            idle_since = Instant::now();
+            end_offsets.advance(message.partition(), message.offset() + 1);
+            let Some(payload) = message.payload() else {
+                continue;
+            };
+            if self.filter_from_headers
+                && !accounts
+                    .borrow()
+                    .allows(account_filter_header(message.headers()).as_deref())
+            {
+                continue;
+            }
+            match SubscribeUpdate::decode(payload) {
+                Ok(mut update) => {
+                    let cursor = ReplayCursor::from_message(&message);
+                    client_cursor.attach(&mut update, Some(&cursor));
+                    if stream_tx.send(Ok(update)).await.is_err() {
+                        break;
+                    }
+                    client_cursor.sent(Some(cursor));
+                    count += 1;
+                }
+                Err(error) => metrics::decode_error_inc(payload::decode_error_kind(&error)),
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Start and end offsets of partitions with messages to replay. End offsets
+/// are high watermarks, start offsets are resolved from the replay start
+/// time or from the cursor for the cursor partition.
+fn replay_assignment(
+    watermarks: &HashMap<i32, (i64, i64)>,
+    since_offsets: &[(i32, Offset)],
+    cursor: Option<&ReplayCursor>,
+) -> Vec<(i32, i64, i64)> {
+    let mut assignment = since_offsets
+        .iter()
+        .filter_map(|(partition, offset)| {
+            let (low, high) = *watermarks.get(partition)?;
+            let start = match (cursor, offset) {
+                (Some(cursor), _) if cursor.partition == *partition => cursor.offset + 1,
+                (_, Offset::Offset(offset)) => *offset,
+                _ => return None,
+            };
+            // messages after the cursor can be already removed by retention
+            let start = start.max(low);
+            (start < high).then_some((*partition, start, high))
+        })
+        .collect::<Vec<_>>();
+    assignment.sort_unstable();
+    assignment
+}
+
+/// Partitions which are not replayed up to their end offset yet
+#[derive(Debug, Default)]
+struct ReplayEnd(HashMap<i32, i64>);
+
+impl ReplayEnd {
+    fn insert(&mut self, partition: i32, end: i64) {
+        self.0.insert(partition, end);
+    }
+
+    /// `position` is the next offset to read from the partition
+    fn advance(&mut self, partition: i32, position: i64) {
+        if self.0.get(&partition).is_some_and(|end| position >= *end) {
+            self.0.remove(&partition);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            replay_assignment, ClientCursor, ReplayCursor, ReplayEnd, CURSOR_FILTER_PREFIX,
+            CURSOR_TRAILER,
+        },
+        rdkafka::topic_partition_list::Offset,
+        std::collections::HashMap,
+        tonic::Status,
+        yellowstone_grpc_proto::prelude::SubscribeUpdate,
+    };
+
+    #[test]
+    fn cursor_roundtrip() {
+        let cursor = ReplayCursor {
+            topic: "grpc1".to_owned(),
+            partition: 3,
+            offset: 42,
+            timestamp: 1_700_000_000_000,
+        };
+        let encoded = cursor.to_string();
+        assert!(encoded.is_ascii());
+        assert_eq!(encoded.parse::<ReplayCursor>().unwrap(), cursor);
+        assert!("not a cursor".parse::<ReplayCursor>().is_err());
+        assert!("e30=".parse::<ReplayCursor>().is_err()); // `{}`
+    }
+
+    #[test]
+    fn client_cursor() {
+        let cursor = |offset| ReplayCursor {
+            topic: "grpc1".to_owned(),
+            partition: 0,
+            offset,
+            timestamp: 0,
+        };
+        let trailer = |client_cursor: &ClientCursor| {
+            client_cursor
+                .trailer(Status::internal("lagged"))
+                .metadata()
+                .get(CURSOR_TRAILER)
+                .map(|value| value.to_str().unwrap().parse::<ReplayCursor>().unwrap())
+        };
+
+        // filters are not changed by default
+        let mut client_cursor = ClientCursor::default();
+        let mut update = SubscribeUpdate {
+            filters: vec!["client".to_owned()],
+            ..Default::default()
+        };
+        client_cursor.attach(&mut update, Some(&cursor(1)));
+        assert_eq!(update.filters, vec!["client".to_owned()]);
+        assert_eq!(trailer(&client_cursor), None);
+
+        // only cursors of sent messages are in the trailer, messages without
+        // cursor keep the previous one
+        client_cursor.sent(Some(cursor(1)));
+        client_cursor.sent(None);
+        assert_eq!(trailer(&client_cursor), Some(cursor(1)));
+
+        let client_cursor = ClientCursor::new(true);
+        client_cursor.attach(&mut update, Some(&cursor(2)));
+        assert_eq!(update.filters.len(), 2);
+        assert_eq!(
+            update.filters[1]
+                .strip_prefix(CURSOR_FILTER_PREFIX)
+                .map(|value| value.parse::<ReplayCursor>().unwrap()),
+            Some(cursor(2))
+        );
+    }
+
+    #[test]
+    fn replay_assignment_offsets() {
+        // partition 3 is empty and not requested
+        let watermarks = HashMap::from([(0, (0, 100)), (1, (50, 60)), (2, (10, 20))]);
+        let since_offsets = [
+            (0, Offset::Offset(90)),
+            (1, Offset::End),
+            (2, Offset::Offset(10)),
+        ];
+        assert_eq!(
+            replay_assignment(&watermarks, &since_offsets, None),
+            vec![(0, 90, 100), (2, 10, 20)]
+        );
+
+        let cursor = |partition, offset| ReplayCursor {
+            topic: "grpc1".to_owned(),
+            partition,
+            offset,
+            timestamp: 0,
+        };
+        // cursor partition resumes after the cursor, even without messages
+        // after the cursor timestamp
+        assert_eq!(
+            replay_assignment(&watermarks, &since_offsets, Some(&cursor(1, 54))),
+            vec![(0, 90, 100), (1, 55, 60), (2, 10, 20)]
+        );
+        // cursor at the end of the partition
+        assert_eq!(
+            replay_assignment(&watermarks, &since_offsets, Some(&cursor(2, 19))),
+            vec![(0, 90, 100)]
+        );
+        // messages after the cursor are removed by retention
+        assert_eq!(
+            replay_assignment(&watermarks, &since_offsets, Some(&cursor(1, 10))),
+            vec![(0, 90, 100), (1, 50, 60), (2, 10, 20)]
+        );
+    }
+
+    #[test]
+    fn replay_end() {
+        let mut end = ReplayEnd::default();
+        end.insert(0, 100);
+        end.insert(1, 60);
+        end.advance(0, 99);
+        end.advance(2, 1_000); // not replayed partition
+        assert!(!end.is_done());
+        // position after the last message, e.g. last offset is a transaction marker
+        end.advance(1, 61);
+        end.advance(0, 100);
+        assert!(end.is_done());
+    }
+}
@@ -0,0 +1,68 @@
+use {crate::metrics::GprcMessageKind, fastrand::Rng, std::collections::HashMap};
+
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    rates: HashMap<&'static str, f64>,
+    rng: Rng,
+}
+
+impl Sampler {
+    pub fn new(rates: &HashMap<String, f64>, seed: Option<u64>) -> anyhow::Result<Self> {
+        let rates = rates
+            .iter()
+            .map(|(kind, rate)| {
+                let kind = GprcMessageKind::from_name(kind)
+                    .ok_or_else(|| anyhow::anyhow!("unknown kind in `sample_rate`: {kind}"))?
+                    .as_str();
+                anyhow::ensure!(
+                    (0.0..=1.0).contains(rate),
+                    "`sample_rate` for {kind} should be in range 0.0-1.0, got {rate}"
+                );
+                Ok((kind, *rate))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let rng = match seed {
+            Some(seed) => Rng::with_seed(seed),
+            None => Rng::new(),
+        };
+        Ok(Self { rates, rng })
+    }
+
+    /// Returns `false` if message should be dropped
+    pub fn keep(&mut self, kind: GprcMessageKind) -> bool {
+        match self.rates.get(kind.as_str()) {
+            Some(rate) if *rate >= 1.0 => true,
+            Some(rate) => self.rng.f64() < *rate,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::Sampler, crate::metrics::GprcMessageKind, std::collections::HashMap};
+
+    #[test]
+    fn sampler_seeded() {
+        let rates = HashMap::from([("account".to_owned(), 0.1), ("slot".to_owned(), 0.0)]);
+        let mut sampler = Sampler::new(&rates, Some(42)).unwrap();
+        let kept = (0..10_000)
+            .map(|_| sampler.keep(GprcMessageKind::Account))
+            .collect::<Vec<_>>();
+        let count = kept.iter().filter(|keep| **keep).count();
+        assert!((800..1_200).contains(&count), "kept {count}");
+        assert!(!sampler.keep(GprcMessageKind::Slot));
+        assert!(sampler.keep(GprcMessageKind::Transaction));
+
+        let mut sampler = Sampler::new(&rates, Some(42)).unwrap();
+        let kept2 = (0..10_000)
+            .map(|_| sampler.keep(GprcMessageKind::Account))
+            .collect::<Vec<_>>();
+        assert_eq!(kept, kept2);
+
+        let rates = HashMap::from([("accounts".to_owned(), 0.5)]);
+        assert!(Sampler::new(&rates, None).is_err());
+        let rates = HashMap::from([("account".to_owned(), 1.5)]);
+        assert!(Sampler::new(&rates, None).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! Line based script applied to JSON payloads, one statement per line:
+//!
+//! ```text
+//! # comment
+//! skip if $.is_vote == true
+//! drop $.meta.log_messages
+//! set $.meta.inner_instructions = [] if exists $.meta.inner_instructions
+//! key = {slot}_{$.index}
+//! ```
+//!
+//! Statements are `skip`, `drop <path>`, `set <path> = <json>` and
+//! `key = <template>`, with optional ` if <path> == <json>`,
+//! ` if <path> != <json>` or ` if exists <path>` condition. Key template
+//! supports `transform_chain` placeholders and `{<path>}` field values.
+
+use {
+    super::transform::{json_path_get, json_path_remove, json_path_set},
+    serde_json::Value,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Skip,
+    Drop(String),
+    Set(String, Value),
+    Key(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Eq(String, Value),
+    Ne(String, Value),
+    Exists(String),
+}
+
+impl Condition {
+    fn parse(condition: &str) -> anyhow::Result<Self> {
+        if let Some(path) = condition.strip_prefix("exists ") {
+            return Ok(Self::Exists(path.trim().to_owned()));
+        }
+        if let Some((path, value)) = condition.split_once("==") {
+            return Ok(Self::Eq(path.trim().to_owned(), parse_value(value)?));
+        }
+        if let Some((path, value)) = condition.split_once("!=") {
+            return Ok(Self::Ne(path.trim().to_owned(), parse_value(value)?));
+        }
+        anyhow::bail!("invalid condition: {condition}")
+    }
+
+    fn matches(&self, payload: &Value) -> bool {
+        match self {
+            Self::Eq(path, value) => json_path_get(payload, path) == Some(value),
+            Self::Ne(path, value) => json_path_get(payload, path) != Some(value),
+            Self::Exists(path) => json_path_get(payload, path).is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Statement {
+    action: Action,
+    condition: Option<Condition>,
+}
+
+impl Statement {
+    fn parse(line: &str) -> anyhow::Result<Self> {
+        let (action, condition) = match line.rsplit_once(" if ") {
+            Some((action, condition)) => (action.trim(), Some(Condition::parse(condition.trim())?)),
+            None => (line, None),
+        };
+        let action = if action == "skip" {
+            Action::Skip
+        } else if let Some(path) = action.strip_prefix("drop ") {
+            Action::Drop(path.trim().to_owned())
+        } else if let Some(template) = action.strip_prefix("key ") {
+            let template = template
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or_else(|| anyhow::anyhow!("expected `key = <template>`: {line}"))?;
+            Action::Key(template.trim().to_owned())
+        } else if let Some(assignment) = action.strip_prefix("set ") {
+            let (path, value) = assignment
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("expected `set <path> = <json>`: {line}"))?;
+            Action::Set(path.trim().to_owned(), parse_value(value)?)
+        } else {
+            anyhow::bail!("unknown statement: {line}")
+        };
+        Ok(Self { action, condition })
+    }
+}
+
+fn parse_value(value: &str) -> anyhow::Result<Value> {
+    serde_json::from_str(value.trim())
+        .map_err(|error| anyhow::anyhow!("invalid JSON value `{}`: {error}", value.trim()))
+}
+
+/// Result of the script for one payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptOutput {
+    Skip,
+    /// Key template with resolved field values, if key was set
+    Produce(Option<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Script {
+    statements: Vec<Statement>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let statements = source
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx, line.trim()))
+            .filter(|(_idx, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(idx, line)| {
+                Statement::parse(line)
+                    .map_err(|error| error.context(format!("script line {}", idx + 1)))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { statements })
+    }
+
+    /// Apply statements in order, stops on the first matched `skip`
+    pub fn apply(&self, payload: &mut Value) -> ScriptOutput {
+        let mut key = None;
+        for statement in self.statements.iter() {
+            if let Some(condition) = &statement.condition {
+                if !condition.matches(payload) {
+                    continue;
+                }
+            }
+            match &statement.action {
+                Action::Skip => return ScriptOutput::Skip,
+                Action::Drop(path) => {
+                    json_path_remove(payload, path);
+                }
+                Action::Set(path, value) => json_path_set(payload, path, value.clone()),
+                Action::Key(template) => key = Some(render_fields(payload, template)),
+            }
+        }
+        ScriptOutput::Produce(key)
+    }
+}
+
+/// Replace `{$.path}` placeholders with field values, other placeholders are
+/// kept for `TransformVars::render`
+fn render_fields(payload: &Value, template: &str) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{$.") {
+        rendered.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let path = &rest[start + 1..start + end];
+        match json_path_get(payload, path) {
+            Some(Value::String(value)) => rendered.push_str(value),
+            Some(value) => rendered.push_str(&value.to_string()),
+            None => {}
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{Script, ScriptOutput},
+        serde_json::json,
+    };
+
+    #[test]
+    fn script_apply() {
+        let script = Script::parse(
+            r#"
+            # votes are not needed
+            skip if $.is_vote == true
+            drop $.meta.log_messages
+            set $.meta.fee = "<redacted>" if exists $.meta.fee
+            key = {slot}_{$.index}
+            "#,
+        )
+        .unwrap();
+
+        let mut payload = json!({"is_vote": true, "index": 3});
+        assert_eq!(script.apply(&mut payload), ScriptOutput::Skip);
+
+        let mut payload = json!({
+            "is_vote": false,
+            "index": 3,
+            "meta": {"fee": 5000, "log_messages": ["log"]},
+        });
+        assert_eq!(
+            script.apply(&mut payload),
+            ScriptOutput::Produce(Some("{slot}_3".to_owned()))
+        );
+        assert_eq!(
+            payload,
+            json!({"is_vote": false, "index": 3, "meta": {"fee": "<redacted>"}})
+        );
+
+        assert!(Script::parse("reject").is_err());
+        assert!(Script::parse("set $.a = not json").is_err());
+        assert!(Script::parse("skip if $.a").is_err());
+    }
+}
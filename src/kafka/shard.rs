@@ -0,0 +1,106 @@
+use {
+    super::{config::ConfigShardHashField, payload},
+    yellowstone_grpc_proto::prelude::subscribe_update::UpdateOneof,
+};
+
+/// FNV-1a with murmur3 finalizer (FNV low bits are poorly mixed), stable
+/// across instances and versions
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Shard of the update, messages without the field are hashed by slot and
+/// messages without slot belong to shard `0`
+pub fn shard_of(update: &UpdateOneof, field: ConfigShardHashField, shard_count: u32) -> u32 {
+    let key = match (field, update) {
+        (ConfigShardHashField::Pubkey, UpdateOneof::Account(msg)) => msg
+            .account
+            .as_ref()
+            .map(|account| account.pubkey.as_slice()),
+        (ConfigShardHashField::Signature, UpdateOneof::Account(msg)) => {
+            msg.account.as_ref().map(|account| {
+                account
+                    .txn_signature
+                    .as_deref()
+                    .unwrap_or(account.pubkey.as_slice())
+            })
+        }
+        (ConfigShardHashField::Signature, UpdateOneof::Transaction(msg)) => msg
+            .transaction
+            .as_ref()
+            .map(|transaction| transaction.signature.as_slice()),
+        (ConfigShardHashField::Signature, UpdateOneof::TransactionStatus(msg)) => {
+            Some(msg.signature.as_slice())
+        }
+        _ => None,
+    };
+    let hash = match (key, payload::update_slot(update)) {
+        (Some(key), _) => stable_hash(key),
+        (None, Some(slot)) => stable_hash(&slot.to_le_bytes()),
+        (None, None) => return 0,
+    };
+    (hash % shard_count as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::shard_of,
+        crate::kafka::config::ConfigShardHashField,
+        yellowstone_grpc_proto::prelude::{
+            subscribe_update::UpdateOneof, SubscribeUpdateAccount, SubscribeUpdateAccountInfo,
+            SubscribeUpdatePing, SubscribeUpdateSlot,
+        },
+    };
+
+    fn account(pubkey: u8, slot: u64) -> UpdateOneof {
+        UpdateOneof::Account(SubscribeUpdateAccount {
+            account: Some(SubscribeUpdateAccountInfo {
+                pubkey: vec![pubkey; 32],
+                ..Default::default()
+            }),
+            slot,
+            is_startup: false,
+        })
+    }
+
+    #[test]
+    fn shard_by_field() {
+        let slot = UpdateOneof::Slot(SubscribeUpdateSlot {
+            slot: 100,
+            ..Default::default()
+        });
+        for field in [
+            ConfigShardHashField::Slot,
+            ConfigShardHashField::Pubkey,
+            ConfigShardHashField::Signature,
+        ] {
+            assert_eq!(
+                shard_of(&slot, field, 16),
+                shard_of(&account(1, 100), ConfigShardHashField::Slot, 16)
+            );
+            assert_eq!(
+                shard_of(&UpdateOneof::Ping(SubscribeUpdatePing {}), field, 16),
+                0
+            );
+        }
+
+        // same pubkey is in the same shard in every slot
+        let shard = shard_of(&account(7, 1), ConfigShardHashField::Pubkey, 16);
+        assert!((2..1_000).all(|slot| {
+            shard_of(&account(7, slot), ConfigShardHashField::Pubkey, 16) == shard
+        }));
+        // all shards are used
+        let shards = (0..=255)
+            .map(|pubkey| shard_of(&account(pubkey, 1), ConfigShardHashField::Pubkey, 4))
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(shards.len(), 4);
+    }
+}
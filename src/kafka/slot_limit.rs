@@ -0,0 +1,85 @@
+use {std::collections::BTreeMap, tracing::warn};
+
+/// Counters of slots this far behind the max seen slot are removed, if
+/// confirmations are not received
+const MAX_SLOTS_BEHIND: u64 = 1_000;
+
+/// Counts messages per slot, slots are removed once confirmed or when they
+/// fall `MAX_SLOTS_BEHIND` slots behind the max seen slot
+#[derive(Debug, Clone)]
+pub struct SlotLimiter {
+    max_messages_per_slot: usize,
+    max_slot: u64,
+    counts: BTreeMap<u64, usize>,
+}
+
+impl SlotLimiter {
+    pub const fn new(max_messages_per_slot: usize) -> Self {
+        Self {
+            max_messages_per_slot,
+            max_slot: 0,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Returns `false` if limit for the slot is already reached
+    pub fn allow(&mut self, slot: u64) -> bool {
+        if slot > self.max_slot {
+            self.max_slot = slot;
+            self.counts = self
+                .counts
+                .split_off(&slot.saturating_sub(MAX_SLOTS_BEHIND));
+        }
+
+        let count = self.counts.entry(slot).or_default();
+        *count += 1;
+        if *count == self.max_messages_per_slot + 1 {
+            warn!(
+                "slot {slot} exceeded {} messages, skip messages until confirmed",
+                self.max_messages_per_slot
+            );
+        }
+        *count <= self.max_messages_per_slot
+    }
+
+    /// Reset counters for the slot and older slots
+    pub fn confirmed(&mut self, slot: u64) {
+        self.counts = self.counts.split_off(&slot.saturating_add(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SlotLimiter, MAX_SLOTS_BEHIND};
+
+    #[test]
+    fn slot_limiter() {
+        let mut limiter = SlotLimiter::new(2);
+        assert!(limiter.allow(10));
+        assert!(limiter.allow(10));
+        assert!(!limiter.allow(10));
+        assert!(limiter.allow(11));
+
+        limiter.confirmed(10);
+        assert!(limiter.allow(10));
+        assert!(limiter.allow(11));
+        assert!(!limiter.allow(11));
+    }
+
+    #[test]
+    fn slot_limiter_window() {
+        let mut limiter = SlotLimiter::new(1);
+        assert!(limiter.allow(10));
+        assert!(!limiter.allow(10));
+
+        // without confirmations counters are kept only within the window
+        for slot in 11..=10 + MAX_SLOTS_BEHIND {
+            assert!(limiter.allow(slot));
+        }
+        assert_eq!(limiter.counts.len(), MAX_SLOTS_BEHIND as usize + 1);
+        assert!(limiter.allow(11 + MAX_SLOTS_BEHIND));
+        assert_eq!(limiter.counts.len(), MAX_SLOTS_BEHIND as usize + 1);
+        assert!(!limiter.counts.contains_key(&10));
+        assert!(!limiter.allow(11));
+    }
+}
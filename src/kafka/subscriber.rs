@@ -0,0 +1,822 @@
+use {
+    super::{
+        config::ConfigGrpcKeepalive,
+        error_events::{ErrorEventKind, ErrorEvents},
+        metrics, payload, readiness,
+        reorder::SlotReorderBuffer,
+    },
+    crate::metrics::GprcMessageKind,
+    futures::{
+        sink::{Sink, SinkExt},
+        stream::{Stream, StreamExt},
+    },
+    std::{
+        collections::HashMap,
+        fmt,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+    tokio::{
+        sync::{mpsc, watch},
+        task::{JoinHandle, JoinSet},
+        time::{interval, interval_at, sleep, sleep_until, Instant, MissedTickBehavior},
+    },
+    tonic::{
+        metadata::{AsciiMetadataKey, AsciiMetadataValue},
+        service::Interceptor,
+        transport::ClientTlsConfig,
+        Request, Response, Status,
+    },
+    tonic_health::pb::{
+        health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+        HealthCheckResponse,
+    },
+    tracing::{info, warn},
+    yellowstone_grpc_client::GeyserGrpcClient,
+    yellowstone_grpc_proto::{
+        prelude::{
+            geyser_client::GeyserClient, subscribe_update::UpdateOneof, SubscribeRequest,
+            SubscribeRequestPing, SubscribeUpdate,
+        },
+        prost::Message,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeyserSource {
+    Primary,
+    Secondary,
+}
+
+impl fmt::Display for GeyserSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Primary => "primary",
+            Self::Secondary => "secondary",
+        })
+    }
+}
+
+/// Primary subscription with optional secondary, secondary is active only
+/// when no updates received from primary for `failover_threshold`
+#[derive(Debug)]
+pub struct GeyserFailover {
+    primary: JoinSet<anyhow::Result<()>>,
+    secondary: Option<JoinSet<anyhow::Result<()>>>,
+    _primary_active_tx: watch::Sender<bool>,
+    secondary_active_tx: watch::Sender<bool>,
+    updates_rx: mpsc::Receiver<(GeyserSource, SubscribeUpdate)>,
+    failover_threshold: Duration,
+    primary_last_seen: Instant,
+    failover_active: bool,
+    subscriptions: Arc<AtomicU64>,
+}
+
+impl GeyserFailover {
+    /// Secondary subscriber is `primary` with `secondary_endpoints`, every
+    /// subscriber is spawned with `pool_size` connections
+    pub fn spawn(
+        primary: GeyserSubscriber,
+        secondary_endpoints: Option<Vec<String>>,
+        pool_size: usize,
+        failover_threshold: Duration,
+    ) -> Self {
+        let (updates_tx, updates_rx) = mpsc::channel(1_024);
+        let (primary_active_tx, primary_active_rx) = watch::channel(true);
+        let (secondary_active_tx, secondary_active_rx) = watch::channel(false);
+        let subscriptions = Arc::new(AtomicU64::new(0));
+        let secondary = secondary_endpoints.map(|endpoints| {
+            GeyserSubscriber {
+                source: GeyserSource::Secondary,
+                endpoints,
+                ..primary.clone()
+            }
+            .spawn_pool(
+                pool_size,
+                updates_tx.clone(),
+                secondary_active_rx,
+                Arc::clone(&subscriptions),
+            )
+        });
+        let primary = GeyserSubscriber {
+            source: GeyserSource::Primary,
+            ..primary
+        }
+        .spawn_pool(
+            pool_size,
+            updates_tx,
+            primary_active_rx,
+            Arc::clone(&subscriptions),
+        );
+        metrics::failover_active_set(false);
+
+        Self {
+            primary,
+            secondary,
+            _primary_active_tx: primary_active_tx,
+            secondary_active_tx,
+            updates_rx,
+            failover_threshold,
+            primary_last_seen: Instant::now(),
+            failover_active: false,
+            subscriptions,
+        }
+    }
+
+    /// Number of established streams of all subscribers, changes on every
+    /// reconnect and on activation of the secondary
+    pub fn subscriptions(&self) -> u64 {
+        self.subscriptions.load(Ordering::Relaxed)
+    }
+
+    /// Next update from the active subscribers, fails if any subscriber
+    /// finished. Cancel safe.
+    pub async fn recv(&mut self) -> anyhow::Result<(GeyserSource, SubscribeUpdate)> {
+        let Self {
+            primary,
+            secondary,
+            secondary_active_tx,
+            updates_rx,
+            failover_threshold,
+            primary_last_seen,
+            failover_active,
+            ..
+        } = self;
+        loop {
+            let failover_check = sleep_until(*primary_last_seen + *failover_threshold);
+            tokio::select! {
+                Some(result) = primary.join_next() => {
+                    result??;
+                    anyhow::bail!("primary gRPC subscriber finished");
+                }
+                Some(result) = async { secondary.as_mut().expect("defined").join_next().await }, if secondary.is_some() => {
+                    result??;
+                    anyhow::bail!("secondary gRPC subscriber finished");
+                }
+                _ = failover_check, if secondary.is_some() && !*failover_active => {
+                    warn!("no messages from primary gRPC for {failover_threshold:?}, activate secondary");
+                    *failover_active = true;
+                    metrics::failover_active_set(true);
+                    let _ = secondary_active_tx.send(true);
+                }
+                message = updates_rx.recv() => {
+                    let Some((source, message)) = message else {
+                        anyhow::bail!("gRPC subscribers closed");
+                    };
+                    if source == GeyserSource::Primary {
+                        *primary_last_seen = Instant::now();
+                        if *failover_active {
+                            info!("primary gRPC recovered, deactivate secondary");
+                            *failover_active = false;
+                            metrics::failover_active_set(false);
+                            let _ = secondary_active_tx.send(false);
+                        }
+                    }
+                    return Ok((source, message));
+                }
+            }
+        }
+    }
+
+    /// Next update sorted by slot with `buffer` during backfill. Cancel safe.
+    pub async fn recv_reordered(
+        &mut self,
+        buffer: &mut SlotReorderBuffer<(GeyserSource, SubscribeUpdate)>,
+    ) -> anyhow::Result<(GeyserSource, SubscribeUpdate)> {
+        loop {
+            if let Some(message) = buffer.pop() {
+                return Ok(message);
+            }
+            let message = self.recv().await?;
+            let slot = message
+                .1
+                .update_oneof
+                .as_ref()
+                .and_then(payload::update_slot);
+            buffer.push(slot, message);
+        }
+    }
+
+    pub fn abort(&mut self) {
+        self.primary.abort_all();
+        if let Some(secondary) = &mut self.secondary {
+            secondary.abort_all();
+        }
+    }
+}
+
+/// Adds token to every request under the configured header name
+#[derive(Debug, Clone)]
+struct InterceptorAuthHeader {
+    header: AsciiMetadataKey,
+    token: Option<AsciiMetadataValue>,
+}
+
+impl Interceptor for InterceptorAuthHeader {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = self.token.clone() {
+            request.metadata_mut().insert(self.header.clone(), token);
+        }
+        Ok(request)
+    }
+}
+
+/// Parse default and per endpoint header names for `x_token`, names should
+/// be valid ASCII metadata keys
+pub fn parse_x_token_headers(
+    header: &str,
+    overrides: &HashMap<String, String>,
+) -> anyhow::Result<(AsciiMetadataKey, HashMap<String, AsciiMetadataKey>)> {
+    let parse = |header: &str| {
+        header
+            .parse::<AsciiMetadataKey>()
+            .map_err(|_error| anyhow::anyhow!("invalid header name for `x_token`: {header}"))
+    };
+    let overrides = overrides
+        .iter()
+        .map(|(endpoint, header)| Ok((endpoint.clone(), parse(header)?)))
+        .collect::<anyhow::Result<_>>()?;
+    Ok((parse(header)?, overrides))
+}
+
+/// Consecutive failed attempts after which the endpoint is skipped in
+/// hedged connect
+const BREAKER_FAILURES: u32 = 3;
+/// Time the endpoint is skipped for, the next failed attempt after that
+/// opens the breaker again
+const BREAKER_OPEN_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct EndpointBreaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Per-endpoint circuit breakers for hedged connect
+#[derive(Debug)]
+struct EndpointBreakers {
+    endpoints: Vec<EndpointBreaker>,
+}
+
+impl EndpointBreakers {
+    fn new(endpoints: usize) -> Self {
+        Self {
+            endpoints: vec![EndpointBreaker::default(); endpoints],
+        }
+    }
+
+    fn is_open(&self, idx: usize, now: Instant) -> bool {
+        self.endpoints[idx]
+            .open_until
+            .is_some_and(|open_until| now < open_until)
+    }
+
+    fn failure(&mut self, idx: usize, now: Instant) {
+        let breaker = &mut self.endpoints[idx];
+        breaker.failures += 1;
+        if breaker.failures >= BREAKER_FAILURES {
+            breaker.open_until = Some(now + BREAKER_OPEN_DURATION);
+        }
+    }
+
+    fn success(&mut self, idx: usize) {
+        self.endpoints[idx] = EndpointBreaker::default();
+    }
+
+    /// Endpoints starting from `idx` without open breaker, all endpoints if
+    /// every breaker is open
+    fn candidates(&self, idx: usize, now: Instant) -> Vec<usize> {
+        let all = (0..self.endpoints.len()).map(|offset| (idx + offset) % self.endpoints.len());
+        let closed = all
+            .clone()
+            .filter(|idx| !self.is_open(*idx, now))
+            .collect::<Vec<_>>();
+        if closed.is_empty() {
+            all.collect()
+        } else {
+            closed
+        }
+    }
+}
+
+/// Background health check, aborted on drop so attempts cancelled or lost in
+/// hedged connect do not leave it running
+#[derive(Debug)]
+struct HealthTask(JoinHandle<()>);
+
+impl Drop for HealthTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+enum StreamEnd {
+    /// Reconnect with reason for metrics
+    Reconnect(&'static str),
+    Deactivated,
+    ReceiverClosed,
+    /// No updates within startup timeout after the first subscribe
+    StartupTimeout(Duration),
+}
+
+/// Subscribe to one of the endpoints and forward all updates to the channel,
+/// on disconnect switch to the next endpoint. Subscription is kept open only
+/// while `active` is `true`.
+#[derive(Debug, Clone)]
+pub struct GeyserSubscriber {
+    pub source: GeyserSource,
+    pub endpoints: Vec<String>,
+    pub x_token: Option<String>,
+    /// Header for `x_token`
+    pub x_token_header: AsciiMetadataKey,
+    /// Header for `x_token` by endpoint, `x_token_header` is used for other
+    /// endpoints
+    pub x_token_header_overrides: HashMap<String, AsciiMetadataKey>,
+    /// Used on (re)connect, updates are sent on the established stream
+    pub request: watch::Receiver<SubscribeRequest>,
+    pub keepalive: ConfigGrpcKeepalive,
+    pub errors: ErrorEvents,
+    /// Send `Ping` on the stream with this interval, reconnect if previous
+    /// ping was not answered
+    pub client_ping_interval: Option<Duration>,
+    /// Used before the first reconnect instead of the regular 2 seconds delay
+    pub initial_reconnect_delay: Duration,
+    /// Service name for health check before subscribe
+    pub health_service_name: String,
+    pub user_agent: String,
+    /// Check health in background with this interval, status changes are
+    /// only logged
+    pub health_check_interval: Option<Duration>,
+    /// Reconnect to the next endpoint if `Slot` update skips more than this
+    /// number of slots
+    pub slot_gap_reconnect_threshold: Option<u64>,
+    /// Fail if no updates received within this time after the first
+    /// successful subscribe, `Ping` / `Pong` are not counted
+    pub startup_message_timeout: Option<Duration>,
+    /// Subscribe to up to this number of endpoints concurrently starting
+    /// from the current one, the first subscribed is used and other attempts
+    /// are cancelled
+    pub hedged_connect_concurrency: Option<usize>,
+}
+
+impl GeyserSubscriber {
+    pub fn spawn(
+        self,
+        updates_tx: mpsc::Sender<(GeyserSource, SubscribeUpdate)>,
+        active: watch::Receiver<bool>,
+        subscriptions: Arc<AtomicU64>,
+    ) -> JoinHandle<anyhow::Result<()>> {
+        tokio::spawn(self.run(updates_tx, active, subscriptions))
+    }
+
+    /// Spawn `pool_size` subscribers, every one with own connection and
+    /// stream, so every update is received `pool_size` times
+    pub fn spawn_pool(
+        self,
+        pool_size: usize,
+        updates_tx: mpsc::Sender<(GeyserSource, SubscribeUpdate)>,
+        active: watch::Receiver<bool>,
+        subscriptions: Arc<AtomicU64>,
+    ) -> JoinSet<anyhow::Result<()>> {
+        let mut set = JoinSet::new();
+        for _ in 0..pool_size {
+            set.spawn(self.clone().run(
+                updates_tx.clone(),
+                active.clone(),
+                Arc::clone(&subscriptions),
+            ));
+        }
+        set
+    }
+
+    async fn run(
+        mut self,
+        updates_tx: mpsc::Sender<(GeyserSource, SubscribeUpdate)>,
+        mut active: watch::Receiver<bool>,
+        subscriptions: Arc<AtomicU64>,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.endpoints.is_empty(),
+            "{}: at least one endpoint should be defined",
+            self.source
+        );
+
+        let mut request = self.request.clone();
+        let mut breakers = EndpointBreakers::new(self.endpoints.len());
+        let mut idx = 0;
+        let mut reconnect_delay = self.initial_reconnect_delay;
+        loop {
+            while !*active.borrow_and_update() {
+                if active.changed().await.is_err() {
+                    return Ok(());
+                }
+            }
+
+            let subscription = match self.hedged_connect_concurrency {
+                Some(concurrency) if self.endpoints.len() > 1 => {
+                    let candidates = breakers.candidates(idx, Instant::now());
+                    info!(
+                        "{}: connecting to {} of {} endpoints, up to {concurrency} at once",
+                        self.source,
+                        candidates.len(),
+                        self.endpoints.len()
+                    );
+                    // attempts use clones, current request is sent by every one
+                    request.borrow_and_update();
+                    let mut attempts = futures::stream::iter(candidates)
+                        .map(|idx| {
+                            let mut request = request.clone();
+                            let this = &self;
+                            async move {
+                                let result =
+                                    this.subscribe(&this.endpoints[idx], &mut request).await;
+                                (idx, result)
+                            }
+                        })
+                        .buffer_unordered(concurrency);
+                    let mut subscription = None;
+                    while let Some((attempt_idx, result)) = attempts.next().await {
+                        match result? {
+                            Some(subscribed) => {
+                                info!(
+                                    "{}: endpoint[{attempt_idx}] won the connect race",
+                                    self.source
+                                );
+                                breakers.success(attempt_idx);
+                                subscription = Some((attempt_idx, subscribed));
+                                break;
+                            }
+                            None => {
+                                breakers.failure(attempt_idx, Instant::now());
+                                if breakers.is_open(attempt_idx, Instant::now()) {
+                                    warn!(
+                                        "{}: endpoint[{attempt_idx}] is skipped in connect race for {BREAKER_OPEN_DURATION:?}",
+                                        self.source
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    // attempts still in progress are cancelled on drop
+                    drop(attempts);
+                    subscription
+                }
+                _ => {
+                    let endpoint = &self.endpoints[idx];
+                    info!("{}: connecting to endpoint[{idx}]: {endpoint}", self.source);
+                    self.subscribe(endpoint, &mut request)
+                        .await?
+                        .map(|subscribed| (idx, subscribed))
+                }
+            };
+            if let Some((subscribed_idx, (health_task, subscribe_tx, stream))) = subscription {
+                idx = subscribed_idx;
+                let endpoint = &self.endpoints[idx];
+                info!("{}: subscribed to endpoint[{idx}]", self.source);
+                subscriptions.fetch_add(1, Ordering::Relaxed);
+                let connections_active = metrics::grpc_connections_active(endpoint);
+                connections_active.inc();
+                readiness::grpc_connected();
+                let stream_end = self
+                    .forward(
+                        endpoint,
+                        subscribe_tx,
+                        stream,
+                        &mut request,
+                        &updates_tx,
+                        &mut active,
+                    )
+                    .await;
+                connections_active.dec();
+                // startup timeout is checked only on the first stream
+                self.startup_message_timeout = None;
+                readiness::grpc_disconnected();
+                drop(health_task);
+                match stream_end {
+                    StreamEnd::Reconnect(reason) => metrics::reconnect_reason_inc(reason),
+                    StreamEnd::Deactivated => {
+                        info!("{}: deactivated", self.source);
+                        continue;
+                    }
+                    StreamEnd::ReceiverClosed => return Ok(()),
+                    StreamEnd::StartupTimeout(timeout) => {
+                        anyhow::bail!(
+                            "{}: no messages received within {timeout:?} after subscribe",
+                            self.source
+                        );
+                    }
+                }
+            }
+
+            idx = (idx + 1) % self.endpoints.len();
+            warn!("{}: switch to endpoint[{idx}]", self.source);
+            sleep(reconnect_delay).await;
+            reconnect_delay = Duration::from_millis(2000);
+        }
+    }
+
+    async fn subscribe(
+        &self,
+        endpoint: &str,
+        request: &mut watch::Receiver<SubscribeRequest>,
+    ) -> anyhow::Result<
+        Option<(
+            Option<HealthTask>,
+            impl Sink<SubscribeRequest, Error = impl fmt::Display> + Unpin,
+            impl Stream<Item = Result<SubscribeUpdate, Status>>,
+        )>,
+    > {
+        let mut builder = GeyserGrpcClient::build_from_shared(endpoint.to_owned())?;
+        builder.endpoint = builder.endpoint.user_agent(self.user_agent.clone())?;
+        let builder = builder
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(5))
+            .http2_keep_alive_interval(self.keepalive.http2_keep_alive_interval)
+            .keep_alive_timeout(self.keepalive.keep_alive_timeout)
+            .keep_alive_while_idle(self.keepalive.keep_alive_while_idle)
+            .tls_config(ClientTlsConfig::new().with_native_roots())?;
+
+        let interceptor = InterceptorAuthHeader {
+            header: self
+                .x_token_header_overrides
+                .get(endpoint)
+                .unwrap_or(&self.x_token_header)
+                .clone(),
+            token: self.x_token.clone().map(TryInto::try_into).transpose()?,
+        };
+        let mut client = match builder.endpoint.connect().await {
+            Ok(channel) => GeyserGrpcClient::new(
+                HealthClient::with_interceptor(channel.clone(), interceptor.clone()),
+                GeyserClient::with_interceptor(channel, interceptor),
+            ),
+            Err(error) => {
+                warn!("{}: failed to connect: {error:?}", self.source);
+                self.reconnect_event(format!("failed to connect to {endpoint}: {error}"));
+                return Ok(None);
+            }
+        };
+
+        let health_request = HealthCheckRequest {
+            service: self.health_service_name.clone(),
+        };
+        if let Err(error) = health_status(client.health.check(health_request.clone()).await) {
+            warn!("{}: health check failed: {error}", self.source);
+            self.reconnect_event(format!("health check failed for {endpoint}: {error}"));
+            return Ok(None);
+        }
+
+        let request = request.borrow_and_update().clone();
+        let subscription = client.subscribe_with_request(Some(request)).await;
+
+        let source = self.source;
+        let health_task = self.health_check_interval.map(|period| {
+            let mut health = client.health;
+            HealthTask(tokio::spawn(async move {
+                let mut health_interval = interval(period);
+                health_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                health_interval.tick().await;
+                let mut last_status = Some(ServingStatus::Serving);
+                loop {
+                    health_interval.tick().await;
+                    let status = match health_status(health.check(health_request.clone()).await) {
+                        Ok(status) => Some(status),
+                        Err(error) => {
+                            warn!("{source}: health check failed: {error}");
+                            None
+                        }
+                    };
+                    if status != last_status {
+                        info!("{source}: health status changed: {last_status:?} -> {status:?}");
+                        last_status = status;
+                    }
+                }
+            }))
+        });
+
+        match subscription {
+            Ok((subscribe_tx, stream)) => Ok(Some((health_task, subscribe_tx, stream))),
+            Err(error) => {
+                drop(health_task);
+                warn!("{}: failed to subscribe: {error:?}", self.source);
+                self.reconnect_event(format!("failed to subscribe to {endpoint}: {error}"));
+                Ok(None)
+            }
+        }
+    }
+
+    async fn forward(
+        &self,
+        endpoint: &str,
+        mut subscribe_tx: impl Sink<SubscribeRequest, Error = impl fmt::Display> + Unpin,
+        stream: impl Stream<Item = Result<SubscribeUpdate, Status>>,
+        request: &mut watch::Receiver<SubscribeRequest>,
+        updates_tx: &mpsc::Sender<(GeyserSource, SubscribeUpdate)>,
+        active: &mut watch::Receiver<bool>,
+    ) -> StreamEnd {
+        tokio::pin!(stream);
+        let subscribed_at = Instant::now();
+        let mut first_update = true;
+        let startup_message_timeout = self.startup_message_timeout;
+        let mut last_slot: Option<u64> = None;
+        let mut ping_interval = self
+            .client_ping_interval
+            .map(|period| interval_at(Instant::now() + period, period));
+        let mut ping_id: i32 = 0;
+        let mut ping_pending = false;
+        let mut request_updates = true;
+        loop {
+            let startup_check =
+                sleep_until(subscribed_at + startup_message_timeout.unwrap_or_default());
+            tokio::select! {
+                _ = startup_check, if first_update && startup_message_timeout.is_some() => {
+                    return StreamEnd::StartupTimeout(startup_message_timeout.expect("defined"));
+                }
+                result = active.changed() => {
+                    if result.is_err() {
+                        return StreamEnd::ReceiverClosed;
+                    }
+                    if !*active.borrow_and_update() {
+                        return StreamEnd::Deactivated;
+                    }
+                }
+                result = request.changed(), if request_updates => {
+                    if result.is_err() {
+                        request_updates = false;
+                        continue;
+                    }
+                    let request = request.borrow_and_update().clone();
+                    if let Err(error) = subscribe_tx.send(request).await {
+                        warn!("{}: failed to send updated request: {error}", self.source);
+                        self.reconnect_event(format!("failed to send updated request: {error}"));
+                        return StreamEnd::Reconnect("config_reload");
+                    }
+                    info!("{}: subscribe request updated", self.source);
+                }
+                _ = async { ping_interval.as_mut().expect("defined").tick().await }, if ping_interval.is_some() => {
+                    if ping_pending {
+                        warn!("{}: no pong received for ping #{ping_id}", self.source);
+                        self.reconnect_event(format!("no pong received for ping #{ping_id}"));
+                        return StreamEnd::Reconnect("watchdog");
+                    }
+                    ping_id = ping_id.wrapping_add(1);
+                    let request = SubscribeRequest {
+                        ping: Some(SubscribeRequestPing { id: ping_id }),
+                        ..Default::default()
+                    };
+                    if let Err(error) = subscribe_tx.send(request).await {
+                        warn!("{}: failed to send ping: {error}", self.source);
+                        self.reconnect_event(format!("failed to send ping: {error}"));
+                        return StreamEnd::Reconnect("watchdog");
+                    }
+                    ping_pending = true;
+                }
+                message = stream.next() => match message {
+                    Some(Ok(SubscribeUpdate { update_oneof: Some(UpdateOneof::Pong(pong)), .. })) if pong.id == ping_id => {
+                        ping_pending = false;
+                    }
+                    Some(Ok(message)) => {
+                        let kind = message
+                            .update_oneof
+                            .as_ref()
+                            .map(GprcMessageKind::from)
+                            .unwrap_or(GprcMessageKind::Unknown);
+                        metrics::grpc_bytes_received_inc(endpoint, kind, message.encoded_len());
+                        if first_update
+                            && !matches!(
+                                message.update_oneof,
+                                Some(UpdateOneof::Ping(_) | UpdateOneof::Pong(_))
+                            )
+                        {
+                            metrics::reconnect_ttfm_observe(endpoint, subscribed_at.elapsed());
+                            first_update = false;
+                        }
+                        let slot_gap = match (&message.update_oneof, self.slot_gap_reconnect_threshold) {
+                            (Some(UpdateOneof::Slot(msg)), Some(threshold)) => {
+                                let gap = last_slot.map_or(0, |last| msg.slot.saturating_sub(last));
+                                last_slot = last_slot.max(Some(msg.slot));
+                                (gap > threshold).then_some(gap)
+                            }
+                            _ => None,
+                        };
+                        if updates_tx.send((self.source, message)).await.is_err() {
+                            return StreamEnd::ReceiverClosed;
+                        }
+                        if let Some(gap) = slot_gap {
+                            warn!("{}: skipped {gap} slots, reconnect_reason = \"slot_gap\"", self.source);
+                            self.reconnect_event(format!("skipped {gap} slots"));
+                            return StreamEnd::Reconnect("slot_gap");
+                        }
+                    }
+                    Some(Err(status)) => {
+                        warn!(
+                            "{}: rpc error (code={:?}): {}",
+                            self.source,
+                            status.code(),
+                            status.message()
+                        );
+                        self.reconnect_event(format!(
+                            "rpc error (code={:?}): {}",
+                            status.code(),
+                            status.message()
+                        ));
+                        return StreamEnd::Reconnect("stream_error");
+                    }
+                    None => {
+                        warn!("{}: stream closed by the remote peer", self.source);
+                        self.reconnect_event("stream closed by the remote peer");
+                        return StreamEnd::Reconnect("stream_closed");
+                    }
+                }
+            }
+        }
+    }
+
+    fn reconnect_event(&self, message: impl fmt::Display) {
+        self.errors.emit(
+            ErrorEventKind::Reconnect,
+            format!("{}: {message}", self.source),
+        );
+    }
+}
+
+fn health_status(
+    result: Result<Response<HealthCheckResponse>, Status>,
+) -> anyhow::Result<ServingStatus> {
+    let status = result
+        .map_err(|status| anyhow::anyhow!("{}", status.message()))?
+        .into_inner()
+        .status();
+    anyhow::ensure!(
+        status == ServingStatus::Serving,
+        "service is not serving: {status:?}"
+    );
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            parse_x_token_headers, EndpointBreakers, HealthTask, BREAKER_FAILURES,
+            BREAKER_OPEN_DURATION,
+        },
+        std::{collections::HashMap, time::Duration},
+        tokio::{sync::oneshot, time::Instant},
+    };
+
+    #[test]
+    fn x_token_headers() {
+        let overrides = HashMap::from([("https://b".to_owned(), "Authorization".to_owned())]);
+        let (header, overrides) = parse_x_token_headers("x-token", &overrides).unwrap();
+        assert_eq!(header.as_str(), "x-token");
+        assert_eq!(overrides["https://b"].as_str(), "authorization");
+
+        assert!(parse_x_token_headers("x token", &HashMap::new()).is_err());
+        assert!(parse_x_token_headers("x-token-bin", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn endpoint_breakers() {
+        let now = Instant::now();
+        let mut breakers = EndpointBreakers::new(3);
+        assert_eq!(breakers.candidates(1, now), [1, 2, 0]);
+
+        for _ in 0..BREAKER_FAILURES {
+            assert!(!breakers.is_open(2, now));
+            breakers.failure(2, now);
+        }
+        assert!(breakers.is_open(2, now));
+        assert_eq!(breakers.candidates(1, now), [1, 0]);
+
+        // half-open after the timeout, a single failure opens it again
+        let later = now + BREAKER_OPEN_DURATION;
+        assert_eq!(breakers.candidates(1, later), [1, 2, 0]);
+        breakers.failure(2, later);
+        assert!(breakers.is_open(2, later + Duration::from_secs(1)));
+        breakers.success(2);
+        assert!(!breakers.is_open(2, later));
+
+        // all endpoints are tried if every breaker is open
+        for idx in 0..3 {
+            for _ in 0..BREAKER_FAILURES {
+                breakers.failure(idx, now);
+            }
+        }
+        assert_eq!(breakers.candidates(0, now), [0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn health_task_aborted_on_drop() {
+        let (tx, rx) = oneshot::channel::<()>();
+        let task = HealthTask(tokio::spawn(async move {
+            let _tx = tx;
+            std::future::pending::<()>().await
+        }));
+        drop(task);
+        // sender is dropped with the aborted task
+        assert!(rx.await.is_err());
+    }
+}
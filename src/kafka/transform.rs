@@ -0,0 +1,241 @@
+use {super::config::ConfigTransform, serde_json::Value};
+
+#[derive(Debug, Default, Clone)]
+pub struct TransformChain {
+    steps: Vec<ConfigTransform>,
+}
+
+impl TransformChain {
+    pub const fn new(steps: Vec<ConfigTransform>) -> Self {
+        Self { steps }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Apply all steps to the payload in order. Headers and key are rendered
+    /// later with [`TransformResult::render`], once the message key is known.
+    pub fn apply(&self, payload: &mut Value) -> TransformResult {
+        let mut result = TransformResult::default();
+        for step in self.steps.iter() {
+            match step {
+                ConfigTransform::AddHeader {
+                    key,
+                    value_template,
+                } => result
+                    .headers
+                    .push((key.clone(), HeaderValue::Template(value_template.clone()))),
+                ConfigTransform::ExtractField {
+                    json_path,
+                    header_name,
+                } => {
+                    if let Some(value) = json_path_get(payload, json_path) {
+                        let value = match value {
+                            Value::String(value) => value.clone(),
+                            value => value.to_string(),
+                        };
+                        result
+                            .headers
+                            .push((header_name.clone(), HeaderValue::Value(value)));
+                    }
+                }
+                ConfigTransform::RenameKey { template } => {
+                    result.key_template = Some(template.clone());
+                }
+                ConfigTransform::DropField { json_path } => {
+                    json_path_remove(payload, json_path);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug)]
+enum HeaderValue {
+    Template(String),
+    Value(String),
+}
+
+#[derive(Debug, Default)]
+pub struct TransformResult {
+    headers: Vec<(String, HeaderValue)>,
+    key_template: Option<String>,
+}
+
+impl TransformResult {
+    /// Override key template, placeholders are rendered in [`Self::render`]
+    pub fn with_key_template(mut self, template: String) -> Self {
+        self.key_template = Some(template);
+        self
+    }
+
+    /// Returns new key (if it was renamed) and headers
+    pub fn render(self, vars: &TransformVars<'_>) -> (Option<String>, Vec<(String, String)>) {
+        let key = self.key_template.map(|template| vars.render(&template));
+        let headers = self
+            .headers
+            .into_iter()
+            .map(|(name, value)| match value {
+                HeaderValue::Template(template) => (name, vars.render(&template)),
+                HeaderValue::Value(value) => (name, value),
+            })
+            .collect();
+        (key, headers)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TransformVars<'a> {
+    pub slot: u64,
+    pub kind: &'a str,
+    pub hash: &'a str,
+    pub key: &'a str,
+}
+
+impl TransformVars<'_> {
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{slot}", &self.slot.to_string())
+            .replace("{kind}", self.kind)
+            .replace("{hash}", self.hash)
+            .replace("{key}", self.key)
+    }
+}
+
+fn json_path_segments(path: &str) -> impl Iterator<Item = &str> {
+    let path = path.strip_prefix("$.").unwrap_or(path);
+    path.split('.').filter(|segment| !segment.is_empty())
+}
+
+pub(crate) fn json_path_get<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    json_path_segments(path).try_fold(value, |value, segment| match value {
+        Value::Object(map) => map.get(segment),
+        Value::Array(vec) => segment.parse::<usize>().ok().and_then(|idx| vec.get(idx)),
+        _ => None,
+    })
+}
+
+pub(crate) fn json_path_remove(value: &mut Value, path: &str) -> Option<Value> {
+    let mut segments = json_path_segments(path).collect::<Vec<_>>();
+    let last = segments.pop()?;
+    let parent = segments
+        .into_iter()
+        .try_fold(value, |value, segment| match value {
+            Value::Object(map) => map.get_mut(segment),
+            Value::Array(vec) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| vec.get_mut(idx)),
+            _ => None,
+        })?;
+    match parent {
+        Value::Object(map) => map.remove(last),
+        Value::Array(vec) => match last.parse::<usize>() {
+            Ok(idx) if idx < vec.len() => Some(vec.remove(idx)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Set value of existing field or array element, or insert field to the
+/// existing object
+#[cfg(feature = "script-transform")]
+pub(crate) fn json_path_set(value: &mut Value, path: &str, new_value: Value) {
+    let mut segments = json_path_segments(path).collect::<Vec<_>>();
+    let Some(last) = segments.pop() else {
+        return;
+    };
+    let parent = segments
+        .into_iter()
+        .try_fold(value, |value, segment| match value {
+            Value::Object(map) => map.get_mut(segment),
+            Value::Array(vec) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| vec.get_mut(idx)),
+            _ => None,
+        });
+    match parent {
+        Some(Value::Object(map)) => {
+            map.insert(last.to_owned(), new_value);
+        }
+        Some(Value::Array(vec)) => {
+            if let Some(item) = last.parse::<usize>().ok().and_then(|idx| vec.get_mut(idx)) {
+                *item = new_value;
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{TransformChain, TransformVars},
+        crate::kafka::config::ConfigTransform,
+        serde_json::json,
+    };
+
+    const VARS: TransformVars<'static> = TransformVars {
+        slot: 42,
+        kind: "transaction",
+        hash: "abcd",
+        key: "42_abcd",
+    };
+
+    #[test]
+    fn transform_chain_headers_and_key() {
+        let chain = TransformChain::new(vec![
+            ConfigTransform::AddHeader {
+                key: "origin".to_owned(),
+                value_template: "{kind}@{slot}".to_owned(),
+            },
+            ConfigTransform::ExtractField {
+                json_path: "$.meta.fee".to_owned(),
+                header_name: "fee".to_owned(),
+            },
+            ConfigTransform::RenameKey {
+                template: "tx-{key}".to_owned(),
+            },
+        ]);
+        let mut payload = json!({"meta": {"fee": 5000}});
+        let (key, headers) = chain.apply(&mut payload).render(&VARS);
+        assert_eq!(key.as_deref(), Some("tx-42_abcd"));
+        assert_eq!(
+            headers,
+            vec![
+                ("origin".to_owned(), "transaction@42".to_owned()),
+                ("fee".to_owned(), "5000".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn transform_chain_drop_field() {
+        let chain = TransformChain::new(vec![
+            ConfigTransform::DropField {
+                json_path: "meta.log_messages".to_owned(),
+            },
+            ConfigTransform::DropField {
+                json_path: "signatures.0".to_owned(),
+            },
+            ConfigTransform::DropField {
+                json_path: "missing.field".to_owned(),
+            },
+        ]);
+        let mut payload = json!({
+            "meta": {"fee": 5000, "log_messages": ["a", "b"]},
+            "signatures": ["s1", "s2"],
+        });
+        let (key, headers) = chain.apply(&mut payload).render(&VARS);
+        assert_eq!(key, None);
+        assert!(headers.is_empty());
+        assert_eq!(
+            payload,
+            json!({"meta": {"fee": 5000}, "signatures": ["s2"]})
+        );
+    }
+}
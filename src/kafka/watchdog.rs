@@ -0,0 +1,170 @@
+use {
+    super::metrics,
+    futures::future::{BoxFuture, Shared},
+    std::{future::Future, time::Duration},
+    tokio::time::{sleep, Instant},
+    tracing::error,
+};
+
+/// Restarts of failed pipeline with `auto_restart`
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Error is returned after more failures within `window`
+    pub max_restart_attempts: usize,
+    pub backoff: Duration,
+    pub window: Duration,
+}
+
+impl RestartPolicy {
+    /// Run pipeline created by `run` in a task, restart it after `backoff` on
+    /// error or panic. Returns on success, on shutdown and once
+    /// `max_restart_attempts` is exceeded within `window`.
+    pub async fn run<F, Fut>(
+        self,
+        name: &str,
+        shutdown: Shared<BoxFuture<'static, ()>>,
+        mut run: F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let mut failures = Vec::new();
+        loop {
+            let error = match tokio::spawn(run()).await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(error)) => error,
+                Err(error) => anyhow::Error::new(error).context("pipeline task failed"),
+            };
+            if shutdown.peek().is_some() {
+                return Err(error);
+            }
+
+            let now = Instant::now();
+            failures.retain(|failed_at| now.duration_since(*failed_at) < self.window);
+            failures.push(now);
+            if failures.len() > self.max_restart_attempts {
+                return Err(error.context(format!(
+                    "pipeline failed {} times within {:?}",
+                    failures.len(),
+                    self.window
+                )));
+            }
+            error!(
+                "pipeline {name} failed: {error:?}, restart in {:?}",
+                self.backoff
+            );
+            metrics::pipeline_restart_inc(name);
+            tokio::select! {
+                _ = shutdown.clone() => return Ok(()),
+                _ = sleep(self.backoff) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::RestartPolicy,
+        futures::future::{pending, FutureExt},
+        std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            time::Duration,
+        },
+        tokio::time::Instant,
+    };
+
+    #[tokio::test]
+    async fn restart_with_backoff() {
+        let policy = RestartPolicy {
+            max_restart_attempts: 2,
+            backoff: Duration::from_millis(20),
+            window: Duration::from_secs(60),
+        };
+
+        // fails twice, then succeeds
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let ts = Instant::now();
+        let result = policy
+            .run("test", pending::<()>().boxed().shared(), || {
+                let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+                async move {
+                    anyhow::ensure!(attempt >= 2, "attempt #{attempt} failed");
+                    Ok(())
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+        assert!(ts.elapsed() >= Duration::from_millis(40));
+
+        // panics are restarted, error after `max_restart_attempts` restarts
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let result = policy
+            .run("test", pending::<()>().boxed().shared(), || {
+                let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+                async move {
+                    if attempt == 0 {
+                        panic!("attempt #0 panicked");
+                    }
+                    anyhow::bail!("attempt #{attempt} failed")
+                }
+            })
+            .await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "pipeline failed 3 times within 60s"
+        );
+        assert_eq!(attempts.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn restart_window() {
+        let policy = RestartPolicy {
+            max_restart_attempts: 1,
+            backoff: Duration::from_millis(30),
+            window: Duration::from_millis(20),
+        };
+
+        // failures older than the window are not counted
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let result = policy
+            .run("test", pending::<()>().boxed().shared(), || {
+                let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+                async move {
+                    anyhow::ensure!(attempt >= 4, "attempt #{attempt} failed");
+                    Ok(())
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::Relaxed), 5);
+    }
+
+    #[tokio::test]
+    async fn restart_shutdown() {
+        let policy = RestartPolicy {
+            max_restart_attempts: 10,
+            backoff: Duration::from_secs(60),
+            window: Duration::from_secs(60),
+        };
+
+        // shutdown during backoff
+        let shutdown = tokio::time::sleep(Duration::from_millis(20))
+            .boxed()
+            .shared();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let result = policy
+            .run("test", shutdown, || {
+                attempts.fetch_add(1, Ordering::Relaxed);
+                async { anyhow::bail!("failed") }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(Ordering::Relaxed), 1);
+    }
+}
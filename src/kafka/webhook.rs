@@ -0,0 +1,56 @@
+use {
+    serde::Serialize,
+    std::time::{Duration, SystemTime},
+    tracing::{info, warn},
+};
+
+#[derive(Debug, Serialize)]
+struct PreShutdownEvent<'a> {
+    instance_id: &'a str,
+    reason: &'a str,
+    timestamp: String,
+}
+
+/// Notifies external service before shutdown, e.g. to mark the instance as
+/// draining in a service registry
+#[derive(Debug, Clone)]
+pub struct PreShutdownWebhook {
+    client: reqwest::Client,
+    url: String,
+    instance_id: String,
+}
+
+impl PreShutdownWebhook {
+    pub fn new(url: String, timeout: Duration, instance_id: String) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(timeout).build()?;
+        Ok(Self {
+            client,
+            url,
+            instance_id,
+        })
+    }
+
+    /// POST shutdown event, errors are logged and never stop the shutdown
+    pub async fn notify(&self, reason: &str) {
+        let event = PreShutdownEvent {
+            instance_id: &self.instance_id,
+            reason,
+            timestamp: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+        };
+        let result = self
+            .client
+            .post(&self.url)
+            .json(&event)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(response) => info!(
+                "pre-shutdown webhook {} responded with {}",
+                self.url,
+                response.status()
+            ),
+            Err(error) => warn!("pre-shutdown webhook {} failed: {error}", self.url),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use {
+    super::{
+        config::ConfigWriteBehindCache,
+        error_events::{ErrorEventKind, ErrorEvents},
+        metrics::{self, StatsContext},
+    },
+    crate::metrics::GprcMessageKind,
+    rdkafka::{
+        message::OwnedHeaders,
+        producer::{FutureProducer, FutureRecord},
+    },
+    std::collections::HashMap,
+    tokio::{
+        sync::mpsc,
+        task::JoinHandle,
+        time::{interval, MissedTickBehavior},
+    },
+    tracing::debug,
+};
+
+/// Prepared Kafka record, owned version of `FutureRecord`
+#[derive(Debug)]
+pub struct CachedRecord {
+    pub topic: String,
+    pub key: String,
+    pub payload: Vec<u8>,
+    pub headers: OwnedHeaders,
+    pub partition: Option<i32>,
+    pub kind: GprcMessageKind,
+}
+
+/// Latest record per account pubkey
+#[derive(Debug, Default)]
+struct PendingRecords {
+    records: HashMap<Vec<u8>, CachedRecord>,
+}
+
+impl PendingRecords {
+    /// Returns `true` if record for the same pubkey was superseded
+    fn insert(&mut self, pubkey: Vec<u8>, record: CachedRecord) -> bool {
+        self.records.insert(pubkey, record).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    fn take(&mut self) -> Vec<CachedRecord> {
+        self.records
+            .drain()
+            .map(|(_pubkey, record)| record)
+            .collect()
+    }
+}
+
+/// Collapse account updates within flush interval (last-write-wins) and
+/// produce them to Kafka from the background task
+#[derive(Debug)]
+pub struct WriteBehindCache {
+    records_tx: mpsc::Sender<(Vec<u8>, CachedRecord)>,
+}
+
+impl WriteBehindCache {
+    pub fn spawn(
+        config: ConfigWriteBehindCache,
+        kafka: FutureProducer<StatsContext>,
+        errors: ErrorEvents,
+    ) -> (Self, JoinHandle<anyhow::Result<()>>) {
+        let (records_tx, records_rx) = mpsc::channel(config.capacity.max(1));
+        let task = tokio::spawn(Self::run(config, kafka, errors, records_rx));
+        (Self { records_tx }, task)
+    }
+
+    pub async fn push(&self, pubkey: Vec<u8>, record: CachedRecord) -> anyhow::Result<()> {
+        self.records_tx
+            .send((pubkey, record))
+            .await
+            .map_err(|_| anyhow::anyhow!("write-behind cache is closed"))
+    }
+
+    async fn run(
+        config: ConfigWriteBehindCache,
+        kafka: FutureProducer<StatsContext>,
+        errors: ErrorEvents,
+        mut records_rx: mpsc::Receiver<(Vec<u8>, CachedRecord)>,
+    ) -> anyhow::Result<()> {
+        let mut pending = PendingRecords::default();
+        let mut flush_interval = interval(config.flush_interval);
+        flush_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        flush_interval.tick().await;
+
+        loop {
+            tokio::select! {
+                maybe_record = records_rx.recv() => match maybe_record {
+                    Some((pubkey, record)) => {
+                        if pending.insert(pubkey, record) {
+                            metrics::cache_collapsed_inc();
+                        }
+                        if pending.len() >= config.capacity {
+                            Self::flush(&kafka, &errors, pending.take()).await?;
+                            flush_interval.reset();
+                        }
+                    }
+                    None => break,
+                },
+                _ = flush_interval.tick() => {
+                    Self::flush(&kafka, &errors, pending.take()).await?;
+                }
+            }
+        }
+
+        Self::flush(&kafka, &errors, pending.take()).await
+    }
+
+    async fn flush(
+        kafka: &FutureProducer<StatsContext>,
+        errors: &ErrorEvents,
+        records: Vec<CachedRecord>,
+    ) -> anyhow::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        debug!("write-behind cache: flush {} records", records.len());
+
+        let mut futures = Vec::with_capacity(records.len());
+        for record in records.iter() {
+            let mut future_record = FutureRecord::to(&record.topic)
+                .key(&record.key)
+                .payload(&record.payload)
+                .headers(record.headers.clone());
+            future_record.partition = record.partition;
+            match kafka.send_result(future_record) {
+                Ok(future) => futures.push((record, future)),
+                Err((error, _record)) => {
+                    errors.emit(
+                        ErrorEventKind::Produce,
+                        format!("failed to send message with key {}: {error}", record.key),
+                    );
+                    return Err(error.into());
+                }
+            }
+        }
+
+        for (record, future) in futures {
+            if let Err((error, _message)) = future.await? {
+                errors.emit(
+                    ErrorEventKind::Produce,
+                    format!("failed to send message with key {}: {error}", record.key),
+                );
+                return Err(error.into());
+            }
+            metrics::sent_inc(record.kind);
+            metrics::bytes_produced_inc(record.kind, record.payload.len());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{CachedRecord, PendingRecords},
+        crate::metrics::GprcMessageKind,
+        rdkafka::message::OwnedHeaders,
+    };
+
+    fn record(key: &str) -> CachedRecord {
+        CachedRecord {
+            topic: "topic".to_owned(),
+            key: key.to_owned(),
+            payload: vec![],
+            headers: OwnedHeaders::new(),
+            partition: None,
+            kind: GprcMessageKind::Account,
+        }
+    }
+
+    #[test]
+    fn last_write_wins() {
+        let mut pending = PendingRecords::default();
+        assert!(!pending.insert(vec![1], record("1_a")));
+        assert!(!pending.insert(vec![2], record("1_b")));
+        assert!(pending.insert(vec![1], record("2_c")));
+        assert_eq!(pending.len(), 2);
+
+        let mut keys = pending
+            .take()
+            .into_iter()
+            .map(|record| record.key)
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, ["1_b", "2_c"]);
+        assert_eq!(pending.len(), 0);
+    }
+}
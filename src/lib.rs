@@ -1,13 +1,15 @@
 pub mod config;
+pub mod frame;
 #[cfg(feature = "kafka")]
 pub mod kafka;
 #[cfg(feature = "metrics")]
 pub mod metrics;
 
-pub mod version;
 pub mod generated;
+pub mod version;
 
 use {
+    crate::config::{LogConfig, LogFormat},
     futures::future::{BoxFuture, FutureExt},
     std::io::{self, IsTerminal},
     tokio::signal::unix::{signal, SignalKind},
@@ -18,27 +20,52 @@ use {
     },
 };
 
-pub fn setup_tracing() -> anyhow::Result<()> {
+pub fn setup_tracing(config: LogConfig) -> anyhow::Result<()> {
     let is_atty = io::stdout().is_terminal() && io::stderr().is_terminal();
-    let io_layer = tracing_subscriber::fmt::layer().with_ansi(is_atty);
+    let (text_layer, json_layer) = match config.format {
+        LogFormat::Text => (
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(is_atty)
+                    .with_file(config.include_location)
+                    .with_line_number(config.include_location),
+            ),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_file(config.include_location)
+                    .with_line_number(config.include_location),
+            ),
+        ),
+    };
     let level_layer = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .from_env_lossy();
     tracing_subscriber::registry()
-        .with(io_layer)
+        .with(text_layer)
+        .with(json_layer)
         .with(level_layer)
         .try_init()?;
     Ok(())
 }
 
 pub fn create_shutdown() -> anyhow::Result<BoxFuture<'static, ()>> {
+    Ok(create_shutdown_signal()?.map(|_signal| ()).boxed())
+}
+
+/// Resolves to the name of received signal: `SIGINT` or `SIGTERM`
+pub fn create_shutdown_signal() -> anyhow::Result<BoxFuture<'static, &'static str>> {
     let mut sigint = signal(SignalKind::interrupt())?;
     let mut sigterm = signal(SignalKind::terminate())?;
     Ok(async move {
         tokio::select! {
-            _ = sigint.recv() => {},
-            _ = sigterm.recv() => {}
-        };
+            _ = sigint.recv() => "SIGINT",
+            _ = sigterm.recv() => "SIGTERM",
+        }
     }
     .boxed())
 }
@@ -1,5 +1,18 @@
 #[cfg(feature = "kafka")]
-use crate::kafka::metrics::{KAFKA_DEDUP_TOTAL, KAFKA_RECV_TOTAL, KAFKA_SENT_TOTAL, KAFKA_STATS};
+use crate::kafka::metrics::{
+    ACTIVE_SUBSCRIBERS, CACHE_COLLAPSED_MESSAGES_TOTAL, CLIENT_RATE_LIMITED_TOTAL,
+    CONFIRMATION_OVERFLOW_TOTAL, CONFIRMATION_TIMEOUT_TOTAL, CONSUMER_LAG_GAUGE,
+    DECODE_ERRORS_TOTAL, DEDUP_BACKEND_LATENCY_SECONDS, DEDUP_EPOCH_RESETS_TOTAL,
+    DEDUP_LAG_SKIP_TOTAL, FAILED_TRANSACTIONS_SKIPPED_TOTAL, GRPC_BYTES_RECEIVED_TOTAL,
+    GRPC_CONNECTIONS_ACTIVE, GRPC_FAILOVER_ACTIVE, IN_FLIGHT_BY_KIND, IN_FLIGHT_TASKS,
+    KAFKA_BYTES_PRODUCED_TOTAL, KAFKA_DEAD_LETTER_TOTAL, KAFKA_DEDUP_TOTAL,
+    KAFKA_DUAL_WRITE_SENT_TOTAL, KAFKA_HEARTBEAT_SENT_TOTAL, KAFKA_REBALANCE_TOTAL,
+    KAFKA_RECV_TOTAL, KAFKA_SAMPLED_OUT_TOTAL, KAFKA_SENT_TOTAL, KAFKA_STATS,
+    LEADER_ELECTION_LEADER, MAX_SLOT_GAP, MESSAGE_LATENCY_SECONDS, NEAR_DUPLICATE_DEDUP_TOTAL,
+    PARTITION_CURRENT_OFFSET, PARTITION_HIGH_WATERMARK, PER_SLOT_LIMIT_EXCEEDED_TOTAL,
+    PIPELINE_RESTARTS_TOTAL, RECONNECT_REASON_TOTAL, RECONNECT_TTFM_SECONDS,
+    SERIALIZE_ERRORS_TOTAL, SKIPPED_TOTAL, SLOT_COMMITMENT_WATERMARK, SLOT_GAP_TOTAL,
+};
 use {
     crate::version::VERSION as VERSION_INFO,
     http_body_util::{combinators::BoxBody, BodyExt, Empty as BodyEmpty, Full as BodyFull},
@@ -12,28 +25,76 @@ use {
         rt::tokio::{TokioExecutor, TokioIo},
         server::conn::auto::Builder as ServerBuilder,
     },
-    prometheus::{IntCounterVec, Opts, Registry, TextEncoder},
-    std::{convert::Infallible, net::SocketAddr, sync::Once},
-    tokio::net::TcpListener,
-    tracing::{error, info},
+    prometheus::{
+        proto::{Metric, MetricFamily, MetricType},
+        IntCounterVec, Opts, Registry, TextEncoder,
+    },
+    std::{
+        collections::{btree_map::Entry, BTreeMap, HashMap},
+        convert::Infallible,
+        env,
+        net::SocketAddr,
+        path::PathBuf,
+        sync::{Mutex, Once, OnceLock},
+        time::Duration,
+    },
+    tokio::{
+        fs,
+        net::{TcpListener, UdpSocket},
+        time::interval,
+    },
+    tracing::{error, info, warn},
     yellowstone_grpc_proto::prelude::subscribe_update::UpdateOneof,
 };
 
-lazy_static::lazy_static! {
-    static ref REGISTRY: Registry = Registry::new();
+/// Created on server start, constant labels are known only after config load
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Registries of embedded pipeline instances by scope, served on
+/// `/metrics/{scope}`
+static SCOPED_REGISTRIES: Mutex<BTreeMap<String, Registry>> = Mutex::new(BTreeMap::new());
 
+lazy_static::lazy_static! {
     static ref VERSION: IntCounterVec = IntCounterVec::new(
         Opts::new("version", "Plugin version info"),
         &["buildts", "git", "package", "proto", "rustc", "solana", "version"]
     ).unwrap();
 }
 
-pub async fn run_server(address: SocketAddr) -> anyhow::Result<()> {
+/// Kubernetes pod metadata from Downward API env variables, missing
+/// variables are skipped
+pub fn pod_metadata_labels() -> HashMap<String, String> {
+    [
+        ("pod", "POD_NAME"),
+        ("namespace", "POD_NAMESPACE"),
+        ("node", "NODE_NAME"),
+    ]
+    .into_iter()
+    .filter_map(|(label, var)| match env::var(var) {
+        Ok(value) => Some((label.to_owned(), value)),
+        Err(_) => {
+            warn!("prometheus pod metadata: env variable {var} is not set");
+            None
+        }
+    })
+    .collect()
+}
+
+/// Create registry on first call, `const_labels` are added to all metrics
+/// Create registry with `const_labels` and register all metrics, only the
+/// first call has effect
+pub fn init_registry(const_labels: HashMap<String, String>) -> anyhow::Result<()> {
+    if REGISTRY.get().is_none() {
+        let const_labels = (!const_labels.is_empty()).then_some(const_labels);
+        let _ = REGISTRY.set(Registry::new_custom(None, const_labels)?);
+    }
+
     static REGISTER: Once = Once::new();
     REGISTER.call_once(|| {
+        let registry = REGISTRY.get().expect("defined");
         macro_rules! register {
             ($collector:ident) => {
-                REGISTRY
+                registry
                     .register(Box::new($collector.clone()))
                     .expect("collector can't be registered");
             };
@@ -44,8 +105,44 @@ pub async fn run_server(address: SocketAddr) -> anyhow::Result<()> {
         {
             register!(KAFKA_STATS);
             register!(KAFKA_DEDUP_TOTAL);
+            register!(NEAR_DUPLICATE_DEDUP_TOTAL);
+            register!(DEDUP_LAG_SKIP_TOTAL);
+            register!(DEDUP_EPOCH_RESETS_TOTAL);
             register!(KAFKA_RECV_TOTAL);
+            register!(DECODE_ERRORS_TOTAL);
+            register!(SERIALIZE_ERRORS_TOTAL);
+            register!(KAFKA_REBALANCE_TOTAL);
             register!(KAFKA_SENT_TOTAL);
+            register!(KAFKA_BYTES_PRODUCED_TOTAL);
+            register!(KAFKA_SAMPLED_OUT_TOTAL);
+            register!(KAFKA_DUAL_WRITE_SENT_TOTAL);
+            register!(KAFKA_HEARTBEAT_SENT_TOTAL);
+            register!(SKIPPED_TOTAL);
+            register!(FAILED_TRANSACTIONS_SKIPPED_TOTAL);
+            register!(RECONNECT_REASON_TOTAL);
+            register!(PIPELINE_RESTARTS_TOTAL);
+            register!(PER_SLOT_LIMIT_EXCEEDED_TOTAL);
+            register!(CACHE_COLLAPSED_MESSAGES_TOTAL);
+            register!(GRPC_FAILOVER_ACTIVE);
+            register!(GRPC_BYTES_RECEIVED_TOTAL);
+            register!(GRPC_CONNECTIONS_ACTIVE);
+            register!(CLIENT_RATE_LIMITED_TOTAL);
+            register!(LEADER_ELECTION_LEADER);
+            register!(MAX_SLOT_GAP);
+            register!(IN_FLIGHT_TASKS);
+            register!(ACTIVE_SUBSCRIBERS);
+            register!(IN_FLIGHT_BY_KIND);
+            register!(CONFIRMATION_TIMEOUT_TOTAL);
+            register!(CONFIRMATION_OVERFLOW_TOTAL);
+            register!(KAFKA_DEAD_LETTER_TOTAL);
+            register!(SLOT_COMMITMENT_WATERMARK);
+            register!(PARTITION_HIGH_WATERMARK);
+            register!(PARTITION_CURRENT_OFFSET);
+            register!(CONSUMER_LAG_GAUGE);
+            register!(SLOT_GAP_TOTAL);
+            register!(MESSAGE_LATENCY_SECONDS);
+            register!(DEDUP_BACKEND_LATENCY_SECONDS);
+            register!(RECONNECT_TTFM_SECONDS);
         }
 
         VERSION
@@ -61,6 +158,54 @@ pub async fn run_server(address: SocketAddr) -> anyhow::Result<()> {
             .inc();
     });
 
+    Ok(())
+}
+
+/// Create registry for a pipeline instance, collectors registered in it are
+/// served on `/metrics/{scope}` and not mixed with the default registry
+pub fn scoped_registry(
+    scope: &str,
+    const_labels: HashMap<String, String>,
+) -> anyhow::Result<Registry> {
+    anyhow::ensure!(
+        !scope.is_empty()
+            && scope
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-'),
+        "metrics scope should be non-empty and contain only `[A-Za-z0-9_-]`: {scope}"
+    );
+    let mut registries = SCOPED_REGISTRIES.lock().expect("alive mutex");
+    match registries.entry(scope.to_owned()) {
+        Entry::Occupied(_) => anyhow::bail!("metrics scope {scope} already exists"),
+        Entry::Vacant(entry) => {
+            let const_labels = (!const_labels.is_empty()).then_some(const_labels);
+            let registry = Registry::new_custom(None, const_labels)?;
+            entry.insert(registry.clone());
+            Ok(registry)
+        }
+    }
+}
+
+/// Stop serving registry of the pipeline instance
+pub fn remove_scoped_registry(scope: &str) -> Option<Registry> {
+    SCOPED_REGISTRIES.lock().expect("alive mutex").remove(scope)
+}
+
+fn scoped_gather(scope: &str) -> Option<Vec<MetricFamily>> {
+    SCOPED_REGISTRIES
+        .lock()
+        .expect("alive mutex")
+        .get(scope)
+        .map(Registry::gather)
+}
+
+/// Start metrics server, `const_labels` are added to all metrics
+pub async fn run_server(
+    address: SocketAddr,
+    const_labels: HashMap<String, String>,
+) -> anyhow::Result<()> {
+    init_registry(const_labels)?;
+
     let listener = TcpListener::bind(&address).await?;
     info!("prometheus server started: {address:?}");
     tokio::spawn(async move {
@@ -77,9 +222,16 @@ pub async fn run_server(address: SocketAddr) -> anyhow::Result<()> {
                     .serve_connection(
                         TokioIo::new(stream),
                         service_fn(move |req: Request<BodyIncoming>| async move {
-                            match req.uri().path() {
-                                "/metrics" => metrics_handler(),
-                                _ => not_found_handler(),
+                            let path = req.uri().path();
+                            match path.strip_prefix("/metrics") {
+                                Some("") => metrics_handler(default_gather()),
+                                Some(scope) => {
+                                    match scope.strip_prefix('/').and_then(scoped_gather) {
+                                        Some(families) => metrics_handler(families),
+                                        None => not_found_handler(),
+                                    }
+                                }
+                                None => not_found_handler(),
                             }
                         }),
                     )
@@ -94,13 +246,174 @@ pub async fn run_server(address: SocketAddr) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn metrics_handler() -> http::Result<Response<BoxBody<Bytes, Infallible>>> {
-    let metrics = TextEncoder::new()
-        .encode_to_string(&REGISTRY.gather())
+/// Periodically write metrics to the file for node_exporter textfile
+/// collector, the file is replaced atomically by rename
+pub fn run_textfile(
+    path: PathBuf,
+    period: Duration,
+    const_labels: HashMap<String, String>,
+) -> anyhow::Result<()> {
+    init_registry(const_labels)?;
+
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    info!("prometheus textfile started: {path:?}");
+    tokio::spawn(async move {
+        let mut interval = interval(period);
+        loop {
+            interval.tick().await;
+            let result = async {
+                fs::write(&tmp_path, encode_metrics(&default_gather())).await?;
+                fs::rename(&tmp_path, &path).await
+            };
+            if let Err(error) = result.await {
+                error!("failed to write metrics to {path:?}: {error}");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Max size of one UDP datagram with StatsD lines
+const STATSD_MAX_PACKET_SIZE: usize = 1_432;
+
+/// Periodically send metrics to StatsD `address` in DogStatsD format, labels
+/// are sent as tags. Counters are sent as deltas since the previous send.
+pub async fn run_statsd(
+    address: &str,
+    prefix: Option<String>,
+    period: Duration,
+    const_labels: HashMap<String, String>,
+) -> anyhow::Result<()> {
+    init_registry(const_labels)?;
+
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(address).await?;
+    info!("statsd sink started: {address}");
+    tokio::spawn(async move {
+        let mut counters = HashMap::new();
+        let mut interval = interval(period);
+        loop {
+            interval.tick().await;
+            let lines = encode_statsd(&default_gather(), prefix.as_deref(), &mut counters);
+            for packet in statsd_packets(&lines) {
+                if let Err(error) = socket.send(packet.as_bytes()).await {
+                    error!("failed to send metrics to statsd: {error}");
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// DogStatsD lines for metric families, `counters` keeps previous values of
+/// counters to send deltas
+fn encode_statsd(
+    families: &[MetricFamily],
+    prefix: Option<&str>,
+    counters: &mut HashMap<String, f64>,
+) -> Vec<String> {
+    let mut lines = vec![];
+    for family in families {
+        let name = match prefix {
+            Some(prefix) => format!("{prefix}.{}", family.get_name()),
+            None => family.get_name().to_owned(),
+        };
+        for metric in family.get_metric() {
+            let tags = statsd_tags(metric);
+            match family.get_field_type() {
+                MetricType::COUNTER => {
+                    let value = metric.get_counter().get_value();
+                    let previous = counters.insert(format!("{name}{tags}"), value);
+                    // value below previous means that counter was recreated
+                    let delta = value
+                        - previous
+                            .filter(|previous| *previous <= value)
+                            .unwrap_or(0.0);
+                    if delta > 0.0 {
+                        lines.push(format!("{name}:{delta}|c{tags}"));
+                    }
+                }
+                MetricType::GAUGE => {
+                    let value = metric.get_gauge().get_value();
+                    lines.push(format!("{name}:{value}|g{tags}"));
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    lines.push(format!(
+                        "{name}_count:{}|g{tags}",
+                        histogram.get_sample_count()
+                    ));
+                    lines.push(format!("{name}_sum:{}|g{tags}", histogram.get_sample_sum()));
+                }
+                MetricType::SUMMARY | MetricType::UNTYPED => {}
+            }
+        }
+    }
+    lines
+}
+
+fn statsd_tags(metric: &Metric) -> String {
+    let tags = metric
+        .get_label()
+        .iter()
+        .map(|label| format!("{}:{}", label.get_name(), label.get_value()))
+        .collect::<Vec<_>>();
+    if tags.is_empty() {
+        String::new()
+    } else {
+        format!("|#{}", tags.join(","))
+    }
+}
+
+/// Join lines to datagrams up to [`STATSD_MAX_PACKET_SIZE`]
+fn statsd_packets(lines: &[String]) -> Vec<String> {
+    let mut packets = vec![];
+    let mut packet = String::new();
+    for line in lines {
+        if !packet.is_empty() && packet.len() + 1 + line.len() > STATSD_MAX_PACKET_SIZE {
+            packets.push(std::mem::take(&mut packet));
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    packets
+}
+
+/// Metrics in text format, `scope` selects scoped registry
+pub fn encode_text(scope: Option<&str>) -> Option<String> {
+    match scope {
+        None => Some(encode_metrics(&default_gather())),
+        Some(scope) => scoped_gather(scope).map(|families| encode_metrics(&families)),
+    }
+}
+
+fn default_gather() -> Vec<MetricFamily> {
+    REGISTRY.get().map(Registry::gather).unwrap_or_default()
+}
+
+fn encode_metrics(families: &[MetricFamily]) -> String {
+    TextEncoder::new()
+        .encode_to_string(families)
         .unwrap_or_else(|error| {
             error!("could not encode custom metrics: {}", error);
             String::new()
-        });
+        })
+}
+
+fn metrics_handler(
+    families: Vec<MetricFamily>,
+) -> http::Result<Response<BoxBody<Bytes, Infallible>>> {
+    let metrics = encode_metrics(&families);
     Response::builder()
         .status(StatusCode::OK)
         .body(BodyFull::new(Bytes::from(metrics)).boxed())
@@ -143,6 +456,23 @@ impl From<&UpdateOneof> for GprcMessageKind {
 }
 
 impl GprcMessageKind {
+    /// Kinds which can be written to Kafka
+    pub const PRODUCED: [Self; 7] = [
+        Self::Account,
+        Self::Slot,
+        Self::Transaction,
+        Self::TransactionStatus,
+        Self::Block,
+        Self::BlockMeta,
+        Self::Entry,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::PRODUCED
+            .into_iter()
+            .find(|kind| kind.as_str() == name)
+    }
+
     pub const fn as_str(self) -> &'static str {
         match self {
             GprcMessageKind::Account => "account",
@@ -158,3 +488,66 @@ impl GprcMessageKind {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{
+            encode_metrics, encode_statsd, remove_scoped_registry, scoped_gather, scoped_registry,
+            statsd_packets,
+        },
+        prometheus::{IntCounter, IntGaugeVec, Opts, Registry},
+        std::collections::HashMap,
+    };
+
+    #[test]
+    fn scoped_registries() {
+        let labels = HashMap::from([("pipeline".to_owned(), "a".to_owned())]);
+        let registry = scoped_registry("pipeline-a", labels).unwrap();
+        let counter = IntCounter::new("scoped_total", "Scoped counter").unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        counter.inc();
+
+        assert!(scoped_registry("pipeline-a", HashMap::new()).is_err());
+        assert!(scoped_registry("pipeline/a", HashMap::new()).is_err());
+        assert!(scoped_gather("pipeline-b").is_none());
+
+        let text = encode_metrics(&scoped_gather("pipeline-a").unwrap());
+        assert!(text.contains("scoped_total{pipeline=\"a\"} 1"));
+
+        assert!(remove_scoped_registry("pipeline-a").is_some());
+        assert!(scoped_gather("pipeline-a").is_none());
+    }
+
+    #[test]
+    fn statsd_encoding() {
+        let registry = Registry::new();
+        let counter = IntCounter::new("sent_total", "Sent").unwrap();
+        let gauge = IntGaugeVec::new(Opts::new("active", "Active"), &["endpoint"]).unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        counter.inc_by(5);
+        gauge.with_label_values(&["a"]).set(2);
+
+        let mut counters = HashMap::new();
+        let lines = encode_statsd(&registry.gather(), Some("kafka"), &mut counters);
+        assert_eq!(
+            lines,
+            ["kafka.active:2|g|#endpoint:a", "kafka.sent_total:5|c"]
+        );
+
+        counter.inc_by(3);
+        let lines = encode_statsd(&registry.gather(), None, &mut HashMap::new());
+        assert_eq!(lines, ["active:2|g|#endpoint:a", "sent_total:8|c"]);
+        let lines = encode_statsd(&registry.gather(), Some("kafka"), &mut counters);
+        assert_eq!(
+            lines,
+            ["kafka.active:2|g|#endpoint:a", "kafka.sent_total:3|c"]
+        );
+
+        let lines = vec!["a".repeat(1_000), "b".repeat(400), "c".repeat(100)];
+        let packets = statsd_packets(&lines);
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].len(), 1_401);
+    }
+}
@@ -0,0 +1,178 @@
+use {
+    rdkafka::{
+        admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
+        client::DefaultClientContext,
+        config::ClientConfig,
+        consumer::{Consumer, StreamConsumer},
+        message::Message,
+    },
+    sha2::{Digest, Sha256},
+    std::{
+        collections::HashMap,
+        net::{SocketAddr, TcpListener},
+        path::Path,
+        process::{Child, Command},
+        time::Duration,
+    },
+    testcontainers_modules::{
+        kafka::{Kafka, KAFKA_PORT},
+        testcontainers::runners::AsyncRunner,
+    },
+    tokio::time::{sleep, timeout},
+    yellowstone_grpc_kafka::kafka::{config::ConfigGrpcListen, grpc::GrpcService},
+    yellowstone_grpc_proto::{
+        prelude::{
+            subscribe_update::UpdateOneof, SubscribeUpdate, SubscribeUpdateTransaction,
+            SubscribeUpdateTransactionInfo,
+        },
+        prost::Message as _,
+    },
+};
+
+const TOPIC: &str = "grpc2kafka-integration";
+const MESSAGES: u64 = 100;
+
+/// Kills `grpc-kafka` process even if test failed.
+struct ChildGuard(Child);
+
+impl Drop for ChildGuard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn free_local_addr() -> anyhow::Result<SocketAddr> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?)
+}
+
+fn create_update(slot: u64) -> SubscribeUpdate {
+    SubscribeUpdate {
+        filters: vec!["client".to_owned()],
+        update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+            transaction: Some(SubscribeUpdateTransactionInfo {
+                signature: vec![slot as u8; 64],
+                is_vote: false,
+                transaction: None,
+                meta: None,
+                index: slot,
+            }),
+            slot,
+        })),
+        created_at: None,
+    }
+}
+
+fn write_config(path: &Path, bootstrap: &str, endpoint: SocketAddr) -> anyhow::Result<()> {
+    let config = serde_json::json!({
+        "kafka": {
+            "bootstrap.servers": bootstrap,
+        },
+        "grpc2kafka": {
+            "endpoint": format!("http://{endpoint}"),
+            "request": {
+                "transactions": {
+                    "client": {}
+                }
+            },
+            "kafka_topic": TOPIC,
+            "format": "protobuf",
+        }
+    });
+    std::fs::write(path, serde_json::to_vec(&config)?)?;
+    Ok(())
+}
+
+type Messages = HashMap<String, Vec<u8>>;
+
+async fn run_pipeline(bootstrap: &str, config_path: &Path) -> anyhow::Result<(Messages, Messages)> {
+    // mock Geyser server, every update sent to the channel is streamed to subscribers
+    let endpoint = free_local_addr()?;
+    let (grpc_tx, grpc_shutdown) = GrpcService::run(
+        &[ConfigGrpcListen::Tcp(endpoint)],
+        1_024,
+        Duration::from_secs(10),
+    )?;
+
+    write_config(config_path, bootstrap, endpoint)?;
+    let _child = Command::new(env!("CARGO_BIN_EXE_grpc-kafka"))
+        .arg("--config")
+        .arg(config_path)
+        .arg("grpc2kafka")
+        .spawn()
+        .map(ChildGuard)?;
+
+    timeout(Duration::from_secs(60), async {
+        while grpc_tx.receiver_count() == 0 {
+            sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("grpc2kafka did not subscribe in time"))?;
+
+    let mut expected = HashMap::new();
+    for slot in 0..MESSAGES {
+        let update = create_update(slot);
+        let payload = update.encode_to_vec();
+        let key = format!("{slot}_{}", const_hex::encode(Sha256::digest(&payload)));
+        expected.insert(key, payload);
+        anyhow::ensure!(grpc_tx.broadcast(update), "grpc2kafka is disconnected");
+    }
+
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("bootstrap.servers", bootstrap)
+        .set("group.id", "grpc2kafka-integration")
+        .set("auto.offset.reset", "earliest")
+        .create()?;
+    consumer.subscribe(&[TOPIC])?;
+
+    let mut received = HashMap::new();
+    timeout(Duration::from_secs(60), async {
+        while (received.len() as u64) < MESSAGES {
+            let message = consumer.recv().await?;
+            let key = message
+                .key()
+                .map(|key| String::from_utf8_lossy(key).into_owned())
+                .unwrap_or_default();
+            received.insert(key, message.payload().unwrap_or_default().to_vec());
+        }
+        Ok::<(), anyhow::Error>(())
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("received only {} messages", received.len()))??;
+
+    grpc_shutdown.await??;
+    Ok((received, expected))
+}
+
+#[tokio::test]
+async fn grpc2kafka_transactions_to_kafka() -> anyhow::Result<()> {
+    let kafka = Kafka::default().start().await?;
+    let bootstrap = format!("127.0.0.1:{}", kafka.get_host_port_ipv4(KAFKA_PORT).await?);
+
+    let admin: AdminClient<DefaultClientContext> = ClientConfig::new()
+        .set("bootstrap.servers", &bootstrap)
+        .create()?;
+    let options = AdminOptions::new().operation_timeout(Some(Duration::from_secs(10)));
+    admin
+        .create_topics(
+            &[NewTopic::new(TOPIC, 1, TopicReplication::Fixed(1))],
+            &options,
+        )
+        .await?
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|(topic, error)| anyhow::anyhow!("failed to create {topic}: {error}"))?;
+
+    let config_path = std::env::temp_dir().join(format!("grpc2kafka-{}.json", std::process::id()));
+    let result = run_pipeline(&bootstrap, &config_path).await;
+
+    // cleanup
+    let _ = std::fs::remove_file(&config_path);
+    admin.delete_topics(&[TOPIC], &options).await?;
+
+    let (received, expected) = result?;
+    assert_eq!(received.len() as u64, MESSAGES);
+    assert_eq!(received, expected);
+    Ok(())
+}
@@ -0,0 +1,7 @@
+//! End-to-end tests, require Docker. Run with:
+//!
+//! ```bash
+//! cargo test --test integration --features integration-tests
+//! ```
+
+mod grpc2kafka;